@@ -0,0 +1,224 @@
+//!
+//! Ready-made actix-web handlers for the shurjopay checkout flow.
+//!
+//! Available with the `actix` feature.
+//!
+//! `ShurjopayData::scope` mounts the handlers in a `Scope`:
+//! - `POST /checkout` JSON `CheckoutRequest` in, redirect to the checkout url out
+//! - `GET /return` and `GET /cancel` verify the `order_id` shurjopay appends
+//! - `POST /notify` verifies the `order_id` of an instant payment notification
+//!
+//! The handlers are public so they can be mounted on other paths as well,
+//! they expect `web::Data<ShurjopayData>` in the app data.
+//!
+//! The plugin is blocking, so every call to shurjopay runs on
+//! `actix_web::web::block`. For the same reason the plugin must be
+//! created outside of async code, e.g. before the server starts or inside
+//! `web::block`.
+//!
+//! Requests are handled concurrently, each on its own copy of the shared
+//! plugin, see `web::create_checkout` and `web::verify_order`.
+//!
+
+use std::convert::Infallible;
+use std::future::{ready, Ready};
+use std::net::SocketAddr;
+
+use actix_web::dev::Payload;
+use actix_web::http::{header, StatusCode};
+use actix_web::{web, FromRequest, HttpRequest, HttpResponse, Scope};
+use serde_json::json;
+
+use crate::error::SpError;
+use crate::order_id::validate_order_id;
+use crate::shurjopay::{ShurjopayPlugin, SpVerifyResponse};
+use crate::web::{
+    self as sp_web, CheckoutRequest, OrderIdParams, PaymentCallbacks, PaymentOutcome, PluginHandle, SharedPlugin,
+};
+
+/// App data of the shurjopay handlers: the shared plugin, callbacks and redirect urls
+/// Without redirect urls the return and cancel handlers answer with a JSON `PaymentOutcome`
+#[derive(Clone)]
+pub struct ShurjopayData {
+    plugin: PluginHandle,
+    callbacks: PaymentCallbacks,
+    success_redirect: Option<String>,
+    failure_redirect: Option<String>,
+    trust_forwarded_headers: bool,
+}
+
+impl ShurjopayData {
+    /// Creates the app data around a configured plugin
+    pub fn new(sp_instance: ShurjopayPlugin) -> Self {
+        Self::from_shared(sp_web::share(sp_instance))
+    }
+
+    /// Creates the app data around a plugin that is shared with other handlers
+    pub fn from_shared(plugin: SharedPlugin) -> Self {
+        ShurjopayData {
+            plugin: PluginHandle::new(plugin),
+            callbacks: PaymentCallbacks::default(),
+            success_redirect: None,
+            failure_redirect: None,
+            trust_forwarded_headers: false,
+        }
+    }
+
+    /// Sets the callback for successful payments, see `PaymentCallbacks::on_success`
+    pub fn on_success<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&SpVerifyResponse) + Send + Sync + 'static,
+    {
+        self.callbacks = self.callbacks.on_success(callback);
+        self
+    }
+
+    /// Sets the callback for payments that are not successful or could not be verified
+    pub fn on_failure<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&PaymentOutcome) + Send + Sync + 'static,
+    {
+        self.callbacks = self.callbacks.on_failure(callback);
+        self
+    }
+
+    /// Redirects the shopper to `url?order_id=...` after a successful payment
+    pub fn success_redirect(mut self, url: &str) -> Self {
+        self.success_redirect = Some(url.to_string());
+        self
+    }
+
+    /// Redirects the shopper to `url?order_id=...` after a payment that is not successful
+    pub fn failure_redirect(mut self, url: &str) -> Self {
+        self.failure_redirect = Some(url.to_string());
+        self
+    }
+
+    /// Takes the shopper's IP from the `Forwarded` and `X-Forwarded-For` headers
+    /// Only for applications behind a proxy that sets them, anyone else can fake them
+    pub fn trust_forwarded_headers(mut self) -> Self {
+        self.trust_forwarded_headers = true;
+        self
+    }
+
+    /// The shared plugin used by the handlers
+    pub fn plugin(&self) -> SharedPlugin {
+        self.plugin.shared()
+    }
+
+    /// Builds a `Scope` at `path` with the checkout, return, cancel and notification handlers
+    pub fn scope(self, path: &str) -> Scope {
+        web::scope(path)
+            .app_data(web::Data::new(self))
+            .route("/checkout", web::post().to(checkout))
+            .route("/return", web::get().to(return_or_cancel))
+            .route("/cancel", web::get().to(return_or_cancel))
+            .route("/notify", web::post().to(notify))
+    }
+}
+
+/// IP address of the shopper
+/// The IP of the connection, or of the `Forwarded` and `X-Forwarded-For` headers
+/// if `ShurjopayData::trust_forwarded_headers` is set
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientIp(pub Option<String>);
+
+impl FromRequest for ClientIp {
+    type Error = Infallible;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let trusted = req
+            .app_data::<web::Data<ShurjopayData>>()
+            .map_or(false, |data| data.trust_forwarded_headers);
+        let client_ip = if trusted {
+            req.connection_info().realip_remote_addr().map(|addr| match addr.parse::<SocketAddr>() {
+                Ok(addr) => addr.ip().to_string(),
+                Err(_) => addr.to_string(),
+            })
+        } else {
+            req.peer_addr().map(|addr| addr.ip().to_string())
+        };
+        ready(Ok(ClientIp(client_ip)))
+    }
+}
+
+/// Creates a payment and redirects to the shurjopay checkout page
+/// The `client_ip` of the request body wins over the IP of the connection
+pub async fn checkout(
+    data: web::Data<ShurjopayData>,
+    client_ip: ClientIp,
+    request: web::Json<CheckoutRequest>,
+) -> HttpResponse {
+    let mut request = request.into_inner();
+    if request.client_ip.is_none() {
+        request.client_ip = client_ip.0;
+    }
+    let plugin = data.plugin.shared();
+    match web::block(move || sp_web::create_checkout(&plugin, request)).await {
+        Ok(Ok(checkout_url)) => redirect(&checkout_url),
+        Ok(Err(err)) => checkout_error(&err),
+        Err(err) => error_response(StatusCode::INTERNAL_SERVER_ERROR, &err.to_string()),
+    }
+}
+
+/// Verifies the `order_id` shurjopay appends to the return and cancel urls
+pub async fn return_or_cancel(data: web::Data<ShurjopayData>, params: web::Query<OrderIdParams>) -> HttpResponse {
+    let order_id = match params.into_inner().order_id {
+        Some(order_id) => order_id,
+        None => return error_response(StatusCode::BAD_REQUEST, "order_id is missing"),
+    };
+    let outcome = match verify(&data, order_id).await {
+        Ok(outcome) => outcome,
+        Err(response) => return response,
+    };
+    let redirect_to = if outcome.paid {
+        &data.success_redirect
+    } else {
+        &data.failure_redirect
+    };
+    match redirect_to {
+        Some(url) => redirect(&sp_web::redirect_url(url, &outcome.order_id)),
+        None => HttpResponse::Ok().json(outcome),
+    }
+}
+
+/// Verifies the `order_id` of an instant payment notification sent in the query, as JSON or as a form
+pub async fn notify(
+    data: web::Data<ShurjopayData>,
+    params: web::Query<OrderIdParams>,
+    body: web::Bytes,
+) -> HttpResponse {
+    let order_id = match params.into_inner().order_id.or_else(|| sp_web::order_id_from_body(&body)) {
+        Some(order_id) => order_id,
+        None => return error_response(StatusCode::BAD_REQUEST, "order_id is missing"),
+    };
+    match verify(&data, order_id).await {
+        Ok(outcome) => HttpResponse::Ok().json(outcome),
+        Err(response) => response,
+    }
+}
+
+async fn verify(data: &ShurjopayData, order_id: String) -> Result<PaymentOutcome, HttpResponse> {
+    if let Err(err) = validate_order_id(&order_id) {
+        return Err(error_response(StatusCode::BAD_REQUEST, &err.to_string()));
+    }
+    let plugin = data.plugin.shared();
+    let callbacks = data.callbacks.clone();
+    web::block(move || sp_web::verify_order(&plugin, &order_id, &callbacks))
+        .await
+        .map_err(|err| error_response(StatusCode::INTERNAL_SERVER_ERROR, &err.to_string()))
+}
+
+fn redirect(url: &str) -> HttpResponse {
+    HttpResponse::SeeOther().insert_header((header::LOCATION, url)).finish()
+}
+
+fn checkout_error(err: &SpError) -> HttpResponse {
+    let status = StatusCode::from_u16(sp_web::checkout_error_status(err)).unwrap_or(StatusCode::BAD_GATEWAY);
+    error_response(status, &err.to_string())
+}
+
+fn error_response(status: StatusCode, message: &str) -> HttpResponse {
+    HttpResponse::build(status).json(json!({ "error": message }))
+}
@@ -0,0 +1,175 @@
+//!
+//! Ready-made axum routes for the shurjopay checkout flow.
+//!
+//! Available with the `axum` feature.
+//!
+//! Routes:
+//! - `POST /checkout` JSON `CheckoutRequest` in, redirect to the checkout url out
+//! - `GET /return` and `GET /cancel` verify the `order_id` shurjopay appends
+//! - `POST /ipn` verifies the `order_id` of an instant payment notification
+//!
+//! The plugin is blocking, so every call to shurjopay runs on
+//! `tokio::task::spawn_blocking`. For the same reason the plugin must be
+//! created outside of async code, e.g. before the runtime starts or inside
+//! `spawn_blocking`.
+//!
+//! Requests are handled concurrently, each on its own copy of the shared
+//! plugin, see `web::create_checkout` and `web::verify_order`.
+//!
+
+use axum::body::Bytes;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde_json::json;
+
+use crate::order_id::validate_order_id;
+use crate::shurjopay::{ShurjopayPlugin, SpVerifyResponse};
+use crate::web::{self, CheckoutRequest, OrderIdParams, PaymentCallbacks, PaymentOutcome, PluginHandle, SharedPlugin};
+
+/// State shared by the shurjopay routes
+#[derive(Clone)]
+pub struct ShurjopayState {
+    plugin: PluginHandle,
+    callbacks: PaymentCallbacks,
+    success_redirect: Option<String>,
+    failure_redirect: Option<String>,
+}
+
+/// Builder of the shurjopay `Router`
+/// Without redirect urls the return and cancel routes answer with a JSON `PaymentOutcome`
+#[derive(Clone)]
+pub struct ShurjopayRoutes {
+    state: ShurjopayState,
+}
+
+impl ShurjopayRoutes {
+    /// Creates the routes around a configured plugin
+    pub fn new(sp_instance: ShurjopayPlugin) -> Self {
+        Self::from_shared(web::share(sp_instance))
+    }
+
+    /// Creates the routes around a plugin that is shared with other handlers
+    pub fn from_shared(plugin: SharedPlugin) -> Self {
+        ShurjopayRoutes {
+            state: ShurjopayState {
+                plugin: PluginHandle::new(plugin),
+                callbacks: PaymentCallbacks::default(),
+                success_redirect: None,
+                failure_redirect: None,
+            },
+        }
+    }
+
+    /// Sets the callback for successful payments, see `PaymentCallbacks::on_success`
+    pub fn on_success<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&SpVerifyResponse) + Send + Sync + 'static,
+    {
+        self.state.callbacks = self.state.callbacks.on_success(callback);
+        self
+    }
+
+    /// Sets the callback for payments that are not successful or could not be verified
+    pub fn on_failure<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&PaymentOutcome) + Send + Sync + 'static,
+    {
+        self.state.callbacks = self.state.callbacks.on_failure(callback);
+        self
+    }
+
+    /// Redirects the shopper to `url?order_id=...` after a successful payment
+    pub fn success_redirect(mut self, url: &str) -> Self {
+        self.state.success_redirect = Some(url.to_string());
+        self
+    }
+
+    /// Redirects the shopper to `url?order_id=...` after a payment that is not successful
+    pub fn failure_redirect(mut self, url: &str) -> Self {
+        self.state.failure_redirect = Some(url.to_string());
+        self
+    }
+
+    /// The shared plugin used by the routes
+    pub fn plugin(&self) -> SharedPlugin {
+        self.state.plugin.shared()
+    }
+
+    /// Builds the `Router`, nest it to serve the routes under a path
+    pub fn router<S>(self) -> Router<S>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        Router::new()
+            .route("/checkout", post(checkout))
+            .route("/return", get(return_or_cancel))
+            .route("/cancel", get(return_or_cancel))
+            .route("/ipn", post(ipn))
+            .with_state(self.state)
+    }
+}
+
+async fn checkout(State(state): State<ShurjopayState>, Json(request): Json<CheckoutRequest>) -> Response {
+    let plugin = state.plugin.shared();
+    match tokio::task::spawn_blocking(move || web::create_checkout(&plugin, request)).await {
+        Ok(Ok(checkout_url)) => Redirect::to(&checkout_url).into_response(),
+        Ok(Err(err)) => {
+            let status = StatusCode::from_u16(web::checkout_error_status(&err)).unwrap_or(StatusCode::BAD_GATEWAY);
+            (status, Json(json!({ "error": err.to_string() }))).into_response()
+        }
+        Err(err) => internal_error(err),
+    }
+}
+
+async fn return_or_cancel(State(state): State<ShurjopayState>, Query(params): Query<OrderIdParams>) -> Response {
+    let order_id = match params.order_id {
+        Some(order_id) => order_id,
+        None => return missing_order_id(),
+    };
+    let outcome = match verify(&state, order_id).await {
+        Ok(outcome) => outcome,
+        Err(response) => return response,
+    };
+    let redirect = if outcome.paid {
+        &state.success_redirect
+    } else {
+        &state.failure_redirect
+    };
+    match redirect {
+        Some(url) => Redirect::to(&web::redirect_url(url, &outcome.order_id)).into_response(),
+        None => Json(outcome).into_response(),
+    }
+}
+
+async fn ipn(State(state): State<ShurjopayState>, Query(params): Query<OrderIdParams>, body: Bytes) -> Response {
+    let order_id = match params.order_id.or_else(|| web::order_id_from_body(&body)) {
+        Some(order_id) => order_id,
+        None => return missing_order_id(),
+    };
+    match verify(&state, order_id).await {
+        Ok(outcome) => Json(outcome).into_response(),
+        Err(response) => response,
+    }
+}
+
+async fn verify(state: &ShurjopayState, order_id: String) -> Result<PaymentOutcome, Response> {
+    if let Err(err) = validate_order_id(&order_id) {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({ "error": err.to_string() }))).into_response());
+    }
+    let plugin = state.plugin.shared();
+    let callbacks = state.callbacks.clone();
+    tokio::task::spawn_blocking(move || web::verify_order(&plugin, &order_id, &callbacks))
+        .await
+        .map_err(internal_error)
+}
+
+fn missing_order_id() -> Response {
+    (StatusCode::BAD_REQUEST, Json(json!({ "error": "order_id is missing" }))).into_response()
+}
+
+fn internal_error(err: tokio::task::JoinError) -> Response {
+    (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": err.to_string() }))).into_response()
+}
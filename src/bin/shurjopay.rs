@@ -0,0 +1,361 @@
+//!
+//! `shurjopay` command line tool for operators.
+//!
+//! Configuration is read from the `.env` file the same way
+//! `ShurjopayPlugin::set_config_from_env_file` does.
+//!
+//! Exit codes:
+//! - `0` the request succeeded and every payment is successful
+//! - `1` a payment is not successful
+//! - `2` invalid command line
+//! - `3` configuration is missing
+//! - `4` shurjopay could not be reached or answered unexpectedly
+//! - `5` shurjopay refused the username or password
+//! - `6` shurjopay does not know the order id
+//! - `7` a local file could not be read or written
+//!
+
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use clap::{Args, Parser, Subcommand};
+use serde_json::json;
+
+use shurjopay_plugin::error::SpError;
+use shurjopay_plugin::export::VerifyExport;
+use shurjopay_plugin::payment_store::PaymentState;
+use shurjopay_plugin::phone::PhoneNumber;
+#[cfg(feature = "qr")]
+use shurjopay_plugin::qr::{ErrorCorrection, QrOptions, MAX_MODULE_SIZE};
+use shurjopay_plugin::reconciliation::SP_CODE_UNKNOWN_ORDER;
+#[cfg(feature = "qr")]
+use shurjopay_plugin::shurjopay::SpCheckoutResponse;
+use shurjopay_plugin::shurjopay::{ShurjopayPlugin, SpVerifyResponse};
+
+const EXIT_OK: i32 = 0;
+const EXIT_NOT_PAID: i32 = 1;
+#[cfg(feature = "qr")]
+const EXIT_USAGE: i32 = 2;
+const EXIT_CONFIG: i32 = 3;
+const EXIT_GATEWAY: i32 = 4;
+const EXIT_AUTH: i32 = 5;
+const EXIT_UNKNOWN_ORDER: i32 = 6;
+const EXIT_IO: i32 = 7;
+
+#[derive(Parser)]
+#[command(name = "shurjopay", version, about = "Operate shurjoPay payments from the command line")]
+struct Cli {
+    /// Print machine readable JSON instead of text
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Fetch an auth token and show when it expires
+    Token,
+    /// Create a payment and print its checkout url
+    Checkout(Box<CheckoutArgs>),
+    /// Verify a payment by shurjopay order id
+    Verify { order_id: String },
+    /// Show payment details and status by shurjopay order id
+    Status { order_id: String },
+    /// Verify every order id in a file, one per line
+    VerifyBatch { file: PathBuf },
+}
+
+#[derive(Args)]
+struct CheckoutArgs {
+    #[arg(long)]
+    amount: String,
+    #[arg(long)]
+    order_id: String,
+    #[arg(long, default_value = "BDT")]
+    currency: String,
+    #[arg(long)]
+    customer_name: String,
+    #[arg(long)]
+    customer_address: String,
+    /// Bangladeshi mobile number, e.g. `01811177722` or `+880 1811-177722`
+    #[arg(long)]
+    customer_phone: PhoneNumber,
+    #[arg(long)]
+    customer_city: String,
+    #[arg(long)]
+    customer_post_code: String,
+    /// Overrides `DEFAULT_RETURN_URL`
+    #[arg(long)]
+    return_url: Option<String>,
+    /// Overrides `DEFAULT_CANCEL_URL`
+    #[arg(long)]
+    cancel_url: Option<String>,
+    #[arg(long)]
+    client_ip: Option<String>,
+    #[cfg(feature = "qr")]
+    #[command(flatten)]
+    qr: QrArgs,
+}
+
+#[cfg(feature = "qr")]
+#[derive(Args)]
+struct QrArgs {
+    /// Also print the checkout url as a QR code
+    #[arg(long, value_name = "FORMAT")]
+    qr: Option<QrFormat>,
+    /// File to write the QR code to, required for `png`
+    #[arg(long, requires = "qr", required_if_eq("qr", "png"))]
+    qr_output: Option<PathBuf>,
+    /// Error correction level: L, M, Q or H
+    #[arg(long, default_value = "M")]
+    qr_error_correction: ErrorCorrection,
+    /// Pixels per module of `svg` and `png` codes, 1 to 64
+    #[arg(long, default_value = "8", value_parser = clap::value_parser!(u32).range(1..=i64::from(MAX_MODULE_SIZE)))]
+    qr_module_size: u32,
+}
+
+#[cfg(feature = "qr")]
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum QrFormat {
+    Terminal,
+    Svg,
+    Png,
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let mut sp_instance = ShurjopayPlugin::new();
+    sp_instance.set_config_from_env_file();
+    if sp_instance.config.is_none() {
+        eprintln!("shurjopay: configuration is not set, create a .env file (see _env_sample)");
+        process::exit(EXIT_CONFIG);
+    }
+
+    let code = match cli.command {
+        Command::Token => token(&mut sp_instance, cli.json),
+        Command::Checkout(args) => {
+            let args = *args;
+            let mut checkout = sp_instance.make_payment_request_object(
+                args.amount,
+                args.order_id,
+                args.currency,
+                args.customer_name,
+                args.customer_address,
+                args.customer_phone.to_string(),
+                args.customer_city,
+                args.customer_post_code,
+            );
+            checkout.return_url = args.return_url.unwrap_or(checkout.return_url);
+            checkout.cancel_url = args.cancel_url.unwrap_or(checkout.cancel_url);
+            checkout.client_ip = args.client_ip.unwrap_or(checkout.client_ip);
+
+            match sp_instance.make_payment_checkout(checkout) {
+                Some(response) => {
+                    if cli.json {
+                        print_json(&response);
+                    } else {
+                        println!("checkout_url: {}", response.checkout_url);
+                        println!("sp_order_id:  {}", response.sp_order_id);
+                    }
+                    #[cfg(feature = "qr")]
+                    let code = checkout_qr(&response, &args.qr, cli.json);
+                    #[cfg(not(feature = "qr"))]
+                    let code = EXIT_OK;
+                    code
+                }
+                None => failure(&sp_instance),
+            }
+        }
+        Command::Verify { order_id } => {
+            let response = sp_instance.verify_payment(Some(order_id));
+            single_result(&sp_instance, response, cli.json)
+        }
+        Command::Status { order_id } => {
+            let response = sp_instance.check_payment(Some(order_id));
+            single_result(&sp_instance, response, cli.json)
+        }
+        Command::VerifyBatch { file } => verify_batch(&mut sp_instance, &file, cli.json),
+    };
+    process::exit(code);
+}
+
+/// Writes the QR code requested with `--qr`
+/// Text codes go to standard error with `--json` so standard output stays JSON
+#[cfg(feature = "qr")]
+fn checkout_qr(response: &SpCheckoutResponse, args: &QrArgs, json: bool) -> i32 {
+    let format = match args.qr {
+        Some(format) => format,
+        None => return EXIT_OK,
+    };
+    let options = QrOptions {
+        error_correction: args.qr_error_correction,
+        module_size: args.qr_module_size,
+        ..Default::default()
+    };
+    let code = match response.qr_code(options) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("shurjopay: {}", err);
+            return EXIT_USAGE;
+        }
+    };
+    let bytes = match format {
+        QrFormat::Terminal => code.to_terminal().into_bytes(),
+        QrFormat::Svg => code.to_svg().into_bytes(),
+        // clap requires `--qr-output` for png
+        QrFormat::Png => match code.to_png() {
+            Ok(png) => png,
+            Err(err) => {
+                eprintln!("shurjopay: {}", err);
+                return EXIT_USAGE;
+            }
+        },
+    };
+    match &args.qr_output {
+        Some(path) => {
+            if let Err(err) = fs::write(path, bytes) {
+                eprintln!("shurjopay: cannot write {}: {}", path.display(), err);
+                return EXIT_IO;
+            }
+        }
+        None if json => eprintln!("{}", String::from_utf8_lossy(&bytes)),
+        None => println!("{}", String::from_utf8_lossy(&bytes)),
+    }
+    EXIT_OK
+}
+
+fn token(sp_instance: &mut ShurjopayPlugin, json: bool) -> i32 {
+    sp_instance.get_auth_token();
+    let auth_token = match sp_instance.auth_token.clone() {
+        Some(auth_token) => auth_token,
+        None => return failure(sp_instance),
+    };
+    let expires_at = sp_instance.token_expire_time.map(|time| time.to_string());
+    if json {
+        print_json(&json!({
+            "token_type": auth_token.token_type,
+            "store_id": auth_token.store_id,
+            "token_create_time": auth_token.token_create_time,
+            "expires_in": auth_token.expires_in,
+            "expires_at": expires_at,
+        }));
+    } else {
+        println!("token_type:        {}", auth_token.token_type);
+        println!("store_id:          {}", auth_token.store_id);
+        println!("token_create_time: {}", auth_token.token_create_time);
+        println!("expires_in:        {}s", auth_token.expires_in);
+        println!("expires_at:        {}", expires_at.unwrap_or_else(|| "unknown".to_string()));
+    }
+    EXIT_OK
+}
+
+fn single_result(sp_instance: &ShurjopayPlugin, response: Option<SpVerifyResponse>, json: bool) -> i32 {
+    let response = match response {
+        Some(response) => response,
+        None => return failure(sp_instance),
+    };
+    if json {
+        print_json(&response);
+    } else {
+        print_verify_text(&response);
+    }
+    payment_code(&response)
+}
+
+fn verify_batch(sp_instance: &mut ShurjopayPlugin, file: &PathBuf, json: bool) -> i32 {
+    let content = match fs::read_to_string(file) {
+        Ok(content) => content,
+        Err(err) => {
+            eprintln!("shurjopay: cannot read {}: {}", file.display(), err);
+            return EXIT_IO;
+        }
+    };
+
+    let export = VerifyExport::default();
+    let mut code = EXIT_OK;
+    for order_id in content.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+        match sp_instance.verify_payment(Some(order_id.to_string())) {
+            Some(response) => {
+                if json {
+                    let stdout = std::io::stdout();
+                    if let Err(err) = export.write_ndjson(stdout.lock(), [&response]) {
+                        eprintln!("shurjopay: {}", err);
+                    }
+                } else {
+                    println!(
+                        "{}\t{}\t{}",
+                        order_id,
+                        response.sp_code.map(|sp_code| sp_code.to_string()).unwrap_or_default(),
+                        response.sp_message.clone().unwrap_or_default()
+                    );
+                }
+                if payment_code(&response) != EXIT_OK && code == EXIT_OK {
+                    code = EXIT_NOT_PAID;
+                }
+            }
+            None => {
+                let error = sp_instance.last_error.clone();
+                match &error {
+                    Some(err) => eprintln!("shurjopay: {}: {}", order_id, err),
+                    None => eprintln!("shurjopay: {}: verification failed", order_id),
+                }
+                code = error_code(error.as_ref());
+            }
+        }
+    }
+    code
+}
+
+fn print_verify_text(response: &SpVerifyResponse) {
+    let field = |value: &Option<String>| value.clone().unwrap_or_default();
+    println!("order_id:          {}", field(&response.order_id));
+    println!("customer_order_id: {}", field(&response.customer_order_id));
+    println!("sp_code:           {}", response.sp_code.map(|sp_code| sp_code.to_string()).unwrap_or_default());
+    println!("sp_message:        {}", field(&response.sp_message));
+    println!("method:            {}", field(&response.method));
+    println!("bank_trx_id:       {}", field(&response.bank_trx_id));
+    println!("invoice_no:        {}", field(&response.invoice_no));
+    println!("currency:          {}", field(&response.currency));
+    println!("payable_amount:    {}", response.payable_amount.map(|amount| amount.to_string()).unwrap_or_default());
+    println!("received_amount:   {}", field(&response.received_amount));
+    println!("date_time:         {}", field(&response.date_time));
+}
+
+fn print_json<T: serde::Serialize>(value: &T) {
+    match serde_json::to_string_pretty(value) {
+        Ok(text) => println!("{}", text),
+        Err(err) => eprintln!("shurjopay: {}", err),
+    }
+}
+
+/// Exit code of a verification result
+fn payment_code(response: &SpVerifyResponse) -> i32 {
+    if response.sp_code == Some(SP_CODE_UNKNOWN_ORDER) {
+        return EXIT_UNKNOWN_ORDER;
+    }
+    match PaymentState::from_sp_code(response.sp_code) {
+        PaymentState::Paid => EXIT_OK,
+        _ => EXIT_NOT_PAID,
+    }
+}
+
+/// Reports the last error of the plugin and returns its exit code
+fn failure(sp_instance: &ShurjopayPlugin) -> i32 {
+    match &sp_instance.last_error {
+        Some(err) => eprintln!("shurjopay: {}", err),
+        None => eprintln!("shurjopay: request failed"),
+    }
+    error_code(sp_instance.last_error.as_ref())
+}
+
+fn error_code(error: Option<&SpError>) -> i32 {
+    match error {
+        Some(SpError::Config(_)) => EXIT_CONFIG,
+        Some(SpError::Auth(_)) => EXIT_AUTH,
+        _ => EXIT_GATEWAY,
+    }
+}
@@ -0,0 +1,381 @@
+//!
+//! This module models itemized orders.
+//!
+//! A `Cart` holds line items with a SKU, quantity, unit price and VAT rate,
+//! cart discounts and a shipping charge, all in one currency. Its totals are
+//! computed exactly in `Money`:
+//! - discounts are shared across the lines in proportion to their net amount
+//! - VAT is computed per line on the discounted net amount, rounded half away from zero
+//! - shipping is added without VAT
+//!
+//! The total becomes the `amount` of the checkout, and the cart is kept in a
+//! `CartStore` under the merchant `order_id` for receipts and for
+//! reconciliation against the gateway's `payable_amount`.
+//!
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::discount::Discount;
+use crate::error::SpError;
+use crate::money::Money;
+use crate::payment_store::PaymentState;
+use crate::reconciliation::LocalRecord;
+use crate::shurjopay::{ShurjopayPlugin, SpCheckout, SpCheckoutResponse};
+
+/// VAT rate in basis points, `1500` is 15%
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone`, `Copy`, `PartialEq`, `Eq` and `Default` functions
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct VatRate {
+    basis_points: u32,
+}
+
+impl VatRate {
+    /// No VAT
+    pub const ZERO: VatRate = VatRate { basis_points: 0 };
+
+    /// VAT rate of whole percent, e.g. `VatRate::percent(15)`
+    pub fn percent(percent: u32) -> Self {
+        VatRate {
+            basis_points: percent.saturating_mul(100),
+        }
+    }
+
+    /// VAT rate in basis points, e.g. `750` for 7.5%
+    pub fn from_basis_points(basis_points: u32) -> Self {
+        VatRate { basis_points }
+    }
+
+    pub fn basis_points(&self) -> u32 {
+        self.basis_points
+    }
+
+    /// VAT of a net amount
+    pub fn of(&self, net: &Money) -> Result<Money, SpError> {
+        net.basis_points(self.basis_points)
+    }
+}
+
+/// A product in a cart
+/// `unit_price` excludes VAT
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone` and `PartialEq` functions
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LineItem {
+    pub sku: String,
+    pub name: String,
+    pub quantity: u32,
+    pub unit_price: Money,
+    pub vat_rate: VatRate,
+}
+
+impl LineItem {
+    /// This is a constructor to initiate a line item without VAT
+    pub fn new(sku: &str, name: &str, quantity: u32, unit_price: Money) -> Self {
+        LineItem {
+            sku: sku.to_string(),
+            name: name.to_string(),
+            quantity,
+            unit_price,
+            vat_rate: VatRate::ZERO,
+        }
+    }
+
+    /// Sets the VAT rate of the item
+    pub fn with_vat(mut self, vat_rate: VatRate) -> Self {
+        self.vat_rate = vat_rate;
+        self
+    }
+
+    /// Quantity times unit price
+    pub fn net(&self) -> Result<Money, SpError> {
+        self.unit_price.checked_mul(i64::from(self.quantity))
+    }
+}
+
+/// A discount taken off the cart before VAT
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone` and `PartialEq` functions
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CartDiscount {
+    /// Shown on receipts, e.g. a coupon code
+    pub label: String,
+    pub amount: Money,
+}
+
+/// Amounts of one line of a cart
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone` and `PartialEq` functions
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct LineTotal {
+    pub sku: String,
+    /// Quantity times unit price
+    pub net: Money,
+    /// Share of the cart discounts
+    pub discount: Money,
+    pub vat: Money,
+    /// `net - discount + vat`
+    pub total: Money,
+}
+
+/// Amounts of a cart
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone` and `PartialEq` functions
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CartTotals {
+    pub lines: Vec<LineTotal>,
+    /// Sum of the line nets
+    pub subtotal: Money,
+    /// Sum of the discounts, at most the subtotal
+    pub discount: Money,
+    pub vat: Money,
+    pub shipping: Money,
+    /// `subtotal - discount + vat + shipping`, the amount to charge
+    pub total: Money,
+}
+
+/// An itemized order in a single currency
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone` and `PartialEq` functions
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Cart {
+    currency: String,
+    items: Vec<LineItem>,
+    discounts: Vec<CartDiscount>,
+    shipping: Money,
+}
+
+impl Cart {
+    /// This is a constructor to initiate an empty cart in `currency`
+    pub fn new(currency: &str) -> Self {
+        let shipping = Money::zero(currency);
+        Cart {
+            currency: shipping.currency().to_string(),
+            items: Vec::new(),
+            discounts: Vec::new(),
+            shipping,
+        }
+    }
+
+    /// Upper case currency code of the cart
+    pub fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    pub fn items(&self) -> &[LineItem] {
+        &self.items
+    }
+
+    pub fn discounts(&self) -> &[CartDiscount] {
+        &self.discounts
+    }
+
+    pub fn shipping(&self) -> &Money {
+        &self.shipping
+    }
+
+    /// Adds a line item
+    /// The quantity must be positive and the unit price not negative, in the currency of the cart
+    pub fn add_item(&mut self, item: LineItem) -> Result<&mut Self, SpError> {
+        self.check_currency(&item.unit_price)?;
+        if item.quantity == 0 {
+            return Err(SpError::InvalidAmount(format!("quantity of {} must be positive", item.sku)));
+        }
+        if item.unit_price.minor() < 0 {
+            return Err(SpError::InvalidAmount(format!("unit price of {} is negative", item.sku)));
+        }
+        self.items.push(item);
+        Ok(self)
+    }
+
+    /// Adds a discount, the amount must not be negative
+    pub fn add_discount(&mut self, label: &str, amount: Money) -> Result<&mut Self, SpError> {
+        self.check_currency(&amount)?;
+        if amount.minor() < 0 {
+            return Err(SpError::InvalidAmount(format!("discount {} is negative", label)));
+        }
+        self.discounts.push(CartDiscount {
+            label: label.to_string(),
+            amount,
+        });
+        Ok(self)
+    }
+
+    /// Adds a discount computed on the sum of the line nets, e.g. of a redeemed coupon
+    pub fn apply_discount(&mut self, label: &str, discount: &Discount) -> Result<&mut Self, SpError> {
+        let nets = self.items.iter().map(LineItem::net).collect::<Result<Vec<Money>, SpError>>()?;
+        let applied = discount.apply(&sum(&self.currency, &nets)?)?;
+        self.add_discount(label, applied.discount)
+    }
+
+    /// Sets the shipping charge, it must not be negative
+    pub fn set_shipping(&mut self, shipping: Money) -> Result<&mut Self, SpError> {
+        self.check_currency(&shipping)?;
+        if shipping.minor() < 0 {
+            return Err(SpError::InvalidAmount(format!("shipping {} is negative", shipping)));
+        }
+        self.shipping = shipping;
+        Ok(self)
+    }
+
+    /// This function computes the amounts of the cart
+    /// An empty cart or a total that is not positive is `SpError::InvalidAmount`
+    pub fn totals(&self) -> Result<CartTotals, SpError> {
+        if self.items.is_empty() {
+            return Err(SpError::InvalidAmount("cart is empty".to_string()));
+        }
+        let nets = self.items.iter().map(LineItem::net).collect::<Result<Vec<Money>, SpError>>()?;
+        let subtotal = sum(&self.currency, &nets)?;
+        let discounts: Vec<Money> = self.discounts.iter().map(|discount| discount.amount.clone()).collect();
+        let discount = sum(&self.currency, &discounts)?;
+        let discount = if discount.minor() > subtotal.minor() { subtotal.clone() } else { discount };
+
+        let shares = allocate(&discount, &nets);
+        let mut lines = Vec::with_capacity(self.items.len());
+        for ((item, net), share) in self.items.iter().zip(nets).zip(shares) {
+            let vat = item.vat_rate.of(&net.checked_sub(&share)?)?;
+            let total = net.checked_sub(&share)?.checked_add(&vat)?;
+            lines.push(LineTotal {
+                sku: item.sku.clone(),
+                net,
+                discount: share,
+                vat,
+                total,
+            });
+        }
+        let vats: Vec<Money> = lines.iter().map(|line| line.vat.clone()).collect();
+        let vat = sum(&self.currency, &vats)?;
+        let total = subtotal
+            .checked_sub(&discount)?
+            .checked_add(&vat)?
+            .checked_add(&self.shipping)?;
+        if total.minor() <= 0 {
+            return Err(SpError::InvalidAmount(format!("cart total {} must be positive", total)));
+        }
+        Ok(CartTotals {
+            lines,
+            subtotal,
+            discount,
+            vat,
+            shipping: self.shipping.clone(),
+            total,
+        })
+    }
+
+    /// This function sets the `amount` and `currency` of a checkout to the total of the cart
+    pub fn to_checkout(&self, mut checkout_item: SpCheckout) -> Result<SpCheckout, SpError> {
+        let totals = self.totals()?;
+        checkout_item.amount = totals.total.amount_string();
+        checkout_item.currency = self.currency.clone();
+        Ok(checkout_item)
+    }
+
+    /// Builds the record reconciliation compares with the gateway's `payable_amount`
+    /// `order_id` is the shurjopay order id of the checkout
+    pub fn local_record(&self, order_id: &str, expected_status: PaymentState) -> Result<LocalRecord, SpError> {
+        Ok(LocalRecord {
+            order_id: order_id.to_string(),
+            expected: self.totals()?.total,
+            expected_status,
+        })
+    }
+
+    fn check_currency(&self, amount: &Money) -> Result<(), SpError> {
+        if amount.currency() != self.currency {
+            return Err(SpError::CurrencyMismatch {
+                expected: self.currency.clone(),
+                actual: amount.currency().to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Sum of amounts in `currency`
+fn sum(currency: &str, amounts: &[Money]) -> Result<Money, SpError> {
+    amounts
+        .iter()
+        .try_fold(Money::zero(currency), |total, amount| total.checked_add(amount))
+}
+
+/// Splits `amount` across `weights` in proportion, the shares add up to `amount` exactly
+/// Minor units left by rounding down go to the largest remainders, earlier lines first on ties
+fn allocate(amount: &Money, weights: &[Money]) -> Vec<Money> {
+    let total: i128 = weights.iter().map(|weight| i128::from(weight.minor())).sum();
+    if total <= 0 || amount.minor() == 0 {
+        return weights.iter().map(|_| Money::zero(amount.currency())).collect();
+    }
+    let amount_minor = i128::from(amount.minor());
+    let mut shares: Vec<i128> = Vec::with_capacity(weights.len());
+    let mut remainders: Vec<(i128, usize)> = Vec::with_capacity(weights.len());
+    for (index, weight) in weights.iter().enumerate() {
+        let product = amount_minor * i128::from(weight.minor());
+        shares.push(product / total);
+        remainders.push((product % total, index));
+    }
+    let left = amount_minor - shares.iter().sum::<i128>();
+    remainders.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    for (_, index) in remainders.into_iter().take(left as usize) {
+        shares[index] += 1;
+    }
+    shares
+        .into_iter()
+        .map(|share| Money::from_minor(share as i64, amount.currency()))
+        .collect()
+}
+
+/// Storage backend of cart itemizations, by merchant order id
+pub trait CartStore: fmt::Debug + Send + Sync {
+    /// Inserts or replaces the cart of an order
+    fn save_cart(&self, order_id: &str, cart: &Cart) -> Result<(), SpError>;
+
+    /// Looks up the cart of an order
+    fn find_cart(&self, order_id: &str) -> Result<Option<Cart>, SpError>;
+}
+
+/// In memory `CartStore`
+/// Carts are lost when the process exits
+#[derive(Debug, Default)]
+pub struct MemoryCartStore {
+    carts: Mutex<HashMap<String, Cart>>,
+}
+
+impl MemoryCartStore {
+    /// This is a constructor to initiate an empty `MemoryCartStore`
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl CartStore for MemoryCartStore {
+    fn save_cart(&self, order_id: &str, cart: &Cart) -> Result<(), SpError> {
+        let mut carts = self.carts.lock().map_err(|e| SpError::Store(e.to_string()))?;
+        carts.insert(order_id.to_string(), cart.clone());
+        Ok(())
+    }
+
+    fn find_cart(&self, order_id: &str) -> Result<Option<Cart>, SpError> {
+        let carts = self.carts.lock().map_err(|e| SpError::Store(e.to_string()))?;
+        Ok(carts.get(order_id).cloned())
+    }
+}
+
+impl ShurjopayPlugin {
+    /// This function commits secure checkout of a cart
+    ///
+    /// `checkout_item` supplies the order id and customer, its amount and currency are
+    /// replaced by the cart total. The cart is saved in `store` under the merchant
+    /// order id before the checkout is sent.
+    pub fn make_payment_cart(
+        &mut self,
+        cart: &Cart,
+        checkout_item: SpCheckout,
+        store: &dyn CartStore,
+    ) -> Result<SpCheckoutResponse, SpError> {
+        let checkout_item = cart.to_checkout(checkout_item)?;
+        store.save_cart(&checkout_item.order_id, cart)?;
+        self.make_payment_checkout(checkout_item).ok_or_else(|| {
+            self.last_error
+                .clone()
+                .unwrap_or_else(|| SpError::Http("checkout failed".to_string()))
+        })
+    }
+}
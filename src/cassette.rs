@@ -0,0 +1,218 @@
+//!
+//! Record-and-replay transports for deterministic tests.
+//!
+//! `RecordingTransport` wraps a real transport and writes every
+//! request/response pair to a cassette file. The `Authorization` header,
+//! the `password` sent for a token and the `token` received are scrubbed
+//! before anything is written.
+//!
+//! `ReplayTransport` serves the recorded responses without a network.
+//! Requests are matched by method, url and body; a request that matches
+//! no recorded interaction panics so the test fails loudly.
+//!
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::error::SpError;
+use crate::transport::{Transport, TransportRequest, TransportResponse};
+
+/// Value written in place of a secret
+pub const SCRUBBED: &str = "[scrubbed]";
+
+/// Request headers whose values are scrubbed
+const SECRET_HEADERS: [&str; 2] = ["authorization", "proxy-authorization"];
+
+/// JSON fields of request and response bodies whose values are scrubbed
+const SECRET_FIELDS: [&str; 2] = ["password", "token"];
+
+/// A recorded request and its response
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone` and `PartialEq` functions
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Interaction {
+    pub request: TransportRequest,
+    pub response: TransportResponse,
+}
+
+/// Recorded interactions, stored as pretty printed JSON
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone`, `PartialEq` and `Default` functions
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SpError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|err| SpError::Config(format!("cannot read cassette {}: {}", path.display(), err)))?;
+        serde_json::from_str(&text)
+            .map_err(|err| SpError::Config(format!("invalid cassette {}: {}", path.display(), err)))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SpError> {
+        let path = path.as_ref();
+        let text = serde_json::to_string_pretty(self).map_err(|err| SpError::Config(err.to_string()))?;
+        fs::write(path, text)
+            .map_err(|err| SpError::Config(format!("cannot write cassette {}: {}", path.display(), err)))
+    }
+}
+
+/// Transport recording every interaction of an inner transport to a cassette file
+/// The file is rewritten after each interaction
+#[derive(Debug)]
+pub struct RecordingTransport {
+    inner: Arc<dyn Transport>,
+    path: PathBuf,
+    cassette: Mutex<Cassette>,
+}
+
+impl RecordingTransport {
+    /// Records to a new cassette at `path`, an existing file is replaced
+    pub fn new<P: Into<PathBuf>>(inner: Arc<dyn Transport>, path: P) -> Self {
+        RecordingTransport {
+            inner,
+            path: path.into(),
+            cassette: Mutex::new(Cassette::default()),
+        }
+    }
+
+    /// Interactions recorded so far
+    pub fn cassette(&self) -> Cassette {
+        self.cassette.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+}
+
+impl Transport for RecordingTransport {
+    fn send(&self, request: &TransportRequest) -> Result<TransportResponse, SpError> {
+        let response = self.inner.send(request)?;
+        let mut cassette = self.cassette.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        cassette.interactions.push(Interaction {
+            request: scrub_request(request),
+            response: scrub_response(&response),
+        });
+        if let Err(err) = cassette.save(&self.path) {
+            eprintln!("{}", err);
+        }
+        Ok(response)
+    }
+}
+
+/// Transport serving the responses of a cassette
+/// Each interaction is served once in recorded order; once all matching
+/// interactions are used the last one is served again, e.g. for token refreshes
+#[derive(Debug)]
+pub struct ReplayTransport {
+    cassette: Cassette,
+    used: Mutex<Vec<bool>>,
+}
+
+impl ReplayTransport {
+    pub fn new(cassette: Cassette) -> Self {
+        let used = vec![false; cassette.interactions.len()];
+        ReplayTransport {
+            cassette,
+            used: Mutex::new(used),
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SpError> {
+        Ok(Self::new(Cassette::load(path)?))
+    }
+
+    /// Number of recorded interactions not served yet
+    pub fn unplayed(&self) -> usize {
+        self.used
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .iter()
+            .filter(|used| !**used)
+            .count()
+    }
+}
+
+impl Transport for ReplayTransport {
+    /// Panics if no recorded interaction matches `request`
+    fn send(&self, request: &TransportRequest) -> Result<TransportResponse, SpError> {
+        let request = scrub_request(request);
+        let mut used = self.used.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let matching: Vec<usize> = self
+            .cassette
+            .interactions
+            .iter()
+            .enumerate()
+            .filter(|(_, interaction)| same_request(&interaction.request, &request))
+            .map(|(index, _)| index)
+            .collect();
+        let index = match matching.iter().find(|index| !used[**index]).or_else(|| matching.last()) {
+            Some(index) => *index,
+            None => panic!(
+                "cassette has no recorded response for {} {} {}",
+                request.method, request.url, request.body
+            ),
+        };
+        used[index] = true;
+        Ok(self.cassette.interactions[index].response.clone())
+    }
+}
+
+/// Copy of `request` with its secrets scrubbed
+pub fn scrub_request(request: &TransportRequest) -> TransportRequest {
+    TransportRequest {
+        method: request.method.clone(),
+        url: request.url.clone(),
+        headers: request
+            .headers
+            .iter()
+            .map(|(name, value)| {
+                if SECRET_HEADERS.contains(&name.to_ascii_lowercase().as_str()) {
+                    (name.clone(), SCRUBBED.to_string())
+                } else {
+                    (name.clone(), value.clone())
+                }
+            })
+            .collect(),
+        body: scrub_body(&request.body),
+    }
+}
+
+/// Copy of `response` with its secrets scrubbed
+pub fn scrub_response(response: &TransportResponse) -> TransportResponse {
+    TransportResponse {
+        status: response.status,
+        headers: response.headers.clone(),
+        body: scrub_body(&response.body),
+    }
+}
+
+fn scrub_body(body: &str) -> String {
+    let mut json: Value = match serde_json::from_str(body) {
+        Ok(json) => json,
+        Err(_) => return body.to_string(),
+    };
+    if let Value::Object(fields) = &mut json {
+        for field in SECRET_FIELDS {
+            if let Some(value) = fields.get_mut(field) {
+                *value = Value::String(SCRUBBED.to_string());
+            }
+        }
+    }
+    json.to_string()
+}
+
+fn same_request(recorded: &TransportRequest, request: &TransportRequest) -> bool {
+    if !recorded.method.eq_ignore_ascii_case(&request.method) || recorded.url != request.url {
+        return false;
+    }
+    match (
+        serde_json::from_str::<Value>(&recorded.body),
+        serde_json::from_str::<Value>(&request.body),
+    ) {
+        (Ok(recorded), Ok(request)) => recorded == request,
+        _ => recorded.body == request.body,
+    }
+}
@@ -0,0 +1,203 @@
+//!
+//! This module implements a circuit breaker per shurjopay end point.
+//!
+//! Every request to an end point is recorded as a success or a failure.
+//! Failures are requests that could not be sent or got a server error.
+//! Once the failure rate of the last `window` requests reaches
+//! `failure_rate`, the circuit of that end point opens and requests fail
+//! fast with `SpError::CircuitOpen` instead of blocking until they time out.
+//! After `cool_down` a single probe request is let through (half-open):
+//! its success closes the circuit, its failure opens it again.
+//!
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::SpError;
+
+/// State of the circuit of an end point
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone`, `Copy` and `PartialEq` functions
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Requests are sent
+    Closed,
+    /// Requests fail fast until the cool-down is over
+    Open,
+    /// A single probe request decides whether the circuit closes again
+    HalfOpen,
+}
+
+impl CircuitState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CircuitState::Closed => "closed",
+            CircuitState::Open => "open",
+            CircuitState::HalfOpen => "half_open",
+        }
+    }
+}
+
+impl fmt::Display for CircuitState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Settings of the circuit breaker
+/// This structure implements `Debug`, `Clone` and `PartialEq` functions
+#[derive(Debug, Clone, PartialEq)]
+pub struct CircuitBreakerConfig {
+    /// Number of recent requests the failure rate is computed over
+    pub window: usize,
+    /// Requests in the window needed before the circuit may open
+    pub minimum_requests: usize,
+    /// Failure rate from 0.0 to 1.0 that opens the circuit
+    pub failure_rate: f64,
+    /// Time an open circuit fails fast before a probe request is let through
+    pub cool_down: Duration,
+}
+
+impl Default for CircuitBreakerConfig {
+    /// This function will set default value for CircuitBreakerConfig struct
+    fn default() -> Self {
+        CircuitBreakerConfig {
+            window: 20,
+            minimum_requests: 5,
+            failure_rate: 0.5,
+            cool_down: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct EndPointCircuit {
+    outcomes: VecDeque<bool>,
+    opened_at: Option<Instant>,
+    probe_in_flight: bool,
+}
+
+impl EndPointCircuit {
+    fn state(&self, config: &CircuitBreakerConfig) -> CircuitState {
+        match self.opened_at {
+            None => CircuitState::Closed,
+            Some(_) if self.probe_in_flight => CircuitState::HalfOpen,
+            Some(opened_at) if opened_at.elapsed() >= config.cool_down => CircuitState::HalfOpen,
+            Some(_) => CircuitState::Open,
+        }
+    }
+
+    fn failure_rate_reached(&self, config: &CircuitBreakerConfig) -> bool {
+        if self.outcomes.len() < config.minimum_requests.max(1) {
+            return false;
+        }
+        let failures = self.outcomes.iter().filter(|success| !**success).count();
+        failures as f64 / self.outcomes.len() as f64 >= config.failure_rate
+    }
+}
+
+/// Circuit breaker keeping one circuit per end point
+/// Clones of a `ShurjopayPlugin` share the breaker
+#[derive(Debug, Default)]
+pub struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    circuits: Mutex<HashMap<String, EndPointCircuit>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        CircuitBreaker {
+            config,
+            circuits: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn config(&self) -> &CircuitBreakerConfig {
+        &self.config
+    }
+
+    /// Checks whether a request to `end_point` may be sent
+    /// A half-open circuit lets one probe request through and refuses the others
+    pub fn allow(&self, end_point: &str) -> Result<(), SpError> {
+        let mut circuits = self.lock();
+        let circuit = circuits.entry(end_point.to_string()).or_default();
+        match circuit.state(&self.config) {
+            CircuitState::Closed => Ok(()),
+            CircuitState::HalfOpen if !circuit.probe_in_flight => {
+                circuit.probe_in_flight = true;
+                Ok(())
+            }
+            _ => {
+                let retry_after = circuit
+                    .opened_at
+                    .map(|opened_at| self.config.cool_down.saturating_sub(opened_at.elapsed()))
+                    .unwrap_or_default();
+                Err(SpError::CircuitOpen {
+                    end_point: end_point.to_string(),
+                    retry_after_ms: retry_after.as_millis() as u64,
+                })
+            }
+        }
+    }
+
+    /// Records a successful request to `end_point`
+    pub fn record_success(&self, end_point: &str) {
+        self.record(end_point, true);
+    }
+
+    /// Records a failed request to `end_point`
+    pub fn record_failure(&self, end_point: &str) {
+        self.record(end_point, false);
+    }
+
+    /// Current state of the circuit of `end_point`
+    pub fn state(&self, end_point: &str) -> CircuitState {
+        self.lock()
+            .get(end_point)
+            .map(|circuit| circuit.state(&self.config))
+            .unwrap_or(CircuitState::Closed)
+    }
+
+    /// States of every end point a request was sent to, for health checks
+    pub fn states(&self) -> BTreeMap<String, CircuitState> {
+        self.lock()
+            .iter()
+            .map(|(end_point, circuit)| (end_point.clone(), circuit.state(&self.config)))
+            .collect()
+    }
+
+    /// Closes every circuit and forgets the recorded requests
+    pub fn reset(&self) {
+        self.lock().clear();
+    }
+
+    fn record(&self, end_point: &str, success: bool) {
+        let mut circuits = self.lock();
+        let circuit = circuits.entry(end_point.to_string()).or_default();
+        if circuit.opened_at.is_some() {
+            circuit.probe_in_flight = false;
+            if success {
+                *circuit = EndPointCircuit::default();
+                circuit.outcomes.push_back(true);
+            } else {
+                circuit.opened_at = Some(Instant::now());
+            }
+            return;
+        }
+        circuit.outcomes.push_back(success);
+        while circuit.outcomes.len() > self.config.window.max(1) {
+            circuit.outcomes.pop_front();
+        }
+        if !success && circuit.failure_rate_reached(&self.config) {
+            circuit.opened_at = Some(Instant::now());
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, EndPointCircuit>> {
+        self.circuits.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
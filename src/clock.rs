@@ -0,0 +1,64 @@
+//!
+//! This module provides the clock billing and expiry decisions read time from.
+//!
+//! `SystemClock` reads the system time. `ManualClock` only moves when it is
+//! set or advanced, so expiry and billing schedules can be tested without
+//! waiting.
+//!
+
+use std::fmt;
+use std::sync::Mutex;
+
+use chrono::{Duration, NaiveDateTime, Utc};
+
+/// Source of the current UTC time
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now(&self) -> NaiveDateTime;
+}
+
+/// `Clock` of the system time
+/// This structure implements `Debug`, `Clone`, `Copy` and `Default` functions
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> NaiveDateTime {
+        Utc::now().naive_utc()
+    }
+}
+
+/// `Clock` that only moves when it is set or advanced, for tests and billing simulations
+#[derive(Debug)]
+pub struct ManualClock {
+    now: Mutex<NaiveDateTime>,
+}
+
+impl ManualClock {
+    /// This is a constructor to initiate a clock stopped at `now`
+    pub fn new(now: NaiveDateTime) -> Self {
+        ManualClock { now: Mutex::new(now) }
+    }
+
+    /// Sets the current time
+    pub fn set(&self, now: NaiveDateTime) {
+        if let Ok(mut current) = self.now.lock() {
+            *current = now;
+        }
+    }
+
+    /// Moves the current time forward by `duration`
+    pub fn advance(&self, duration: Duration) {
+        if let Ok(mut current) = self.now.lock() {
+            *current += duration;
+        }
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> NaiveDateTime {
+        match self.now.lock() {
+            Ok(now) => *now,
+            Err(poisoned) => *poisoned.into_inner(),
+        }
+    }
+}
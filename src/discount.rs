@@ -0,0 +1,246 @@
+//!
+//! This module applies discounts and coupons to checkouts.
+//!
+//! A `Discount` is a fixed amount, a percentage or a percentage capped at an
+//! amount. Applied to the amount of an order it gives an `AppliedDiscount`
+//! with the exact discount and payable amount, known before the checkout is
+//! sent. The checkout carries the original `amount` with `discount_amount`
+//! and `disc_percent`, and shurjopay reports `payable_amount`,
+//! `discsount_amount` and `disc_percent` back on verification, where
+//! `compare` checks them against what was intended.
+//!
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::error::SpError;
+use crate::money::Money;
+use crate::reconciliation::{AmountField, Mismatch};
+use crate::shurjopay::{SpCheckout, SpVerifyResponse};
+
+/// Basis points in one percent
+const BASIS_POINTS_PER_PERCENT: u32 = 100;
+
+/// A discount on the amount of an order
+/// Percentages are in basis points, `1000` is 10%
+/// This enum implements `Serialize`, `Deserialize`, `Debug`, `Clone` and `PartialEq` functions
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Discount {
+    /// A fixed amount off
+    Fixed(Money),
+    /// A percentage of the amount
+    Percentage { basis_points: u32 },
+    /// A percentage of the amount, at most `cap`
+    Capped { basis_points: u32, cap: Money },
+}
+
+impl Discount {
+    /// A discount of whole percent, e.g. `Discount::percent(10)`
+    pub fn percent(percent: u32) -> Self {
+        Discount::Percentage {
+            basis_points: percent.saturating_mul(BASIS_POINTS_PER_PERCENT),
+        }
+    }
+
+    /// A discount of whole percent, at most `cap`
+    pub fn percent_capped(percent: u32, cap: Money) -> Self {
+        Discount::Capped {
+            basis_points: percent.saturating_mul(BASIS_POINTS_PER_PERCENT),
+            cap,
+        }
+    }
+
+    /// This function computes the discount of `amount`
+    ///
+    /// Percentages are rounded half away from zero. The discount is never more than
+    /// the amount; negative amounts, percentages above 100% and amounts in another
+    /// currency are errors.
+    pub fn apply(&self, amount: &Money) -> Result<AppliedDiscount, SpError> {
+        if amount.minor() < 0 {
+            return Err(SpError::InvalidAmount(format!("amount {} is negative", amount)));
+        }
+        let (discount, basis_points) = match self {
+            Discount::Fixed(off) => {
+                check_currency(amount, off)?;
+                if off.minor() < 0 {
+                    return Err(SpError::InvalidAmount(format!("discount {} is negative", off)));
+                }
+                (off.clone(), None)
+            }
+            Discount::Percentage { basis_points } => {
+                check_basis_points(*basis_points)?;
+                (amount.basis_points(*basis_points)?, Some(*basis_points))
+            }
+            Discount::Capped { basis_points, cap } => {
+                check_basis_points(*basis_points)?;
+                check_currency(amount, cap)?;
+                let discount = amount.basis_points(*basis_points)?;
+                if discount.minor() > cap.minor() {
+                    // The gateway would take the percentage, so only the capped amount is sent
+                    (cap.clone(), None)
+                } else {
+                    (discount, Some(*basis_points))
+                }
+            }
+        };
+        let discount = if discount.minor() > amount.minor() { amount.clone() } else { discount };
+        Ok(AppliedDiscount {
+            amount: amount.clone(),
+            payable: amount.checked_sub(&discount)?,
+            discount,
+            basis_points,
+        })
+    }
+}
+
+fn check_basis_points(basis_points: u32) -> Result<(), SpError> {
+    if basis_points > 100 * BASIS_POINTS_PER_PERCENT {
+        return Err(SpError::InvalidAmount(format!(
+            "discount of {} is more than 100%",
+            percent_string(basis_points)
+        )));
+    }
+    Ok(())
+}
+
+fn check_currency(amount: &Money, other: &Money) -> Result<(), SpError> {
+    if amount.currency() != other.currency() {
+        return Err(SpError::CurrencyMismatch {
+            expected: amount.currency().to_string(),
+            actual: other.currency().to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Formats basis points as a percentage without trailing zeros, `750` is `7.5`
+fn percent_string(basis_points: u32) -> String {
+    let whole = basis_points / BASIS_POINTS_PER_PERCENT;
+    match basis_points % BASIS_POINTS_PER_PERCENT {
+        0 => whole.to_string(),
+        fraction if fraction % 10 == 0 => format!("{}.{}", whole, fraction / 10),
+        fraction => format!("{}.{:02}", whole, fraction),
+    }
+}
+
+/// A discount computed for the amount of an order
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone` and `PartialEq` functions
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct AppliedDiscount {
+    /// Amount before the discount, sent as `amount`
+    pub amount: Money,
+    /// Sent as `discount_amount`, reported as `discsount_amount`
+    pub discount: Money,
+    /// `amount - discount`, reported as `payable_amount`
+    pub payable: Money,
+    /// Percentage sent as `disc_percent`, `None` for fixed and capped amounts
+    pub basis_points: Option<u32>,
+}
+
+impl AppliedDiscount {
+    /// This function sets the amount, currency and discount fields of a checkout
+    pub fn to_checkout(&self, mut checkout_item: SpCheckout) -> SpCheckout {
+        checkout_item.amount = self.amount.amount_string();
+        checkout_item.currency = self.amount.currency().to_string();
+        checkout_item.discount_amount = Some(self.discount.amount_string());
+        checkout_item.disc_percent = self.basis_points.map(percent_string);
+        checkout_item
+    }
+}
+
+/// Compares the discount reported on verification with the intended discount
+///
+/// `payable_amount` and `discsount_amount` must equal the intended amounts, and
+/// `disc_percent` the intended percentage, or be zero or missing if none was sent.
+pub fn compare(applied: &AppliedDiscount, response: &SpVerifyResponse) -> Vec<Mismatch> {
+    let mut mismatches = Vec::new();
+    let currency = applied.amount.currency();
+    let amounts = [
+        (AmountField::PayableAmount, &applied.payable, response.payable_amount),
+        (AmountField::DiscountAmount, &applied.discount, response.discsount_amount),
+    ];
+    for (field, expected, actual) in amounts {
+        // A gateway that leaves `discsount_amount` out reports no discount
+        let actual = match (field, actual) {
+            (AmountField::DiscountAmount, None) => Some(0.0),
+            (_, actual) => actual,
+        };
+        match actual.map(|actual| (actual, Money::from_f64(actual, currency))) {
+            Some((_, Ok(actual))) if &actual == expected => {}
+            Some((_, Ok(actual))) => mismatches.push(Mismatch::Amount {
+                field,
+                expected: expected.clone(),
+                actual,
+            }),
+            Some((value, Err(_))) => mismatches.push(Mismatch::InvalidAmount {
+                field,
+                value: value.to_string(),
+            }),
+            None => mismatches.push(Mismatch::InvalidAmount {
+                field,
+                value: "null".to_string(),
+            }),
+        }
+    }
+
+    let expected = applied.basis_points.unwrap_or(0);
+    let actual = response.disc_percent.unwrap_or(0.0);
+    if (actual * f64::from(BASIS_POINTS_PER_PERCENT)).round() != f64::from(expected) {
+        mismatches.push(Mismatch::DiscountPercent {
+            expected: percent_string(expected),
+            actual: response.disc_percent,
+        });
+    }
+    mismatches
+}
+
+/// A discount customers redeem with a code
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone` and `PartialEq` functions
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Coupon {
+    /// Compared without case
+    pub code: String,
+    pub discount: Discount,
+    /// Smallest order amount the coupon applies to
+    pub minimum: Option<Money>,
+    /// Last moment the coupon can be redeemed, in UTC
+    pub valid_until: Option<NaiveDateTime>,
+}
+
+impl Coupon {
+    /// This is a constructor to initiate a coupon without minimum or expiry
+    pub fn new(code: &str, discount: Discount) -> Self {
+        Coupon {
+            code: code.trim().to_string(),
+            discount,
+            minimum: None,
+            valid_until: None,
+        }
+    }
+
+    /// Returns true if `code` redeems this coupon
+    pub fn matches(&self, code: &str) -> bool {
+        self.code.eq_ignore_ascii_case(code.trim())
+    }
+
+    /// This function redeems the coupon for `amount` at `now` (UTC)
+    /// Expired coupons and amounts below the minimum are `SpError::InvalidAmount`
+    pub fn apply(&self, amount: &Money, now: NaiveDateTime) -> Result<AppliedDiscount, SpError> {
+        if let Some(valid_until) = self.valid_until {
+            if now > valid_until {
+                return Err(SpError::InvalidAmount(format!("coupon {} has expired", self.code)));
+            }
+        }
+        if let Some(minimum) = &self.minimum {
+            check_currency(amount, minimum)?;
+            if amount.minor() < minimum.minor() {
+                return Err(SpError::InvalidAmount(format!(
+                    "coupon {} needs an order of at least {}",
+                    self.code, minimum
+                )));
+            }
+        }
+        self.discount.apply(amount)
+    }
+}
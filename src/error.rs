@@ -0,0 +1,113 @@
+//!
+//! This module holds the error type shared by the shurjopay plugin.
+//!
+
+use std::fmt;
+
+use crate::phone::PhoneError;
+
+/// Errors reported by the shurjopay plugin and its helper modules
+/// This structure implements `Debug`, `Clone` and `PartialEq` functions
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpError {
+    /// The plugin is not configured
+    Config(String),
+    /// The http request could not be sent or its response could not be read
+    Http(String),
+    /// Shurjopay refused the username or password
+    Auth(String),
+    /// Shurjopay answered with a response the plugin does not understand
+    Gateway { http_code: u16, body: String },
+    /// A JSON response of shurjopay does not fit the response model, `message` says why
+    Decode {
+        http_code: u16,
+        message: String,
+        body: String,
+    },
+    /// The payment store failed to read or write a record
+    Store(String),
+    /// An amount could not be read as money
+    InvalidAmount(String),
+    /// Two amounts in different currencies were combined
+    CurrencyMismatch { expected: String, actual: String },
+    /// A merchant prefix or order id is not accepted by shurjopay
+    InvalidOrderId(String),
+    /// A customer phone number is not a Bangladeshi mobile number
+    InvalidPhone(PhoneError),
+    /// The request did not finish within the configured timeout
+    Timeout(String),
+    /// The request was refused because too many requests are in flight
+    Overloaded(String),
+    /// The circuit of the end point is open after repeated failures, retry later
+    CircuitOpen { end_point: String, retry_after_ms: u64 },
+    /// The client side rate limit of the end point is exhausted
+    RateLimited { end_point: String, retry_after_ms: u64 },
+    /// A checkout for the order is live with a different amount or currency
+    IdempotencyConflict {
+        order_id: String,
+        expected: String,
+        actual: String,
+    },
+    /// The order is already paid, a new checkout would charge it twice
+    AlreadyPaid(String),
+    /// Another checkout of the order is being created
+    CheckoutInFlight(String),
+    /// No payment link has the id
+    LinkNotFound(String),
+    /// The payment link expired before it was paid
+    LinkExpired(String),
+    /// The payment link is already paid
+    LinkConsumed(String),
+    /// No subscription has the id
+    SubscriptionNotFound(String),
+}
+
+impl fmt::Display for SpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpError::Config(msg) => write!(f, "configuration error: {}", msg),
+            SpError::Http(msg) => write!(f, "http error: {}", msg),
+            SpError::Auth(msg) => write!(f, "authentication failed: {}", msg),
+            SpError::Gateway { http_code, body } => {
+                write!(f, "unexpected gateway response ({}): {}", http_code, body)
+            }
+            SpError::Decode { http_code, message, body } => {
+                write!(f, "cannot decode gateway response ({}): {}: {}", http_code, message, body)
+            }
+            SpError::Store(msg) => write!(f, "payment store error: {}", msg),
+            SpError::InvalidAmount(amount) => write!(f, "invalid amount `{}`", amount),
+            SpError::CurrencyMismatch { expected, actual } => {
+                write!(f, "currency mismatch: expected {}, found {}", expected, actual)
+            }
+            SpError::InvalidOrderId(msg) => write!(f, "invalid order id: {}", msg),
+            SpError::InvalidPhone(err) => write!(f, "invalid phone number: {}", err),
+            SpError::Timeout(msg) => write!(f, "request timed out: {}", msg),
+            SpError::Overloaded(msg) => write!(f, "too many requests in flight: {}", msg),
+            SpError::CircuitOpen { end_point, retry_after_ms } => write!(
+                f,
+                "circuit of `{}` is open after repeated failures, retry in {} ms",
+                end_point, retry_after_ms
+            ),
+            SpError::RateLimited { end_point, retry_after_ms } => write!(
+                f,
+                "rate limit of `{}` is exhausted, retry in {} ms",
+                end_point, retry_after_ms
+            ),
+            SpError::IdempotencyConflict { order_id, expected, actual } => write!(
+                f,
+                "order {} already has a live checkout of {}, requested {}",
+                order_id, expected, actual
+            ),
+            SpError::AlreadyPaid(order_id) => write!(f, "order {} is already paid", order_id),
+            SpError::CheckoutInFlight(order_id) => {
+                write!(f, "a checkout of order {} is already being created", order_id)
+            }
+            SpError::LinkNotFound(id) => write!(f, "payment link {} not found", id),
+            SpError::LinkExpired(id) => write!(f, "payment link {} has expired", id),
+            SpError::LinkConsumed(id) => write!(f, "payment link {} is already paid", id),
+            SpError::SubscriptionNotFound(id) => write!(f, "subscription {} not found", id),
+        }
+    }
+}
+
+impl std::error::Error for SpError {}
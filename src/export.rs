@@ -0,0 +1,380 @@
+//!
+//! This module exports verification results for finance reporting.
+//!
+//! Features:
+//! - CSV with a stable, configurable column order
+//! - Newline delimited JSON, one `SpVerifyResponse` per line
+//! - Card numbers are masked unless masking is turned off
+//! - CSV fields that a spreadsheet would run as a formula are prefixed with `'`
+//! - Settlement summary with count and total by status, method and currency
+//!
+
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::SpError;
+use crate::money::Money;
+use crate::payment_store::PaymentState;
+use crate::shurjopay::SpVerifyResponse;
+
+/// A column of the CSV export
+/// The header of each column is the field name of `SpVerifyResponse`
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone`, `Copy` and `PartialEq` functions
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportColumn {
+    OrderId,
+    CustomerOrderId,
+    DateTime,
+    SpCode,
+    SpMessage,
+    TransactionStatus,
+    BankStatus,
+    Method,
+    BankTrxId,
+    InvoiceNo,
+    Currency,
+    Amount,
+    PayableAmount,
+    DiscountAmount,
+    DiscPercent,
+    ReceivedAmount,
+    UsdAmount,
+    UsdRate,
+    CardHolderName,
+    CardNumber,
+    PhoneNo,
+    Name,
+    Email,
+    Address,
+    City,
+    Value1,
+    Value2,
+    Value3,
+    Value4,
+}
+
+impl ExportColumn {
+    /// Every column in export order
+    pub const ALL: [ExportColumn; 29] = [
+        ExportColumn::OrderId,
+        ExportColumn::CustomerOrderId,
+        ExportColumn::DateTime,
+        ExportColumn::SpCode,
+        ExportColumn::SpMessage,
+        ExportColumn::TransactionStatus,
+        ExportColumn::BankStatus,
+        ExportColumn::Method,
+        ExportColumn::BankTrxId,
+        ExportColumn::InvoiceNo,
+        ExportColumn::Currency,
+        ExportColumn::Amount,
+        ExportColumn::PayableAmount,
+        ExportColumn::DiscountAmount,
+        ExportColumn::DiscPercent,
+        ExportColumn::ReceivedAmount,
+        ExportColumn::UsdAmount,
+        ExportColumn::UsdRate,
+        ExportColumn::CardHolderName,
+        ExportColumn::CardNumber,
+        ExportColumn::PhoneNo,
+        ExportColumn::Name,
+        ExportColumn::Email,
+        ExportColumn::Address,
+        ExportColumn::City,
+        ExportColumn::Value1,
+        ExportColumn::Value2,
+        ExportColumn::Value3,
+        ExportColumn::Value4,
+    ];
+
+    /// Columns used by `VerifyExport::default()`
+    pub const DEFAULT: [ExportColumn; 14] = [
+        ExportColumn::OrderId,
+        ExportColumn::CustomerOrderId,
+        ExportColumn::DateTime,
+        ExportColumn::SpCode,
+        ExportColumn::SpMessage,
+        ExportColumn::Method,
+        ExportColumn::BankTrxId,
+        ExportColumn::InvoiceNo,
+        ExportColumn::Currency,
+        ExportColumn::Amount,
+        ExportColumn::PayableAmount,
+        ExportColumn::ReceivedAmount,
+        ExportColumn::CardHolderName,
+        ExportColumn::CardNumber,
+    ];
+
+    /// Header name of the column
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExportColumn::OrderId => "order_id",
+            ExportColumn::CustomerOrderId => "customer_order_id",
+            ExportColumn::DateTime => "date_time",
+            ExportColumn::SpCode => "sp_code",
+            ExportColumn::SpMessage => "sp_message",
+            ExportColumn::TransactionStatus => "transaction_status",
+            ExportColumn::BankStatus => "bank_status",
+            ExportColumn::Method => "method",
+            ExportColumn::BankTrxId => "bank_trx_id",
+            ExportColumn::InvoiceNo => "invoice_no",
+            ExportColumn::Currency => "currency",
+            ExportColumn::Amount => "amount",
+            ExportColumn::PayableAmount => "payable_amount",
+            ExportColumn::DiscountAmount => "discsount_amount",
+            ExportColumn::DiscPercent => "disc_percent",
+            ExportColumn::ReceivedAmount => "received_amount",
+            ExportColumn::UsdAmount => "usd_amt",
+            ExportColumn::UsdRate => "usd_rate",
+            ExportColumn::CardHolderName => "card_holder_name",
+            ExportColumn::CardNumber => "card_number",
+            ExportColumn::PhoneNo => "phone_no",
+            ExportColumn::Name => "name",
+            ExportColumn::Email => "email",
+            ExportColumn::Address => "address",
+            ExportColumn::City => "city",
+            ExportColumn::Value1 => "value1",
+            ExportColumn::Value2 => "value2",
+            ExportColumn::Value3 => "value3",
+            ExportColumn::Value4 => "value4",
+        }
+    }
+
+    /// Finds a column by its header name
+    pub fn from_name(name: &str) -> Option<Self> {
+        ExportColumn::ALL.iter().copied().find(|column| column.name() == name.trim())
+    }
+
+    /// Value of the column for one verification result, empty if the field is `null`
+    fn value(&self, response: &SpVerifyResponse, mask_card_numbers: bool) -> String {
+        fn text(value: &Option<String>) -> String {
+            value.clone().unwrap_or_default()
+        }
+        fn number<T: ToString>(value: &Option<T>) -> String {
+            value.as_ref().map(|value| value.to_string()).unwrap_or_default()
+        }
+        match self {
+            ExportColumn::OrderId => text(&response.order_id),
+            ExportColumn::CustomerOrderId => text(&response.customer_order_id),
+            ExportColumn::DateTime => text(&response.date_time),
+            ExportColumn::SpCode => number(&response.sp_code),
+            ExportColumn::SpMessage => text(&response.sp_message),
+            ExportColumn::TransactionStatus => text(&response.transaction_status),
+            ExportColumn::BankStatus => text(&response.bank_status),
+            ExportColumn::Method => text(&response.method),
+            ExportColumn::BankTrxId => text(&response.bank_trx_id),
+            ExportColumn::InvoiceNo => text(&response.invoice_no),
+            ExportColumn::Currency => text(&response.currency),
+            ExportColumn::Amount => number(&response.amount),
+            ExportColumn::PayableAmount => number(&response.payable_amount),
+            ExportColumn::DiscountAmount => number(&response.discsount_amount),
+            ExportColumn::DiscPercent => number(&response.disc_percent),
+            ExportColumn::ReceivedAmount => text(&response.received_amount),
+            ExportColumn::UsdAmount => number(&response.usd_amt),
+            ExportColumn::UsdRate => number(&response.usd_rate),
+            ExportColumn::CardHolderName => text(&response.card_holder_name),
+            ExportColumn::CardNumber => match &response.card_number {
+                Some(card_number) if mask_card_numbers => mask_card_number(card_number),
+                card_number => text(card_number),
+            },
+            ExportColumn::PhoneNo => text(&response.phone_no),
+            ExportColumn::Name => text(&response.name),
+            ExportColumn::Email => text(&response.email),
+            ExportColumn::Address => text(&response.address),
+            ExportColumn::City => text(&response.city),
+            ExportColumn::Value1 => text(&response.value1),
+            ExportColumn::Value2 => text(&response.value2),
+            ExportColumn::Value3 => text(&response.value3),
+            ExportColumn::Value4 => text(&response.value4),
+        }
+    }
+}
+
+/// Masks every digit of a card number except the last four
+/// Separators and already masked characters are kept as they are
+pub fn mask_card_number(card_number: &str) -> String {
+    let digits = card_number.chars().filter(|ch| ch.is_ascii_digit()).count();
+    let mut seen = 0;
+    card_number
+        .chars()
+        .map(|ch| {
+            if !ch.is_ascii_digit() {
+                return ch;
+            }
+            seen += 1;
+            if digits - seen < 4 {
+                ch
+            } else {
+                '*'
+            }
+        })
+        .collect()
+}
+
+/// Exporter of verification results
+/// This structure implements `Debug` and `Clone` functions
+#[derive(Debug, Clone)]
+pub struct VerifyExport {
+    pub columns: Vec<ExportColumn>,
+    pub mask_card_numbers: bool,
+}
+
+impl Default for VerifyExport {
+    /// Default columns with masked card numbers
+    fn default() -> Self {
+        VerifyExport {
+            columns: ExportColumn::DEFAULT.to_vec(),
+            mask_card_numbers: true,
+        }
+    }
+}
+
+impl VerifyExport {
+    /// Writes a header row and one row per verification result
+    pub fn write_csv<'a, W, I>(&self, mut writer: W, responses: I) -> io::Result<()>
+    where
+        W: Write,
+        I: IntoIterator<Item = &'a SpVerifyResponse>,
+    {
+        let header: Vec<String> = self.columns.iter().map(|column| column.name().to_string()).collect();
+        write_csv_row(&mut writer, &header)?;
+        for response in responses {
+            let row: Vec<String> = self
+                .columns
+                .iter()
+                .map(|column| column.value(response, self.mask_card_numbers))
+                .collect();
+            write_csv_row(&mut writer, &row)?;
+        }
+        writer.flush()
+    }
+
+    /// Writes one JSON object per line with every field of the verification result
+    pub fn write_ndjson<'a, W, I>(&self, mut writer: W, responses: I) -> io::Result<()>
+    where
+        W: Write,
+        I: IntoIterator<Item = &'a SpVerifyResponse>,
+    {
+        for response in responses {
+            let mut response = response.clone();
+            if self.mask_card_numbers {
+                response.card_number = response.card_number.as_deref().map(mask_card_number);
+            }
+            serde_json::to_writer(&mut writer, &response)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()
+    }
+}
+
+fn write_csv_row<W: Write>(writer: &mut W, fields: &[String]) -> io::Result<()> {
+    let row: Vec<String> = fields.iter().map(|field| escape_csv(field)).collect();
+    writer.write_all(row.join(",").as_bytes())?;
+    writer.write_all(b"\r\n")
+}
+
+fn escape_csv(field: &str) -> String {
+    // Spreadsheets run fields starting with these characters as formulas, numbers are safe
+    let formula = field.starts_with(['=', '+', '-', '@', '\t', '\r']) && field.parse::<f64>().is_err();
+    let field = if formula { format!("'{}", field) } else { field.to_string() };
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+/// Count and total amount per currency of a group of payments
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone` and `Default` functions
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SummaryTotals {
+    pub count: usize,
+    pub totals: BTreeMap<String, Money>,
+}
+
+impl SummaryTotals {
+    fn add(&mut self, amount: Option<&Money>) -> Result<(), SpError> {
+        self.count += 1;
+        if let Some(amount) = amount {
+            let total = match self.totals.get(amount.currency()) {
+                Some(total) => total.checked_add(amount)?,
+                None => amount.clone(),
+            };
+            self.totals.insert(amount.currency().to_string(), total);
+        }
+        Ok(())
+    }
+}
+
+/// Settlement summary of a set of verification results
+/// Amounts are `received_amount`, falling back to `payable_amount` when nothing is received
+/// `by_status` totals the amount of every status, `by_method` and `by_currency` total
+/// only paid results, so they hold money that was actually collected
+/// Results without a method or currency are grouped under `"unknown"`, results whose
+/// amount cannot be read are counted without an amount and in `invalid`
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone` and `Default` functions
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SettlementSummary {
+    pub count: usize,
+    #[serde(default)]
+    pub invalid: usize,
+    pub by_status: BTreeMap<String, SummaryTotals>,
+    pub by_method: BTreeMap<String, SummaryTotals>,
+    pub by_currency: BTreeMap<String, SummaryTotals>,
+}
+
+impl SettlementSummary {
+    /// Builds the summary of `responses`
+    pub fn from_responses<'a, I>(responses: I) -> Result<Self, SpError>
+    where
+        I: IntoIterator<Item = &'a SpVerifyResponse>,
+    {
+        let mut summary = SettlementSummary::default();
+        for response in responses {
+            summary.add(response)?;
+        }
+        Ok(summary)
+    }
+
+    /// Adds one verification result to the summary
+    pub fn add(&mut self, response: &SpVerifyResponse) -> Result<(), SpError> {
+        let currency = response
+            .currency
+            .clone()
+            .filter(|currency| !currency.trim().is_empty())
+            .unwrap_or_else(|| "unknown".to_string())
+            .to_uppercase();
+        let amount = match settled_amount(response, &currency) {
+            Ok(amount) => amount,
+            Err(_) => {
+                self.invalid += 1;
+                None
+            }
+        };
+        let method = response
+            .method
+            .clone()
+            .filter(|method| !method.trim().is_empty())
+            .unwrap_or_else(|| "unknown".to_string());
+        let status = PaymentState::from_sp_code(response.sp_code);
+        let collected = amount.as_ref().filter(|_| status == PaymentState::Paid);
+
+        self.count += 1;
+        self.by_status.entry(status.to_string()).or_default().add(amount.as_ref())?;
+        self.by_method.entry(method).or_default().add(collected)?;
+        self.by_currency.entry(currency).or_default().add(collected)?;
+        Ok(())
+    }
+}
+
+fn settled_amount(response: &SpVerifyResponse, currency: &str) -> Result<Option<Money>, SpError> {
+    if let Some(received) = response.received_amount.as_deref().filter(|received| !received.trim().is_empty()) {
+        return Money::from_gateway_str(received, currency).map(Some);
+    }
+    response
+        .payable_amount
+        .map(|payable| Money::from_f64(payable, currency))
+        .transpose()
+}
@@ -0,0 +1,161 @@
+//!
+//! This module holds the settings of the http client used for gateway requests.
+//!
+//! `SpConfig::http` carries timeouts, an outbound proxy, extra root
+//! certificates, the `User-Agent` and connection pool limits. The plugin
+//! rebuilds its client whenever these settings change.
+//!
+
+use std::fmt;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use reqwest::blocking::Client;
+use reqwest::{Certificate, Proxy};
+use serde::{Deserialize, Serialize};
+
+use crate::error::SpError;
+
+/// `User-Agent` sent when `HttpConfig::user_agent` is not set
+pub const DEFAULT_USER_AGENT: &str = concat!("shurjopay-plugin-rust/", env!("CARGO_PKG_VERSION"));
+
+/// Outbound proxy for gateway requests
+/// Without a proxy the `HTTP_PROXY` and `HTTPS_PROXY` environment variables are used
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone` and `PartialEq` functions,
+/// `Debug` does not print the password
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct ProxyConfig {
+    pub url: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+impl fmt::Debug for ProxyConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ProxyConfig")
+            .field("url", &self.url)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "***"))
+            .finish()
+    }
+}
+
+/// Settings of the http client
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone` and `PartialEq` functions
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HttpConfig {
+    /// Time allowed to open a connection
+    #[serde(default = "default_connect_timeout")]
+    pub connect_timeout: Option<Duration>,
+    /// Time allowed for a whole request, including reading the response body
+    /// The blocking client has no separate read timeout, `None` disables the timeout
+    #[serde(default = "default_timeout")]
+    pub timeout: Option<Duration>,
+    #[serde(default)]
+    pub proxy: Option<ProxyConfig>,
+    /// Extra trusted root certificates in PEM format, each entry may be a bundle
+    #[serde(default)]
+    pub root_certificates_pem: Vec<String>,
+    /// Files with extra trusted root certificates in PEM format, e.g. CA bundles
+    #[serde(default)]
+    pub root_certificate_files: Vec<PathBuf>,
+    /// Trusts only the extra root certificates, pinning the gateway to them
+    #[serde(default)]
+    pub pin_root_certificates: bool,
+    /// `User-Agent` identifying the integration, `DEFAULT_USER_AGENT` if not set
+    #[serde(default)]
+    pub user_agent: Option<String>,
+    /// Idle connections kept open per host
+    #[serde(default)]
+    pub pool_max_idle_per_host: Option<usize>,
+    /// Time an idle connection is kept open
+    #[serde(default)]
+    pub pool_idle_timeout: Option<Duration>,
+}
+
+fn default_connect_timeout() -> Option<Duration> {
+    Some(Duration::from_secs(10))
+}
+
+fn default_timeout() -> Option<Duration> {
+    Some(Duration::from_secs(30))
+}
+
+impl Default for HttpConfig {
+    /// This function will set default value for HttpConfig struct
+    fn default() -> Self {
+        HttpConfig {
+            connect_timeout: default_connect_timeout(),
+            timeout: default_timeout(),
+            proxy: None,
+            root_certificates_pem: Vec::new(),
+            root_certificate_files: Vec::new(),
+            pin_root_certificates: false,
+            user_agent: None,
+            pool_max_idle_per_host: None,
+            pool_idle_timeout: None,
+        }
+    }
+}
+
+impl HttpConfig {
+    /// Builds a blocking http client with these settings
+    /// This function must not be called on an async executor thread
+    pub fn build_client(&self) -> Result<Client, SpError> {
+        let mut builder = Client::builder()
+            .timeout(self.timeout)
+            .user_agent(self.user_agent.clone().unwrap_or_else(|| DEFAULT_USER_AGENT.to_string()));
+        if let Some(connect_timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
+        if let Some(proxy) = &self.proxy {
+            let mut outbound = Proxy::all(proxy.url.as_str())
+                .map_err(|err| SpError::Config(format!("invalid proxy `{}`: {}", proxy.url, err)))?;
+            if let Some(username) = &proxy.username {
+                outbound = outbound.basic_auth(username, proxy.password.as_deref().unwrap_or_default());
+            }
+            builder = builder.proxy(outbound);
+        }
+        for certificate in self.root_certificates()? {
+            builder = builder.add_root_certificate(certificate);
+        }
+        if self.pin_root_certificates {
+            if self.root_certificates_pem.is_empty() && self.root_certificate_files.is_empty() {
+                return Err(SpError::Config("pinning needs at least one root certificate".to_string()));
+            }
+            builder = builder.tls_built_in_root_certs(false);
+        }
+        if let Some(max_idle) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max_idle);
+        }
+        if let Some(idle_timeout) = self.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(idle_timeout);
+        }
+        builder
+            .build()
+            .map_err(|err| SpError::Config(format!("http client: {}", err)))
+    }
+
+    /// Reads the extra root certificates, every certificate of a PEM bundle is trusted
+    /// A PEM text or file without any certificate is a `Config` error
+    pub fn root_certificates(&self) -> Result<Vec<Certificate>, SpError> {
+        let mut pems = self.root_certificates_pem.clone();
+        for file in &self.root_certificate_files {
+            let pem = std::fs::read_to_string(file)
+                .map_err(|err| SpError::Config(format!("cannot read {}: {}", file.display(), err)))?;
+            pems.push(pem);
+        }
+        let mut certificates = Vec::new();
+        for pem in &pems {
+            let bundle = Certificate::from_pem_bundle(pem.as_bytes())
+                .map_err(|err| SpError::Config(format!("invalid root certificate: {}", err)))?;
+            if bundle.is_empty() {
+                return Err(SpError::Config("invalid root certificate: no certificate in PEM".to_string()));
+            }
+            certificates.extend(bundle);
+        }
+        Ok(certificates)
+    }
+}
@@ -0,0 +1,196 @@
+//!
+//! This module makes checkout idempotent by merchant `order_id`.
+//!
+//! A retried checkout for an order that already has a live checkout
+//! returns the existing `checkout_url` and `sp_order_id` instead of
+//! creating a second payment at shurjopay.
+//!
+//! Checkouts are remembered through the plugin's `PaymentStore`;
+//! a `MemoryPaymentStore` is used if no store is set. The plugin and its
+//! clones, e.g. the copies of a `web::SharedPlugin`, share that store and
+//! the orders in flight. A paid order is never checked out again, and while
+//! a checkout of an order is being created a second one is refused.
+//!
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::Utc;
+
+use crate::error::SpError;
+use crate::metrics::{self, Operation};
+use crate::money::Money;
+use crate::payment_store::{MemoryPaymentStore, PaymentRecord, PaymentState, PaymentStore};
+use crate::shurjopay::{ShurjopayPlugin, SpCheckout};
+
+/// Time after which a `CheckoutRequested` record without a response is taken as abandoned
+pub const CHECKOUT_IN_FLIGHT_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Idempotency state of a plugin, shared by its clones and replaced when a store is set
+#[derive(Debug, Default)]
+pub(crate) struct CheckoutGuard {
+    /// Ledger of a plugin without a `PaymentStore`, created by its first idempotent checkout
+    fallback: Mutex<Option<Arc<dyn PaymentStore>>>,
+    /// Orders with a checkout being created
+    in_flight: Mutex<HashSet<String>>,
+}
+
+impl CheckoutGuard {
+    /// Returns the fallback ledger if it was created
+    pub(crate) fn fallback(&self) -> Option<Arc<dyn PaymentStore>> {
+        self.fallback.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    /// Returns the fallback ledger, creating it on first use
+    fn fallback_or_create(&self) -> Arc<dyn PaymentStore> {
+        let mut fallback = self.fallback.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        fallback.get_or_insert_with(|| Arc::new(MemoryPaymentStore::new())).clone()
+    }
+}
+
+/// Reservation of an order while its checkout is created, released on drop
+struct Reservation {
+    guard: Arc<CheckoutGuard>,
+    order_id: String,
+}
+
+impl Reservation {
+    fn acquire(guard: &Arc<CheckoutGuard>, order_id: &str) -> Result<Self, SpError> {
+        let mut in_flight = guard.in_flight.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if !in_flight.insert(order_id.to_string()) {
+            return Err(SpError::CheckoutInFlight(order_id.to_string()));
+        }
+        Ok(Reservation {
+            guard: guard.clone(),
+            order_id: order_id.to_string(),
+        })
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        let mut in_flight = self.guard.in_flight.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        in_flight.remove(&self.order_id);
+    }
+}
+
+/// Result of an idempotent checkout
+/// `replayed` is true if the checkout was returned from an earlier request
+/// This structure implements `Debug`, `Clone` and `PartialEq` functions
+#[derive(Debug, Clone, PartialEq)]
+pub struct IdempotentCheckout {
+    pub checkout_url: String,
+    pub sp_order_id: String,
+    pub replayed: bool,
+}
+
+impl ShurjopayPlugin {
+    /// This function commits secure checkout at most once per merchant `order_id`
+    ///
+    /// A checkout is live from the time shurjopay returns its checkout url until the
+    /// payment is verified as paid, declined or cancelled, or a new checkout for the
+    /// same order fails.
+    /// A repeat request with the same amount and currency returns the live checkout,
+    /// a repeat with a different amount or currency is an `IdempotencyConflict`.
+    /// A paid order is `AlreadyPaid`. While another checkout of the order is being
+    /// created, in this process or recorded in the ledger less than
+    /// `CHECKOUT_IN_FLIGHT_TIMEOUT` ago, the request is `CheckoutInFlight`.
+    pub fn make_payment_idempotent(&mut self, checkout_item: SpCheckout) -> Result<IdempotentCheckout, SpError> {
+        let guard = self.checkout_guard();
+        let store = match self.payment_store() {
+            Some(store) => store,
+            None => guard.fallback_or_create(),
+        };
+
+        let _reservation = Reservation::acquire(&guard, &checkout_item.order_id)?;
+        if let Some(record) = store.find_by_order_id(&checkout_item.order_id)? {
+            if record.state == PaymentState::Paid {
+                return Err(SpError::AlreadyPaid(checkout_item.order_id));
+            }
+            if record.state == PaymentState::CheckoutRequested && !is_abandoned(&record) {
+                return Err(SpError::CheckoutInFlight(checkout_item.order_id));
+            }
+            if let Some(live) = live_checkout(&record) {
+                if !same_amount(&record, &checkout_item) {
+                    return Err(SpError::IdempotencyConflict {
+                        expected: describe_amount(&record.checkout_request),
+                        actual: describe_amount(&checkout_item),
+                        order_id: checkout_item.order_id,
+                    });
+                }
+                self.checkout_response = record.checkout_response.clone();
+                metrics::record_replay(Operation::SecureCheckout);
+                return Ok(live);
+            }
+        }
+
+        match self.make_payment_checkout(checkout_item) {
+            Some(response) => Ok(IdempotentCheckout {
+                checkout_url: response.checkout_url,
+                sp_order_id: response.sp_order_id,
+                replayed: false,
+            }),
+            None => Err(self
+                .last_error
+                .clone()
+                .unwrap_or_else(|| SpError::Http("checkout failed".to_string()))),
+        }
+    }
+}
+
+/// Returns true if a checkout request was recorded too long ago to still be in flight
+fn is_abandoned(record: &PaymentRecord) -> bool {
+    let age = Utc::now().naive_utc() - record.updated_at;
+    age.to_std().map_or(false, |age| age >= CHECKOUT_IN_FLIGHT_TIMEOUT)
+}
+
+/// Returns the live checkout of a ledger record
+/// A checkout verified as `Pending` can still be paid, so it stays live
+fn live_checkout(record: &PaymentRecord) -> Option<IdempotentCheckout> {
+    if !matches!(record.state, PaymentState::CheckoutCreated | PaymentState::Pending) {
+        return None;
+    }
+    Some(IdempotentCheckout {
+        checkout_url: record.checkout_url.clone()?,
+        sp_order_id: record.sp_order_id.clone()?,
+        replayed: true,
+    })
+}
+
+/// Compares amount, discount and currency of a recorded checkout with a repeat request
+/// Amounts are compared as money so `"786"` and `"786.00"` are the same
+fn same_amount(record: &PaymentRecord, checkout_item: &SpCheckout) -> bool {
+    if !record.currency.trim().eq_ignore_ascii_case(checkout_item.currency.trim()) {
+        return false;
+    }
+    same_money(&record.amount, &checkout_item.amount, &record.currency)
+        && same_money(
+            discount(&record.checkout_request),
+            discount(checkout_item),
+            &record.currency,
+        )
+}
+
+fn same_money(recorded: &str, requested: &str, currency: &str) -> bool {
+    match (Money::parse(recorded, currency), Money::parse(requested, currency)) {
+        (Ok(recorded), Ok(requested)) => recorded == requested,
+        _ => recorded.trim() == requested.trim(),
+    }
+}
+
+/// `discount_amount` of a checkout, `"0"` if none was sent
+fn discount(checkout_item: &SpCheckout) -> &str {
+    checkout_item.discount_amount.as_deref().unwrap_or("0")
+}
+
+/// Describes the amount of a checkout for `IdempotencyConflict`
+fn describe_amount(checkout_item: &SpCheckout) -> String {
+    match &checkout_item.discount_amount {
+        Some(discount) => format!(
+            "{} {} less {} discount",
+            checkout_item.amount, checkout_item.currency, discount
+        ),
+        None => format!("{} {}", checkout_item.amount, checkout_item.currency),
+    }
+}
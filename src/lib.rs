@@ -13,10 +13,66 @@
 //! - Handles http request and request errors
 //! - JSON serialization and deserialization
 //! - Authentication during checkout and verification of payments
+//! - Optional ledger of payments through `payment_store`
+//! - Reconciliation of the ledger against shurjopay
+//! - CSV and JSON export of verification results
+//! - Idempotent checkout by merchant order id
+//! - Circuit breaker per gateway end point
+//! - Client side rate limits per gateway end point
+//! - Timeouts, proxy, root certificates and user agent of the http client
+//! - Pluggable transport with record-and-replay cassettes for tests
+//! - Metrics of gateway operations through the `metrics` facade
+//! - Observers of token refreshes, checkouts, verifications and errors
+//! - Panic free decoding of gateway response bodies
+//! - QR codes of checkout urls
+//! - HTML and plain text receipts in English or Bangla (`receipt` feature)
+//! - Shareable single use payment links with expiry
+//! - Recurring billing of subscriptions with dunning
+//! - Itemized carts with discounts, VAT and shipping
+//! - Fixed, percentage and capped discounts and coupons checked after verification
+//! - Unique merchant order ids with the configured prefix
+//! - Validated and masked Bangladeshi mobile numbers for `customer_phone`
+//! - Ready-made axum checkout, return and IPN routes (`axum` feature)
+//! - Ready-made actix-web checkout, return and notification handlers (`actix` feature)
+//! - Gateway operations as `tower::Service`s (`tower` feature)
 //!
 //! 
 pub mod shurjopay;
 pub mod shurjopay_client;
+#[cfg(feature = "actix")]
+pub mod actix_integration;
+#[cfg(feature = "axum")]
+pub mod axum_integration;
+pub mod cart;
+pub mod cassette;
+pub mod circuit_breaker;
+pub mod clock;
+pub mod discount;
+pub mod error;
+pub mod export;
+pub mod http_config;
+pub mod idempotency;
+pub mod metrics;
+pub mod money;
+pub mod observer;
+pub mod order_id;
+pub mod payment_link;
+pub mod payment_store;
+pub mod phone;
+#[cfg(feature = "qr")]
+pub mod qr;
+pub mod rate_limit;
+#[cfg(feature = "receipt")]
+pub mod receipt;
+pub mod reconciliation;
+pub mod response;
+#[cfg(feature = "sqlite")]
+pub mod sqlite_store;
+pub mod subscription;
+#[cfg(feature = "tower")]
+pub mod tower_integration;
+pub mod transport;
+pub mod web;
 
 
 
@@ -0,0 +1,138 @@
+//!
+//! This module records metrics of gateway operations through the `metrics` facade.
+//!
+//! With the `metrics` feature every gateway operation records a request
+//! counter labelled by `operation`, `outcome` and `sp_code`, and a latency
+//! histogram labelled by `operation`. Token refreshes, token cache hits,
+//! retried operations and idempotent checkout replays are counted too. Any `metrics` exporter, e.g. a
+//! Prometheus exporter, can be installed by the application.
+//!
+//! Without the feature these functions do nothing.
+//!
+
+use std::time::Duration;
+
+use crate::error::SpError;
+
+/// Counter of gateway requests, labelled by `operation`, `outcome` and `sp_code`
+pub const REQUESTS_TOTAL: &str = "shurjopay_requests_total";
+/// Histogram of gateway request latency in seconds, labelled by `operation`
+pub const REQUEST_DURATION_SECONDS: &str = "shurjopay_request_duration_seconds";
+/// Counter of retried operations, labelled by `operation`
+pub const RETRIES_TOTAL: &str = "shurjopay_retries_total";
+/// Counter of idempotent checkouts answered with an earlier checkout, labelled by `operation`
+pub const IDEMPOTENT_REPLAYS_TOTAL: &str = "shurjopay_idempotent_replays_total";
+/// Counter of auth token requests, labelled by `reason`: `missing` or `expired`
+pub const TOKEN_REFRESHES_TOTAL: &str = "shurjopay_token_refreshes_total";
+/// Counter of operations served by a cached auth token
+pub const TOKEN_CACHE_HITS_TOTAL: &str = "shurjopay_token_cache_hits_total";
+
+/// `sp_code` label of operations without a shurjopay code
+pub const NO_SP_CODE: &str = "none";
+
+/// Gateway operations with metrics
+/// This enum implements `Debug`, `Clone`, `Copy`, `PartialEq` and `Eq` functions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    GetAuthToken,
+    SecureCheckout,
+    VerifyPayment,
+    PaymentStatus,
+}
+
+impl Operation {
+    /// Value of the `operation` label
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operation::GetAuthToken => "get_auth_token",
+            Operation::SecureCheckout => "secure_ckeckout",
+            Operation::VerifyPayment => "verify_payment_id",
+            Operation::PaymentStatus => "check_payment",
+        }
+    }
+}
+
+/// Value of the `outcome` label for the error of an operation, `success` if there is none
+pub fn outcome(error: Option<&SpError>) -> &'static str {
+    match error {
+        None => "success",
+        Some(SpError::Config(_)) => "config",
+        Some(SpError::Http(_)) => "http",
+        Some(SpError::Auth(_)) => "auth",
+        Some(SpError::Gateway { .. }) | Some(SpError::Decode { .. }) => "gateway",
+        Some(SpError::Store(_)) => "store",
+        Some(SpError::InvalidAmount(_))
+        | Some(SpError::CurrencyMismatch { .. })
+        | Some(SpError::InvalidOrderId(_))
+        | Some(SpError::InvalidPhone(_)) => "invalid_request",
+        Some(SpError::IdempotencyConflict { .. })
+        | Some(SpError::AlreadyPaid(_))
+        | Some(SpError::CheckoutInFlight(_)) => "idempotency_conflict",
+        Some(SpError::Timeout(_)) => "timeout",
+        Some(SpError::Overloaded(_)) => "overloaded",
+        Some(SpError::CircuitOpen { .. }) => "circuit_open",
+        Some(SpError::RateLimited { .. }) => "rate_limited",
+        Some(SpError::LinkNotFound(_)) | Some(SpError::LinkExpired(_)) | Some(SpError::LinkConsumed(_)) => "link_refused",
+        Some(SpError::SubscriptionNotFound(_)) => "subscription_not_found",
+    }
+}
+
+/// Registers units and descriptions of the metrics with the installed recorder
+pub fn describe() {
+    #[cfg(feature = "metrics")]
+    {
+        use ::metrics::{describe_counter, describe_histogram, Unit};
+
+        describe_counter!(REQUESTS_TOTAL, "Gateway requests by operation, outcome and sp_code");
+        describe_histogram!(REQUEST_DURATION_SECONDS, Unit::Seconds, "Latency of gateway operations");
+        describe_counter!(RETRIES_TOTAL, "Retried gateway operations");
+        describe_counter!(IDEMPOTENT_REPLAYS_TOTAL, "Idempotent operations answered with an earlier result");
+        describe_counter!(TOKEN_REFRESHES_TOTAL, "Auth token requests by reason");
+        describe_counter!(TOKEN_CACHE_HITS_TOTAL, "Operations served by a cached auth token");
+    }
+}
+
+/// Records a finished operation
+/// `error` is the error the operation ended with, `sp_code` the code shurjopay returned
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+pub fn record_request(operation: Operation, error: Option<&SpError>, sp_code: Option<String>, elapsed: Duration) {
+    #[cfg(feature = "metrics")]
+    {
+        ::metrics::counter!(
+            REQUESTS_TOTAL,
+            "operation" => operation.as_str(),
+            "outcome" => outcome(error),
+            "sp_code" => sp_code.unwrap_or_else(|| NO_SP_CODE.to_string()),
+        )
+        .increment(1);
+        ::metrics::histogram!(REQUEST_DURATION_SECONDS, "operation" => operation.as_str()).record(elapsed.as_secs_f64());
+    }
+}
+
+/// Records a retry of an operation
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+pub fn record_retry(operation: Operation) {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(RETRIES_TOTAL, "operation" => operation.as_str()).increment(1);
+}
+
+/// Records an idempotent operation answered with an earlier result instead of a gateway call
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+pub fn record_replay(operation: Operation) {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(IDEMPOTENT_REPLAYS_TOTAL, "operation" => operation.as_str()).increment(1);
+}
+
+/// Records a request for a new auth token
+/// `reason` is `missing` if no token was cached or `expired` if the cached token expired
+#[cfg_attr(not(feature = "metrics"), allow(unused_variables))]
+pub fn record_token_refresh(reason: &'static str) {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(TOKEN_REFRESHES_TOTAL, "reason" => reason).increment(1);
+}
+
+/// Records an operation served by the cached auth token
+pub fn record_token_cache_hit() {
+    #[cfg(feature = "metrics")]
+    ::metrics::counter!(TOKEN_CACHE_HITS_TOTAL).increment(1);
+}
@@ -0,0 +1,159 @@
+//!
+//! This module holds the `Money` type used to compare and compute amounts exactly.
+//!
+//! Amounts are kept as integer minor units (poisha for BDT) so that
+//! totals and comparisons never suffer from floating point rounding.
+//!
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::SpError;
+
+/// An amount of money in a currency
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone`, `PartialEq` and `Hash` functions
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Money {
+    minor: i64,
+    currency: String,
+}
+
+impl Money {
+    /// Number of minor units in one major unit
+    pub const MINOR_PER_MAJOR: i64 = 100;
+
+    /// Creates `Money` from minor units (e.g. `78650` poisha is `786.50` BDT)
+    /// The currency code is stored in upper case
+    pub fn from_minor(minor: i64, currency: &str) -> Self {
+        Money {
+            minor,
+            currency: currency.trim().to_uppercase(),
+        }
+    }
+
+    /// Parses a decimal amount such as `"786"` or `"786.50"`
+    /// More than two decimal places is an error
+    pub fn parse(amount: &str, currency: &str) -> Result<Self, SpError> {
+        let invalid = || SpError::InvalidAmount(amount.to_string());
+        let text = amount.trim();
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, text),
+        };
+        let (major, fraction) = match digits.split_once('.') {
+            Some((major, fraction)) => (major, fraction),
+            None => (digits, ""),
+        };
+        if major.is_empty() && fraction.is_empty()
+            || fraction.len() > 2
+            || !major.chars().chain(fraction.chars()).all(|ch| ch.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        let major: i64 = if major.is_empty() { 0 } else { major.parse().map_err(|_| invalid())? };
+        let fraction: i64 = format!("{:0<2}", fraction).parse().map_err(|_| invalid())?;
+        let minor = major
+            .checked_mul(Self::MINOR_PER_MAJOR)
+            .and_then(|minor| minor.checked_add(fraction))
+            .ok_or_else(invalid)?;
+        Ok(Money::from_minor(if negative { -minor } else { minor }, currency))
+    }
+
+    /// Converts a gateway reported amount such as `payable_amount` to `Money`
+    /// The amount is rounded to the nearest minor unit
+    pub fn from_f64(amount: f64, currency: &str) -> Result<Self, SpError> {
+        let minor = (amount * Self::MINOR_PER_MAJOR as f64).round();
+        if !minor.is_finite() || minor.abs() > i64::MAX as f64 {
+            return Err(SpError::InvalidAmount(amount.to_string()));
+        }
+        Ok(Money::from_minor(minor as i64, currency))
+    }
+
+    /// Converts a gateway reported amount string such as `received_amount` to `Money`
+    /// Unlike `parse` any number of decimal places is accepted and rounded
+    pub fn from_gateway_str(amount: &str, currency: &str) -> Result<Self, SpError> {
+        let value: f64 = amount
+            .trim()
+            .parse()
+            .map_err(|_| SpError::InvalidAmount(amount.to_string()))?;
+        Money::from_f64(value, currency)
+    }
+
+    /// Zero amount in `currency`
+    pub fn zero(currency: &str) -> Self {
+        Money::from_minor(0, currency)
+    }
+
+    /// Amount in minor units
+    pub fn minor(&self) -> i64 {
+        self.minor
+    }
+
+    /// Upper case currency code
+    pub fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    /// Amount formatted with two decimal places, as sent in `SpCheckout.amount`
+    pub fn amount_string(&self) -> String {
+        let sign = if self.minor < 0 { "-" } else { "" };
+        let minor = self.minor.unsigned_abs();
+        let per_major = Self::MINOR_PER_MAJOR as u64;
+        format!("{}{}.{:02}", sign, minor / per_major, minor % per_major)
+    }
+
+    /// Adds two amounts of the same currency
+    pub fn checked_add(&self, other: &Money) -> Result<Money, SpError> {
+        self.same_currency(other)?;
+        let minor = self
+            .minor
+            .checked_add(other.minor)
+            .ok_or_else(|| SpError::InvalidAmount(format!("{} + {}", self, other)))?;
+        Ok(Money::from_minor(minor, &self.currency))
+    }
+
+    /// Subtracts an amount of the same currency
+    pub fn checked_sub(&self, other: &Money) -> Result<Money, SpError> {
+        self.same_currency(other)?;
+        let minor = self
+            .minor
+            .checked_sub(other.minor)
+            .ok_or_else(|| SpError::InvalidAmount(format!("{} - {}", self, other)))?;
+        Ok(Money::from_minor(minor, &self.currency))
+    }
+
+    /// Multiplies the amount by a whole number, e.g. a unit price by a quantity
+    pub fn checked_mul(&self, factor: i64) -> Result<Money, SpError> {
+        let minor = self
+            .minor
+            .checked_mul(factor)
+            .ok_or_else(|| SpError::InvalidAmount(format!("{} x {}", self, factor)))?;
+        Ok(Money::from_minor(minor, &self.currency))
+    }
+
+    /// Share of the amount in basis points (`1500` is 15%), rounded half away from zero
+    pub fn basis_points(&self, basis_points: u32) -> Result<Money, SpError> {
+        let product = i128::from(self.minor) * i128::from(basis_points);
+        let rounded = (product.abs() + 5_000) / 10_000 * product.signum();
+        let minor = i64::try_from(rounded)
+            .map_err(|_| SpError::InvalidAmount(format!("{} x {}bp", self, basis_points)))?;
+        Ok(Money::from_minor(minor, &self.currency))
+    }
+
+    fn same_currency(&self, other: &Money) -> Result<(), SpError> {
+        if self.currency != other.currency {
+            return Err(SpError::CurrencyMismatch {
+                expected: self.currency.clone(),
+                actual: other.currency.clone(),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.amount_string(), self.currency)
+    }
+}
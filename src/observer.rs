@@ -0,0 +1,88 @@
+//!
+//! This module notifies observers of the payment lifecycle.
+//!
+//! Observers registered with `ShurjopayPlugin::add_observer` are called
+//! synchronously, in registration order, when an auth token is fetched,
+//! a checkout is created, a verification or payment status is received
+//! or a gateway operation fails. `ChannelObserver` forwards the same
+//! notifications as `PaymentEvent`s over a channel for handling on
+//! another thread.
+//!
+
+use std::fmt;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+
+use crate::error::SpError;
+use crate::shurjopay::{SpAuthToken, SpCheckoutResponse, SpVerifyResponse};
+
+/// Receives payment lifecycle notifications
+/// Every callback does nothing by default
+pub trait PaymentObserver: fmt::Debug + Send + Sync {
+    /// A new auth token was received
+    fn on_token_refreshed(&self, _token: &SpAuthToken) {}
+
+    /// Shurjopay created a checkout
+    fn on_checkout_created(&self, _response: &SpCheckoutResponse) {}
+
+    /// A verification or payment status was received, `sp_code` tells whether the payment succeeded
+    fn on_verified(&self, _response: &SpVerifyResponse) {}
+
+    /// A gateway operation failed
+    fn on_error(&self, _error: &SpError) {}
+}
+
+/// A payment lifecycle notification sent by `ChannelObserver`
+/// This enum implements `Debug` and `Clone` functions
+#[derive(Debug, Clone)]
+pub enum PaymentEvent {
+    TokenRefreshed(SpAuthToken),
+    CheckoutCreated(Box<SpCheckoutResponse>),
+    Verified(Box<SpVerifyResponse>),
+    Error(SpError),
+}
+
+/// Observer sending every notification as a `PaymentEvent` over a channel
+/// Events are dropped once the receiver is gone
+#[derive(Debug)]
+pub struct ChannelObserver {
+    sender: Mutex<Sender<PaymentEvent>>,
+}
+
+impl ChannelObserver {
+    /// Creates an observer and the receiver of its events
+    pub fn new() -> (Self, Receiver<PaymentEvent>) {
+        let (sender, receiver) = mpsc::channel();
+        (Self::from_sender(sender), receiver)
+    }
+
+    /// Creates an observer sending to an existing channel
+    pub fn from_sender(sender: Sender<PaymentEvent>) -> Self {
+        ChannelObserver {
+            sender: Mutex::new(sender),
+        }
+    }
+
+    fn send(&self, event: PaymentEvent) {
+        let sender = self.sender.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let _ = sender.send(event);
+    }
+}
+
+impl PaymentObserver for ChannelObserver {
+    fn on_token_refreshed(&self, token: &SpAuthToken) {
+        self.send(PaymentEvent::TokenRefreshed(token.clone()));
+    }
+
+    fn on_checkout_created(&self, response: &SpCheckoutResponse) {
+        self.send(PaymentEvent::CheckoutCreated(Box::new(response.clone())));
+    }
+
+    fn on_verified(&self, response: &SpVerifyResponse) {
+        self.send(PaymentEvent::Verified(Box::new(response.clone())));
+    }
+
+    fn on_error(&self, error: &SpError) {
+        self.send(PaymentEvent::Error(error.clone()));
+    }
+}
@@ -0,0 +1,127 @@
+//!
+//! This module generates merchant order ids.
+//!
+//! An order id is the merchant `prefix` followed by a ULID, e.g.
+//! `sp01HGW2N7EHJVGF1Q3N6ZJ8M4XT`. ULIDs sort by creation time and are
+//! unique across services, so ids never collide and can be parsed back
+//! into prefix and timestamp for debugging.
+//!
+
+use std::sync::Mutex;
+
+use chrono::{DateTime, Utc};
+use ulid::{Generator, Ulid};
+
+use crate::error::SpError;
+use crate::shurjopay::SpConfig;
+
+/// Longest merchant prefix shurjopay accepts
+pub const MAX_PREFIX_LEN: usize = 5;
+
+/// Longest order id the generator accepts
+pub const MAX_ORDER_ID_LEN: usize = 32;
+
+/// Length of the ULID suffix of a generated order id
+pub const ULID_LEN: usize = 26;
+
+/// Process wide generator so ids are ordered even within the same millisecond
+static GENERATOR: Mutex<Generator> = Mutex::new(Generator::new());
+
+/// A generated order id split back into its parts
+/// This structure implements `Debug`, `Clone` and `PartialEq` functions
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedOrderId {
+    pub prefix: String,
+    pub ulid: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Generator of merchant order ids with a fixed prefix
+/// This structure implements `Debug` and `Clone` functions
+#[derive(Debug, Clone)]
+pub struct OrderIdGenerator {
+    prefix: String,
+}
+
+impl OrderIdGenerator {
+    /// Creates a generator after validating `prefix`
+    pub fn new(prefix: &str) -> Result<Self, SpError> {
+        validate_prefix(prefix)?;
+        Ok(OrderIdGenerator {
+            prefix: prefix.to_string(),
+        })
+    }
+
+    /// Creates a generator with the merchant prefix of `config`
+    pub fn from_config(config: &SpConfig) -> Result<Self, SpError> {
+        Self::new(&config.prefix)
+    }
+
+    /// Merchant prefix of the generated ids
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
+    /// Generates a new order id
+    pub fn generate(&self) -> String {
+        let ulid = match GENERATOR.lock() {
+            Ok(mut generator) => generator.generate().unwrap_or_else(|_| Ulid::new()),
+            Err(_) => Ulid::new(),
+        };
+        format!("{}{}", self.prefix, ulid)
+    }
+}
+
+/// Checks a merchant prefix: 1 to `MAX_PREFIX_LEN` ASCII letters or digits
+pub fn validate_prefix(prefix: &str) -> Result<(), SpError> {
+    if prefix.is_empty() || prefix.len() > MAX_PREFIX_LEN {
+        return Err(SpError::InvalidOrderId(format!(
+            "prefix `{}` must be 1 to {} characters",
+            prefix, MAX_PREFIX_LEN
+        )));
+    }
+    if !prefix.chars().all(|ch| ch.is_ascii_alphanumeric()) {
+        return Err(SpError::InvalidOrderId(format!(
+            "prefix `{}` may only contain letters and digits",
+            prefix
+        )));
+    }
+    Ok(())
+}
+
+/// Checks an order id: 1 to `MAX_ORDER_ID_LEN` ASCII letters, digits, `-` or `_`
+pub fn validate_order_id(order_id: &str) -> Result<(), SpError> {
+    if order_id.is_empty() || order_id.len() > MAX_ORDER_ID_LEN {
+        return Err(SpError::InvalidOrderId(format!(
+            "order id `{}` must be 1 to {} characters",
+            order_id, MAX_ORDER_ID_LEN
+        )));
+    }
+    if let Some(ch) = order_id.chars().find(|ch| !(ch.is_ascii_alphanumeric() || *ch == '-' || *ch == '_')) {
+        return Err(SpError::InvalidOrderId(format!(
+            "order id `{}` contains `{}`, only letters, digits, `-` and `_` are allowed",
+            order_id, ch
+        )));
+    }
+    Ok(())
+}
+
+/// Splits an order id made by `OrderIdGenerator` into prefix and creation time
+pub fn parse_order_id(order_id: &str) -> Result<ParsedOrderId, SpError> {
+    validate_order_id(order_id)?;
+    if order_id.len() <= ULID_LEN {
+        return Err(SpError::InvalidOrderId(format!(
+            "order id `{}` is too short to be generated",
+            order_id
+        )));
+    }
+    let (prefix, suffix) = order_id.split_at(order_id.len() - ULID_LEN);
+    validate_prefix(prefix)?;
+    let ulid = Ulid::from_string(suffix)
+        .map_err(|e| SpError::InvalidOrderId(format!("order id `{}`: {}", order_id, e)))?;
+    Ok(ParsedOrderId {
+        prefix: prefix.to_string(),
+        ulid: suffix.to_string(),
+        created_at: DateTime::<Utc>::from(ulid.datetime()),
+    })
+}
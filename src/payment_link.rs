@@ -0,0 +1,330 @@
+//!
+//! This module creates shareable payment links.
+//!
+//! A payment link fixes the amount, customer and merchant `order_id` of a
+//! payment and can be sent to the customer by SMS, email or chat. Opening
+//! the link starts a fresh `secure_ckeckout`, so an abandoned checkout url
+//! never blocks the customer from trying again. A link is refused after it
+//! expires and after its payment is verified as paid.
+//!
+//! Features:
+//! - `PaymentLinkStore` trait to plug in any storage backend
+//! - `MemoryPaymentLinkStore` for tests and short lived processes
+//! - `PaymentLinks` observes verifications to consume paid links
+//!
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use ulid::Ulid;
+
+use crate::clock::{Clock, SystemClock};
+use crate::error::SpError;
+use crate::money::Money;
+use crate::observer::PaymentObserver;
+use crate::payment_store::PaymentState;
+use crate::shurjopay::{ShurjopayPlugin, SpCheckoutResponse, SpVerifyResponse};
+use crate::web::{self, CheckoutRequest};
+
+/// State of a payment link
+/// This enum implements `Serialize`, `Deserialize`, `Debug`, `Clone`, `Copy`, `PartialEq` and `Eq` functions
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkState {
+    /// The link can be opened to pay
+    Open,
+    /// The payment of the link is verified as paid
+    Consumed,
+}
+
+/// A shareable payment link
+/// `request` always carries the `order_id` fixed when the link was created
+/// This structure implements `Serialize`, `Deserialize`, `Debug` and `Clone` functions
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PaymentLink {
+    pub id: String,
+    pub order_id: String,
+    pub request: CheckoutRequest,
+    pub state: LinkState,
+    /// `sp_order_id` of the latest checkout started from the link
+    pub sp_order_id: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub expires_at: NaiveDateTime,
+    pub consumed_at: Option<NaiveDateTime>,
+}
+
+impl PaymentLink {
+    /// Returns true if the link expired at `now`
+    pub fn is_expired_at(&self, now: NaiveDateTime) -> bool {
+        now >= self.expires_at
+    }
+
+    /// Returns true if the link has expired
+    pub fn is_expired(&self) -> bool {
+        self.is_expired_at(SystemClock.now())
+    }
+}
+
+/// Storage backend of payment links
+pub trait PaymentLinkStore: fmt::Debug + Send + Sync {
+    /// Inserts or replaces the link with the same `id`
+    fn save_link(&self, link: &PaymentLink) -> Result<(), SpError>;
+
+    /// Inserts a new link unless its order id already has one, checked and saved atomically
+    /// returns `false` and saves nothing if the order id has a link
+    fn insert_link(&self, link: &PaymentLink) -> Result<bool, SpError>;
+
+    /// Looks up a link by its id
+    fn find_link(&self, id: &str) -> Result<Option<PaymentLink>, SpError>;
+
+    /// Looks up a link by merchant order id
+    /// `PaymentLinks` creates at most one link per order id
+    fn find_link_by_order_id(&self, order_id: &str) -> Result<Option<PaymentLink>, SpError>;
+
+    /// Looks up a link by the `sp_order_id` of its latest checkout
+    fn find_link_by_sp_order_id(&self, sp_order_id: &str) -> Result<Option<PaymentLink>, SpError>;
+}
+
+/// In memory `PaymentLinkStore`
+/// Links are lost when the process exits
+#[derive(Debug, Default)]
+pub struct MemoryPaymentLinkStore {
+    links: Mutex<HashMap<String, PaymentLink>>,
+}
+
+impl MemoryPaymentLinkStore {
+    /// This is a constructor to initiate an empty `MemoryPaymentLinkStore`
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl PaymentLinkStore for MemoryPaymentLinkStore {
+    fn save_link(&self, link: &PaymentLink) -> Result<(), SpError> {
+        let mut links = self.links.lock().map_err(|e| SpError::Store(e.to_string()))?;
+        links.insert(link.id.clone(), link.clone());
+        Ok(())
+    }
+
+    fn insert_link(&self, link: &PaymentLink) -> Result<bool, SpError> {
+        let mut links = self.links.lock().map_err(|e| SpError::Store(e.to_string()))?;
+        if links.values().any(|existing| existing.order_id == link.order_id) {
+            return Ok(false);
+        }
+        links.insert(link.id.clone(), link.clone());
+        Ok(true)
+    }
+
+    fn find_link(&self, id: &str) -> Result<Option<PaymentLink>, SpError> {
+        let links = self.links.lock().map_err(|e| SpError::Store(e.to_string()))?;
+        Ok(links.get(id).cloned())
+    }
+
+    fn find_link_by_order_id(&self, order_id: &str) -> Result<Option<PaymentLink>, SpError> {
+        let links = self.links.lock().map_err(|e| SpError::Store(e.to_string()))?;
+        Ok(links.values().find(|link| link.order_id == order_id).cloned())
+    }
+
+    fn find_link_by_sp_order_id(&self, sp_order_id: &str) -> Result<Option<PaymentLink>, SpError> {
+        let links = self.links.lock().map_err(|e| SpError::Store(e.to_string()))?;
+        Ok(links
+            .values()
+            .find(|link| link.sp_order_id.as_deref() == Some(sp_order_id))
+            .cloned())
+    }
+}
+
+/// Creates, resolves and consumes payment links
+///
+/// Add it to the plugin with `add_observer` so a verified payment consumes its link.
+/// This structure implements `Debug` and `Clone` functions
+#[derive(Debug, Clone)]
+pub struct PaymentLinks {
+    store: Arc<dyn PaymentLinkStore>,
+    clock: Arc<dyn Clock>,
+}
+
+impl PaymentLinks {
+    /// This is a constructor to manage the links of `store` on the system clock
+    pub fn new(store: Arc<dyn PaymentLinkStore>) -> Self {
+        PaymentLinks {
+            store,
+            clock: Arc::new(SystemClock),
+        }
+    }
+
+    /// Sets the clock expiry is checked against
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// This function creates an open link for `request` that expires at `expires_at` (UTC)
+    /// A missing `order_id` is generated with the configured prefix, an `order_id`
+    /// that already has a link is `InvalidOrderId`. The request is checked with
+    /// `web::validate_request`, so a link is never shared that cannot be paid.
+    pub fn create(
+        &self,
+        sp_instance: &ShurjopayPlugin,
+        mut request: CheckoutRequest,
+        expires_at: NaiveDateTime,
+    ) -> Result<PaymentLink, SpError> {
+        let order_id = match request.order_id.clone() {
+            Some(order_id) => order_id,
+            None => sp_instance.generate_order_id()?,
+        };
+        request.order_id = Some(order_id.clone());
+        web::validate_request(&request)?;
+        let link = PaymentLink {
+            id: Ulid::new().to_string(),
+            order_id,
+            request,
+            state: LinkState::Open,
+            sp_order_id: None,
+            created_at: self.clock.now(),
+            expires_at,
+            consumed_at: None,
+        };
+        if !self.store.insert_link(&link)? {
+            let existing = self.store.find_link_by_order_id(&link.order_id)?.map(|existing| existing.id);
+            return Err(SpError::InvalidOrderId(format!(
+                "order `{}` already has payment link {}",
+                link.order_id,
+                existing.unwrap_or_default()
+            )));
+        }
+        Ok(link)
+    }
+
+    /// This function gives an open link a new expiry, e.g. to reopen the expired link of an
+    /// unpaid order, the link keeps its id and order id
+    /// A consumed link is `LinkConsumed`
+    pub fn reissue(&self, id: &str, expires_at: NaiveDateTime) -> Result<PaymentLink, SpError> {
+        let mut link = self.get(id)?;
+        if link.state == LinkState::Consumed {
+            return Err(SpError::LinkConsumed(link.id));
+        }
+        link.expires_at = expires_at;
+        self.store.save_link(&link)?;
+        Ok(link)
+    }
+
+    /// Looks up the link of a merchant order id
+    pub fn find_by_order_id(&self, order_id: &str) -> Result<Option<PaymentLink>, SpError> {
+        self.store.find_link_by_order_id(order_id)
+    }
+
+    /// Looks up a link by its id
+    pub fn get(&self, id: &str) -> Result<PaymentLink, SpError> {
+        self.store
+            .find_link(id)?
+            .ok_or_else(|| SpError::LinkNotFound(id.to_string()))
+    }
+
+    /// This function opens a link and starts a fresh checkout of its payment
+    ///
+    /// Consumed links are `LinkConsumed` and expired links `LinkExpired`. An order the
+    /// plugin's `PaymentStore` already records as paid consumes the link and is refused too.
+    pub fn resolve(&self, sp_instance: &mut ShurjopayPlugin, id: &str) -> Result<SpCheckoutResponse, SpError> {
+        let mut link = self.get(id)?;
+        if link.state == LinkState::Consumed {
+            return Err(SpError::LinkConsumed(link.id));
+        }
+        if let Some(store) = sp_instance.payment_store() {
+            if let Some(record) = store.find_by_order_id(&link.order_id)? {
+                if record.state == PaymentState::Paid {
+                    self.consume_link(&mut link)?;
+                    return Err(SpError::LinkConsumed(link.id));
+                }
+            }
+        }
+        if link.is_expired_at(self.clock.now()) {
+            return Err(SpError::LinkExpired(link.id));
+        }
+
+        let checkout = web::checkout_item(sp_instance, link.request.clone())?;
+        let response = sp_instance.make_payment_checkout(checkout).ok_or_else(|| {
+            sp_instance
+                .last_error
+                .clone()
+                .unwrap_or_else(|| SpError::Http("checkout failed".to_string()))
+        })?;
+        link.sp_order_id = Some(response.sp_order_id.clone());
+        self.store.save_link(&link)?;
+        Ok(response)
+    }
+
+    /// This function consumes the link of a verified payment
+    /// Returns the link if the verification reports it paid
+    ///
+    /// The link is matched on the `sp_order_id` of its latest checkout, then on the
+    /// merchant order id. A payment that does not match the amount and currency of
+    /// the link is `InvalidAmount` or `CurrencyMismatch` and leaves the link open.
+    pub fn consume(&self, response: &SpVerifyResponse) -> Result<Option<PaymentLink>, SpError> {
+        if PaymentState::from_sp_code(response.sp_code) != PaymentState::Paid {
+            return Ok(None);
+        }
+        let mut link = match &response.order_id {
+            Some(sp_order_id) => self.store.find_link_by_sp_order_id(sp_order_id)?,
+            None => None,
+        };
+        if link.is_none() {
+            if let Some(order_id) = &response.customer_order_id {
+                link = self.store.find_link_by_order_id(order_id)?;
+            }
+        }
+        match link {
+            Some(mut link) => {
+                check_link_amount(&link, response)?;
+                self.consume_link(&mut link)?;
+                Ok(Some(link))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn consume_link(&self, link: &mut PaymentLink) -> Result<(), SpError> {
+        if link.state == LinkState::Consumed {
+            return Ok(());
+        }
+        link.state = LinkState::Consumed;
+        link.consumed_at = Some(self.clock.now());
+        self.store.save_link(link)
+    }
+}
+
+/// Checks that a verified payment paid the amount and currency of its link
+fn check_link_amount(link: &PaymentLink, response: &SpVerifyResponse) -> Result<(), SpError> {
+    let expected = link.request.currency.as_deref().unwrap_or("BDT");
+    let currency = response.currency.as_deref().unwrap_or_default().trim();
+    if !currency.eq_ignore_ascii_case(expected) {
+        return Err(SpError::CurrencyMismatch {
+            expected: expected.to_string(),
+            actual: currency.to_string(),
+        });
+    }
+    let amount = Money::parse(&link.request.amount, expected)?;
+    let paid = response
+        .payable_amount
+        .map(|payable| Money::from_f64(payable, expected))
+        .transpose()?;
+    if paid.as_ref() != Some(&amount) {
+        return Err(SpError::InvalidAmount(format!(
+            "{} paid {} instead of {}",
+            link.order_id,
+            paid.map_or_else(|| "null".to_string(), |paid| paid.to_string()),
+            amount
+        )));
+    }
+    Ok(())
+}
+
+impl PaymentObserver for PaymentLinks {
+    fn on_verified(&self, response: &SpVerifyResponse) {
+        if let Err(err) = self.consume(response) {
+            eprintln!("{}", err);
+        }
+    }
+}
@@ -0,0 +1,373 @@
+//!
+//! This module keeps a persistent ledger of payments made through the plugin.
+//!
+//! Every checkout request, checkout response and verification result is
+//! recorded against the merchant's `order_id`, so the link between the
+//! merchant order, the gateway's `sp_order_id` and the checkout url
+//! survives a restart of the application.
+//!
+//! Features:
+//! - `PaymentStore` trait to plug in any storage backend
+//! - `MemoryPaymentStore` for tests and short lived processes
+//! - `SqlitePaymentStore` behind the `sqlite` feature
+//!
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+use chrono::{NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::SpError;
+use crate::shurjopay::{SpCheckout, SpCheckoutResponse, SpVerifyResponse};
+
+/// State of a payment in the local ledger
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone`, `Copy` and `PartialEq` functions
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaymentState {
+    /// Checkout request is sent to shurjopay
+    CheckoutRequested,
+    /// Shurjopay accepted the checkout and returned a checkout url
+    CheckoutCreated,
+    /// Shurjopay did not return a checkout url
+    CheckoutFailed,
+    /// Verification reported a successful payment (`sp_code` 1000)
+    Paid,
+    /// Verification reported the payment was declined (`sp_code` 1001)
+    Declined,
+    /// Verification reported the payment was cancelled (`sp_code` 1002)
+    Cancelled,
+    /// Verification returned any other `sp_code`
+    Pending,
+}
+
+impl PaymentState {
+    /// Maps the `sp_code` of a verification response to a `PaymentState`
+    pub fn from_sp_code(sp_code: Option<i64>) -> Self {
+        match sp_code {
+            Some(1000) => PaymentState::Paid,
+            Some(1001) => PaymentState::Declined,
+            Some(1002) => PaymentState::Cancelled,
+            _ => PaymentState::Pending,
+        }
+    }
+
+    /// Returns the name used to persist the state
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PaymentState::CheckoutRequested => "checkout_requested",
+            PaymentState::CheckoutCreated => "checkout_created",
+            PaymentState::CheckoutFailed => "checkout_failed",
+            PaymentState::Paid => "paid",
+            PaymentState::Declined => "declined",
+            PaymentState::Cancelled => "cancelled",
+            PaymentState::Pending => "pending",
+        }
+    }
+
+    /// Parses a persisted state name back into a `PaymentState`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "checkout_requested" => Some(PaymentState::CheckoutRequested),
+            "checkout_created" => Some(PaymentState::CheckoutCreated),
+            "checkout_failed" => Some(PaymentState::CheckoutFailed),
+            "paid" => Some(PaymentState::Paid),
+            "declined" => Some(PaymentState::Declined),
+            "cancelled" => Some(PaymentState::Cancelled),
+            "pending" => Some(PaymentState::Pending),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for PaymentState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A payment as recorded in the local ledger
+/// `checkout_request` is stored without the auth token
+/// This structure implements `Serialize`, `Deserialize`, `Debug` and `Clone` functions
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PaymentRecord {
+    pub order_id: String,
+    pub sp_order_id: Option<String>,
+    pub checkout_url: Option<String>,
+    pub amount: String,
+    pub currency: String,
+    pub state: PaymentState,
+    pub checkout_request: SpCheckout,
+    pub checkout_response: Option<SpCheckoutResponse>,
+    pub verify_response: Option<SpVerifyResponse>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+/// A change of `PaymentState` of a single payment
+/// `from` is `None` for the first state of a payment
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone` and `PartialEq` functions
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StateTransition {
+    pub order_id: String,
+    pub from: Option<PaymentState>,
+    pub to: PaymentState,
+    pub at: NaiveDateTime,
+}
+
+/// Storage backend of the payment ledger
+///
+/// Implementors only provide the storage primitives, the `record_*` functions
+/// build the records and state transitions on top of them.
+pub trait PaymentStore: fmt::Debug + Send + Sync {
+    /// Inserts or replaces the record with the same `order_id`
+    fn save_record(&self, record: &PaymentRecord) -> Result<(), SpError>;
+
+    /// Appends a state transition to the history of a payment
+    fn append_transition(&self, transition: &StateTransition) -> Result<(), SpError>;
+
+    /// Saves a record and appends its state transition, if any, atomically
+    /// Either both are stored or neither is
+    fn save_with_transition(&self, record: &PaymentRecord, transition: Option<&StateTransition>) -> Result<(), SpError>;
+
+    /// Saves a record and its state transition like `save_with_transition`, but only if the
+    /// stored record is still in state `expected`, checked and saved atomically
+    /// returns `false` and saves nothing if another writer changed the state first
+    fn save_if_state(
+        &self,
+        record: &PaymentRecord,
+        expected: PaymentState,
+        transition: Option<&StateTransition>,
+    ) -> Result<bool, SpError>;
+
+    /// Looks up a payment by merchant order id
+    fn find_by_order_id(&self, order_id: &str) -> Result<Option<PaymentRecord>, SpError>;
+
+    /// Looks up a payment by the order id shurjopay assigned at checkout
+    fn find_by_sp_order_id(&self, sp_order_id: &str) -> Result<Option<PaymentRecord>, SpError>;
+
+    /// Returns the state transitions of a payment, oldest first
+    fn transitions(&self, order_id: &str) -> Result<Vec<StateTransition>, SpError>;
+
+    /// Records a checkout request before it is sent to shurjopay
+    /// A paid order is kept as it is and refused with `SpError::AlreadyPaid`
+    fn record_checkout_request(&self, checkout: &SpCheckout) -> Result<PaymentRecord, SpError> {
+        let now = now();
+        let request = SpCheckout {
+            token: String::new(),
+            ..checkout.clone()
+        };
+        let existing = self.find_by_order_id(&checkout.order_id)?;
+        if existing.as_ref().map(|record| record.state) == Some(PaymentState::Paid) {
+            return Err(SpError::AlreadyPaid(checkout.order_id.clone()));
+        }
+        let from = existing.as_ref().map(|record| record.state);
+        let record = match existing {
+            Some(existing) => PaymentRecord {
+                amount: request.amount.clone(),
+                currency: request.currency.clone(),
+                checkout_request: request,
+                updated_at: now,
+                ..existing
+            },
+            None => PaymentRecord {
+                order_id: request.order_id.clone(),
+                sp_order_id: None,
+                checkout_url: None,
+                amount: request.amount.clone(),
+                currency: request.currency.clone(),
+                state: PaymentState::CheckoutRequested,
+                checkout_request: request,
+                checkout_response: None,
+                verify_response: None,
+                created_at: now,
+                updated_at: now,
+            },
+        };
+        change_state(self, record, from, PaymentState::CheckoutRequested)
+    }
+
+    /// Records the checkout response of an order
+    /// `None` records a checkout that did not return a checkout url
+    fn record_checkout_response(
+        &self,
+        order_id: &str,
+        response: Option<&SpCheckoutResponse>,
+    ) -> Result<Option<PaymentRecord>, SpError> {
+        let record = match self.find_by_order_id(order_id)? {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+        let state = match response {
+            Some(_) => PaymentState::CheckoutCreated,
+            None => PaymentState::CheckoutFailed,
+        };
+        let record = PaymentRecord {
+            sp_order_id: response.map(|res| res.sp_order_id.clone()).or(record.sp_order_id),
+            checkout_url: response.map(|res| res.checkout_url.clone()).or(record.checkout_url),
+            checkout_response: response.cloned().or(record.checkout_response),
+            updated_at: now(),
+            ..record
+        };
+        let from = Some(record.state);
+        change_state(self, record, from, state).map(Some)
+    }
+
+    /// Records a verification result
+    /// The payment is matched by `sp_order_id` first and then by merchant order id
+    /// A paid payment stays paid, later responses only replace `verify_response`
+    /// returns `None` if the payment is not in the ledger
+    fn record_verification(&self, response: &SpVerifyResponse) -> Result<Option<PaymentRecord>, SpError> {
+        Ok(self.apply_verification(response)?.map(|(record, _)| record))
+    }
+
+    /// Records a verification result like `record_verification` and returns the state
+    /// transition it made, `None` if the state did not change
+    /// Concurrent verifications of a payment are applied one after the other, so only
+    /// one of them reports the transition to `Paid`
+    fn apply_verification(
+        &self,
+        response: &SpVerifyResponse,
+    ) -> Result<Option<(PaymentRecord, Option<StateTransition>)>, SpError> {
+        loop {
+            let mut found = None;
+            if let Some(sp_order_id) = &response.order_id {
+                found = self.find_by_sp_order_id(sp_order_id)?;
+            }
+            if found.is_none() {
+                if let Some(order_id) = &response.customer_order_id {
+                    found = self.find_by_order_id(order_id)?;
+                }
+            }
+            let record = match found {
+                Some(record) => record,
+                None => return Ok(None),
+            };
+            let from = record.state;
+            let to = match from {
+                PaymentState::Paid => PaymentState::Paid,
+                _ => PaymentState::from_sp_code(response.sp_code),
+            };
+            let record = PaymentRecord {
+                // A record found by merchant order id is found by `sp_order_id` next time
+                sp_order_id: record.sp_order_id.or_else(|| response.order_id.clone()),
+                verify_response: Some(response.clone()),
+                state: to,
+                updated_at: now(),
+                ..record
+            };
+            let transition = Some(StateTransition {
+                order_id: record.order_id.clone(),
+                from: Some(from),
+                to,
+                at: record.updated_at,
+            })
+            .filter(|_| from != to);
+            // Another verification changed the payment in between, it is read again
+            if self.save_if_state(&record, from, transition.as_ref())? {
+                return Ok(Some((record, transition)));
+            }
+        }
+    }
+}
+
+/// Saves `record` in state `to` together with a transition if the state changed
+/// `from` is `None` for a record that is not in the ledger yet
+fn change_state<S: PaymentStore + ?Sized>(
+    store: &S,
+    record: PaymentRecord,
+    from: Option<PaymentState>,
+    to: PaymentState,
+) -> Result<PaymentRecord, SpError> {
+    let record = PaymentRecord { state: to, ..record };
+    let transition = StateTransition {
+        order_id: record.order_id.clone(),
+        from,
+        to,
+        at: record.updated_at,
+    };
+    store.save_with_transition(&record, Some(&transition).filter(|_| from != Some(to)))?;
+    Ok(record)
+}
+
+/// Current UTC time used to stamp records
+fn now() -> NaiveDateTime {
+    Utc::now().naive_utc()
+}
+
+/// In memory `PaymentStore`
+/// Records are lost when the process exits
+#[derive(Debug, Default)]
+pub struct MemoryPaymentStore {
+    records: Mutex<HashMap<String, PaymentRecord>>,
+    transitions: Mutex<Vec<StateTransition>>,
+}
+
+impl MemoryPaymentStore {
+    /// This is a constructor to initiate an empty `MemoryPaymentStore`
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl PaymentStore for MemoryPaymentStore {
+    fn save_record(&self, record: &PaymentRecord) -> Result<(), SpError> {
+        let mut records = self.records.lock().map_err(|e| SpError::Store(e.to_string()))?;
+        records.insert(record.order_id.clone(), record.clone());
+        Ok(())
+    }
+
+    fn append_transition(&self, transition: &StateTransition) -> Result<(), SpError> {
+        let mut transitions = self.transitions.lock().map_err(|e| SpError::Store(e.to_string()))?;
+        transitions.push(transition.clone());
+        Ok(())
+    }
+
+    fn save_with_transition(&self, record: &PaymentRecord, transition: Option<&StateTransition>) -> Result<(), SpError> {
+        // Both locks are held so readers never see the record without its transition
+        let mut records = self.records.lock().map_err(|e| SpError::Store(e.to_string()))?;
+        let mut transitions = self.transitions.lock().map_err(|e| SpError::Store(e.to_string()))?;
+        records.insert(record.order_id.clone(), record.clone());
+        transitions.extend(transition.cloned());
+        Ok(())
+    }
+
+    fn save_if_state(
+        &self,
+        record: &PaymentRecord,
+        expected: PaymentState,
+        transition: Option<&StateTransition>,
+    ) -> Result<bool, SpError> {
+        let mut records = self.records.lock().map_err(|e| SpError::Store(e.to_string()))?;
+        let mut transitions = self.transitions.lock().map_err(|e| SpError::Store(e.to_string()))?;
+        if records.get(&record.order_id).map(|stored| stored.state) != Some(expected) {
+            return Ok(false);
+        }
+        records.insert(record.order_id.clone(), record.clone());
+        transitions.extend(transition.cloned());
+        Ok(true)
+    }
+
+    fn find_by_order_id(&self, order_id: &str) -> Result<Option<PaymentRecord>, SpError> {
+        let records = self.records.lock().map_err(|e| SpError::Store(e.to_string()))?;
+        Ok(records.get(order_id).cloned())
+    }
+
+    fn find_by_sp_order_id(&self, sp_order_id: &str) -> Result<Option<PaymentRecord>, SpError> {
+        let records = self.records.lock().map_err(|e| SpError::Store(e.to_string()))?;
+        Ok(records
+            .values()
+            .find(|record| record.sp_order_id.as_deref() == Some(sp_order_id))
+            .cloned())
+    }
+
+    fn transitions(&self, order_id: &str) -> Result<Vec<StateTransition>, SpError> {
+        let transitions = self.transitions.lock().map_err(|e| SpError::Store(e.to_string()))?;
+        Ok(transitions
+            .iter()
+            .filter(|transition| transition.order_id == order_id)
+            .cloned()
+            .collect())
+    }
+}
@@ -0,0 +1,235 @@
+//!
+//! This module validates and normalizes Bangladeshi mobile numbers.
+//!
+//! Customers type their number as `01711-223344`, `+880 1711 223344`,
+//! `8801711223344`, `008801711223344` or in Bangla digits. `PhoneNumber`
+//! accepts all of them, checks the length and operator prefix, and gives
+//! the canonical `01711223344` shurjopay expects in `customer_phone`.
+//! Invalid numbers are `SpError::InvalidPhone` with a `PhoneError` naming
+//! the problem, before the checkout is sent.
+//!
+//! Phone numbers are personal data: `Debug` and `masked` show only the
+//! operator prefix and the last three digits.
+//!
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::SpError;
+
+/// Country calling code of Bangladesh
+pub const COUNTRY_CODE: &str = "880";
+
+/// Digits of a mobile number in the national format, `01XNNNNNNNN`
+pub const NATIONAL_LEN: usize = 11;
+
+/// Why a phone number was refused
+/// This enum implements `Debug`, `Clone`, `PartialEq` and `Eq` functions
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PhoneError {
+    /// No digits were given
+    Empty,
+    /// A character other than a digit, space, `-`, `.`, `(`, `)` or a leading `+`
+    InvalidCharacter(char),
+    /// The country code is not `+880`
+    NotBangladeshi,
+    /// The number does not start with `01`, e.g. a land line
+    NotMobile,
+    /// The number has `digits` digits in the national format instead of 11
+    WrongLength { digits: usize },
+    /// No operator uses the prefix, e.g. `012`
+    UnknownOperator { prefix: String },
+}
+
+impl fmt::Display for PhoneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PhoneError::Empty => write!(f, "no digits"),
+            PhoneError::InvalidCharacter(ch) => write!(f, "`{}` is not allowed", ch),
+            PhoneError::NotBangladeshi => write!(f, "country code is not +{}", COUNTRY_CODE),
+            PhoneError::NotMobile => write!(f, "not a mobile number, mobile numbers start with 01"),
+            PhoneError::WrongLength { digits } => write!(
+                f,
+                "{} digits, a mobile number has {} digits (01XNNNNNNNN)",
+                digits, NATIONAL_LEN
+            ),
+            PhoneError::UnknownOperator { prefix } => write!(f, "no operator uses the prefix {}", prefix),
+        }
+    }
+}
+
+impl From<PhoneError> for SpError {
+    fn from(err: PhoneError) -> Self {
+        SpError::InvalidPhone(err)
+    }
+}
+
+/// Mobile operator of a number, by its prefix
+/// This enum implements `Serialize`, `Deserialize`, `Debug`, `Clone`, `Copy`, `PartialEq` and `Eq` functions
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    /// `013` and `017`
+    Grameenphone,
+    /// `014` and `019`
+    Banglalink,
+    /// `015`
+    Teletalk,
+    /// `016`
+    Airtel,
+    /// `018`
+    Robi,
+}
+
+impl Operator {
+    /// Operator of the third digit of a national number, `None` for unused prefixes
+    fn from_digit(digit: u8) -> Option<Self> {
+        match digit {
+            b'3' | b'7' => Some(Operator::Grameenphone),
+            b'4' | b'9' => Some(Operator::Banglalink),
+            b'5' => Some(Operator::Teletalk),
+            b'6' => Some(Operator::Airtel),
+            b'8' => Some(Operator::Robi),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Operator::Grameenphone => "Grameenphone",
+            Operator::Banglalink => "Banglalink",
+            Operator::Teletalk => "Teletalk",
+            Operator::Airtel => "Airtel",
+            Operator::Robi => "Robi",
+        })
+    }
+}
+
+/// A validated Bangladeshi mobile number
+/// Displays and serializes in the national format `01711223344`
+/// This structure implements `Serialize`, `Deserialize`, `Clone`, `PartialEq`, `Eq` and `Hash` functions,
+/// `Debug` shows the masked number
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+#[serde(try_from = "String", into = "String")]
+pub struct PhoneNumber {
+    /// National format, `01` followed by nine digits
+    national: String,
+}
+
+impl PhoneNumber {
+    /// This function parses and validates a mobile number
+    ///
+    /// The number may have a `+880`, `880` or `00880` country code, spaces,
+    /// `-`, `.` and parentheses, and ASCII or Bangla digits.
+    pub fn parse(phone: &str) -> Result<Self, SpError> {
+        Ok(Self::parse_national(phone)?)
+    }
+
+    fn parse_national(phone: &str) -> Result<Self, PhoneError> {
+        let phone = phone.trim();
+        let (international, rest) = match phone.strip_prefix('+') {
+            Some(rest) => (true, rest),
+            None => (false, phone),
+        };
+        let mut digits = String::with_capacity(rest.len());
+        for ch in rest.chars() {
+            match ch {
+                '0'..='9' => digits.push(ch),
+                '০'..='৯' => digits.push(char::from(b'0' + (ch as u32 - '০' as u32) as u8)),
+                ' ' | '-' | '.' | '(' | ')' => {}
+                _ => return Err(PhoneError::InvalidCharacter(ch)),
+            }
+        }
+        if digits.is_empty() {
+            return Err(PhoneError::Empty);
+        }
+
+        let national = if international {
+            digits.strip_prefix(COUNTRY_CODE).ok_or(PhoneError::NotBangladeshi)?
+        } else if let Some(rest) = digits.strip_prefix("00") {
+            rest.strip_prefix(COUNTRY_CODE).ok_or(PhoneError::NotBangladeshi)?
+        } else {
+            digits.strip_prefix(COUNTRY_CODE).unwrap_or(&digits)
+        };
+        // `+880 01711...` repeats the trunk prefix, `1711...` leaves it out
+        let subscriber = national.strip_prefix('0').unwrap_or(national);
+        if subscriber.is_empty() {
+            return Err(PhoneError::WrongLength { digits: national.len() });
+        }
+        if !subscriber.starts_with('1') {
+            return Err(PhoneError::NotMobile);
+        }
+        if subscriber.len() + 1 != NATIONAL_LEN {
+            return Err(PhoneError::WrongLength {
+                digits: subscriber.len() + 1,
+            });
+        }
+        if Operator::from_digit(subscriber.as_bytes()[1]).is_none() {
+            return Err(PhoneError::UnknownOperator {
+                prefix: format!("0{}", &subscriber[..2]),
+            });
+        }
+        Ok(PhoneNumber {
+            national: format!("0{}", subscriber),
+        })
+    }
+
+    /// National format sent as `customer_phone`, `01711223344`
+    pub fn national(&self) -> &str {
+        &self.national
+    }
+
+    /// International format, `+8801711223344`
+    pub fn international(&self) -> String {
+        format!("+{}{}", COUNTRY_CODE, &self.national[1..])
+    }
+
+    /// Operator of the number
+    pub fn operator(&self) -> Operator {
+        // The prefix was checked on parsing
+        Operator::from_digit(self.national.as_bytes()[2]).unwrap_or(Operator::Grameenphone)
+    }
+
+    /// The number with all but the operator prefix and last three digits masked, `017*****344`
+    pub fn masked(&self) -> String {
+        let hidden = NATIONAL_LEN - 6;
+        format!("{}{}{}", &self.national[..3], "*".repeat(hidden), &self.national[3 + hidden..])
+    }
+}
+
+impl fmt::Display for PhoneNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.national)
+    }
+}
+
+impl fmt::Debug for PhoneNumber {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PhoneNumber").field(&self.masked()).finish()
+    }
+}
+
+impl FromStr for PhoneNumber {
+    type Err = SpError;
+
+    fn from_str(phone: &str) -> Result<Self, SpError> {
+        Self::parse(phone)
+    }
+}
+
+impl TryFrom<String> for PhoneNumber {
+    type Error = SpError;
+
+    fn try_from(phone: String) -> Result<Self, SpError> {
+        Self::parse(&phone)
+    }
+}
+
+impl From<PhoneNumber> for String {
+    fn from(phone: PhoneNumber) -> Self {
+        phone.national
+    }
+}
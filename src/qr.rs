@@ -0,0 +1,184 @@
+//!
+//! This module renders checkout urls as QR codes.
+//!
+//! At a counter or in an SMS the shopper scans the `checkout_url` of a
+//! checkout instead of the plugin opening a browser. `CheckoutQr` encodes
+//! the url once and renders it as SVG, as PNG bytes or as text for a
+//! terminal. Available with the `qr` feature.
+//!
+
+use std::fmt;
+use std::str::FromStr;
+
+use qrcode::render::{svg, unicode};
+use qrcode::{Color, EcLevel, QrCode};
+
+use crate::error::SpError;
+use crate::shurjopay::SpCheckoutResponse;
+
+/// Modules of light border around a QR code
+const QUIET_ZONE: usize = 4;
+
+/// Largest `module_size`, a 64 pixel module already gives a code of several thousand pixels
+pub const MAX_MODULE_SIZE: u32 = 64;
+
+/// Share of a QR code that may be damaged and still scan
+/// This enum implements `Debug`, `Clone`, `Copy`, `PartialEq`, `Eq` and `Default` functions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorCorrection {
+    /// About 7%
+    Low,
+    /// About 15%
+    #[default]
+    Medium,
+    /// About 25%
+    Quartile,
+    /// About 30%, for printed codes that may get scratched
+    High,
+}
+
+impl ErrorCorrection {
+    fn ec_level(self) -> EcLevel {
+        match self {
+            ErrorCorrection::Low => EcLevel::L,
+            ErrorCorrection::Medium => EcLevel::M,
+            ErrorCorrection::Quartile => EcLevel::Q,
+            ErrorCorrection::High => EcLevel::H,
+        }
+    }
+}
+
+impl FromStr for ErrorCorrection {
+    type Err = SpError;
+
+    /// Parses `L`, `M`, `Q` or `H`, or the full names, in any case
+    fn from_str(level: &str) -> Result<Self, SpError> {
+        match level.trim().to_ascii_lowercase().as_str() {
+            "l" | "low" => Ok(ErrorCorrection::Low),
+            "m" | "medium" => Ok(ErrorCorrection::Medium),
+            "q" | "quartile" => Ok(ErrorCorrection::Quartile),
+            "h" | "high" => Ok(ErrorCorrection::High),
+            _ => Err(SpError::Config(format!("unknown error correction level `{}`", level))),
+        }
+    }
+}
+
+/// Settings of a rendered QR code
+/// This structure implements `Debug`, `Clone`, `PartialEq` and `Default` functions
+#[derive(Debug, Clone, PartialEq)]
+pub struct QrOptions {
+    pub error_correction: ErrorCorrection,
+    /// Pixels per module of SVG and PNG images, from 1 to `MAX_MODULE_SIZE`
+    pub module_size: u32,
+    /// Adds the light border scanners need around the code
+    pub quiet_zone: bool,
+}
+
+impl Default for QrOptions {
+    /// This function will set default value for QrOptions struct
+    fn default() -> Self {
+        QrOptions {
+            error_correction: ErrorCorrection::Medium,
+            module_size: 8,
+            quiet_zone: true,
+        }
+    }
+}
+
+/// QR code of a checkout url
+#[derive(Clone)]
+pub struct CheckoutQr {
+    code: QrCode,
+    options: QrOptions,
+}
+
+impl CheckoutQr {
+    /// Encodes `checkout_url`
+    /// A url too long for a QR code or a `module_size` out of range is a `SpError::Config`
+    pub fn new(checkout_url: &str, options: QrOptions) -> Result<Self, SpError> {
+        if !(1..=MAX_MODULE_SIZE).contains(&options.module_size) {
+            return Err(SpError::Config(format!(
+                "qr module size {} is not between 1 and {}",
+                options.module_size, MAX_MODULE_SIZE
+            )));
+        }
+        let code = QrCode::with_error_correction_level(checkout_url, options.error_correction.ec_level())
+            .map_err(|err| SpError::Config(format!("qr code: {}", err)))?;
+        Ok(CheckoutQr { code, options })
+    }
+
+    /// Number of modules on each side, without the quiet zone
+    pub fn modules(&self) -> usize {
+        self.code.width()
+    }
+
+    /// Renders an SVG document
+    pub fn to_svg(&self) -> String {
+        let size = self.options.module_size;
+        self.code
+            .render::<svg::Color>()
+            .quiet_zone(self.options.quiet_zone)
+            .module_dimensions(size, size)
+            .dark_color(svg::Color("#000000"))
+            .light_color(svg::Color("#ffffff"))
+            .build()
+    }
+
+    /// Renders a grayscale PNG image
+    pub fn to_png(&self) -> Result<Vec<u8>, SpError> {
+        let size = self.options.module_size as usize;
+        let border = if self.options.quiet_zone { QUIET_ZONE } else { 0 };
+        let modules = self.code.width();
+        let side = (modules + 2 * border) * size;
+        let colors = self.code.to_colors();
+
+        let mut pixels = vec![255u8; side * side];
+        for y in 0..modules {
+            for x in 0..modules {
+                if colors[y * modules + x] == Color::Dark {
+                    for row in 0..size {
+                        let start = ((y + border) * size + row) * side + (x + border) * size;
+                        pixels[start..start + size].fill(0);
+                    }
+                }
+            }
+        }
+
+        let png_error = |err: png::EncodingError| SpError::Config(format!("qr png: {}", err));
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, side as u32, side as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(png_error)?;
+        writer.write_image_data(&pixels).map_err(png_error)?;
+        writer.finish().map_err(png_error)?;
+        Ok(bytes)
+    }
+
+    /// Renders text of half block characters, two modules per line
+    /// Colors are inverted so the code scans on terminals with a dark background
+    pub fn to_terminal(&self) -> String {
+        self.code
+            .render::<unicode::Dense1x2>()
+            .quiet_zone(self.options.quiet_zone)
+            .dark_color(unicode::Dense1x2::Light)
+            .light_color(unicode::Dense1x2::Dark)
+            .build()
+    }
+}
+
+impl fmt::Debug for CheckoutQr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CheckoutQr")
+            .field("modules", &self.code.width())
+            .field("options", &self.options)
+            .finish()
+    }
+}
+
+impl SpCheckoutResponse {
+    /// This function encodes `checkout_url` as a QR code
+    pub fn qr_code(&self, options: QrOptions) -> Result<CheckoutQr, SpError> {
+        CheckoutQr::new(&self.checkout_url, options)
+    }
+}
@@ -0,0 +1,205 @@
+//!
+//! This module implements a client side rate limiter for gateway requests.
+//!
+//! Limits are token buckets set on `SpConfig::rate_limit`, one shared by
+//! every end point (`global`) and one per end point (`end_points`, keyed
+//! like `SpConfig::verification_end_point`). A request takes a token from
+//! both. When a bucket is empty the request either waits for a token or
+//! fails with `SpError::RateLimited`, as set by `on_exhausted`.
+//!
+//! A `Retry-After` header on a `429` or `503` response pauses the end
+//! point for the time the gateway asks for.
+//!
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::SpError;
+
+/// Key of the bucket shared by every end point
+const GLOBAL: &str = "*";
+
+/// Token bucket settings
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone` and `PartialEq` functions
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RateLimit {
+    /// Tokens added per second
+    pub requests_per_second: f64,
+    /// Tokens the bucket holds, i.e. requests that may be sent at once
+    pub burst: u32,
+}
+
+impl RateLimit {
+    pub fn per_second(requests_per_second: f64) -> Self {
+        RateLimit {
+            requests_per_second,
+            burst: requests_per_second.ceil().max(1.0) as u32,
+        }
+    }
+}
+
+/// What a request does when its bucket is empty
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone`, `Copy` and `PartialEq` functions
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OnExhausted {
+    /// Block until a token is available
+    #[default]
+    Wait,
+    /// Fail at once with `SpError::RateLimited`
+    Fail,
+}
+
+/// Rate limits of the gateway requests, no limit is set by default
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone` and `PartialEq` functions
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct RateLimitConfig {
+    #[serde(default)]
+    pub global: Option<RateLimit>,
+    #[serde(default)]
+    pub end_points: HashMap<String, RateLimit>,
+    #[serde(default)]
+    pub on_exhausted: OnExhausted,
+}
+
+impl RateLimitConfig {
+    /// Sets the limit shared by every end point
+    pub fn global(mut self, limit: RateLimit) -> Self {
+        self.global = Some(limit);
+        self
+    }
+
+    /// Sets the limit of `end_point`
+    pub fn end_point(mut self, end_point: &str, limit: RateLimit) -> Self {
+        self.end_points.insert(end_point.to_string(), limit);
+        self
+    }
+
+    /// Sets what a request does when its bucket is empty
+    pub fn on_exhausted(mut self, on_exhausted: OnExhausted) -> Self {
+        self.on_exhausted = on_exhausted;
+        self
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl Bucket {
+    /// Refills the bucket and returns the time until a token is available
+    fn wait_time(&mut self, limit: &RateLimit, now: Instant) -> Duration {
+        let burst = f64::from(limit.burst.max(1));
+        let elapsed = now.saturating_duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.requests_per_second).min(burst);
+        self.refilled_at = now;
+        if self.tokens >= 1.0 {
+            Duration::ZERO
+        } else if limit.requests_per_second <= 0.0 {
+            Duration::MAX
+        } else {
+            Duration::from_secs_f64((1.0 - self.tokens) / limit.requests_per_second)
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct LimiterState {
+    buckets: HashMap<String, Bucket>,
+    paused_until: HashMap<String, Instant>,
+}
+
+/// Rate limiter state shared by the clones of a `ShurjopayPlugin`
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    state: Mutex<LimiterState>,
+}
+
+impl RateLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes a token for a request to `end_point`, waiting or failing as `config` says
+    pub fn acquire(&self, config: &RateLimitConfig, end_point: &str) -> Result<(), SpError> {
+        let limits: Vec<(&str, &RateLimit)> = config
+            .global
+            .iter()
+            .map(|limit| (GLOBAL, limit))
+            .chain(config.end_points.get(end_point).map(|limit| (end_point, limit)))
+            .collect();
+        loop {
+            let wait = {
+                let mut state = self.lock();
+                let now = Instant::now();
+                let mut wait = state
+                    .paused_until
+                    .get(end_point)
+                    .map(|until| until.saturating_duration_since(now))
+                    .unwrap_or_default();
+                for (key, limit) in &limits {
+                    let bucket = state.buckets.entry(key.to_string()).or_insert_with(|| Bucket {
+                        tokens: f64::from(limit.burst.max(1)),
+                        refilled_at: now,
+                    });
+                    wait = wait.max(bucket.wait_time(limit, now));
+                }
+                if wait.is_zero() {
+                    for (key, _) in &limits {
+                        if let Some(bucket) = state.buckets.get_mut(*key) {
+                            bucket.tokens -= 1.0;
+                        }
+                    }
+                    return Ok(());
+                }
+                wait
+            };
+            if config.on_exhausted == OnExhausted::Fail || wait == Duration::MAX {
+                return Err(SpError::RateLimited {
+                    end_point: end_point.to_string(),
+                    retry_after_ms: wait.as_millis().min(u128::from(u64::MAX)) as u64,
+                });
+            }
+            thread::sleep(wait);
+        }
+    }
+
+    /// Pauses requests to `end_point` for `duration`, e.g. as asked by a `Retry-After` header
+    /// A duration too long to represent pauses for `MAX_RETRY_AFTER`
+    pub fn pause(&self, end_point: &str, duration: Duration) {
+        let now = Instant::now();
+        let until = now.checked_add(duration).unwrap_or(now + MAX_RETRY_AFTER);
+        let mut state = self.lock();
+        let paused_until = state.paused_until.entry(end_point.to_string()).or_insert(until);
+        if *paused_until < until {
+            *paused_until = until;
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, LimiterState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Longest pause a `Retry-After` header can ask for
+pub const MAX_RETRY_AFTER: Duration = Duration::from_secs(3600);
+
+/// Reads a `Retry-After` header given in seconds or as an HTTP date, at most `MAX_RETRY_AFTER`
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let retry_after = match value.parse::<u64>() {
+        Ok(seconds) => Duration::from_secs(seconds),
+        Err(_) => {
+            let at = DateTime::parse_from_rfc2822(value).ok()?.with_timezone(&Utc);
+            (at - Utc::now()).to_std().unwrap_or_default()
+        }
+    };
+    Some(retry_after.min(MAX_RETRY_AFTER))
+}
@@ -0,0 +1,535 @@
+//!
+//! This module renders receipts of verified payments.
+//!
+//! A `Receipt` is built from an `SpVerifyResponse` and, for itemized
+//! orders, the `Cart` of the order. It carries the bank transaction id,
+//! invoice number, payment method, amounts and the masked card number,
+//! with dates, digits and labels localized in English or Bangla.
+//! `ReceiptRenderer` renders it as HTML and plain text through
+//! [minijinja](https://docs.rs/minijinja) templates; the built-in
+//! templates can be replaced by the merchant's own. Values are HTML
+//! escaped in the HTML template. Available with the `receipt` feature.
+//!
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use minijinja::Environment;
+use serde::Serialize;
+
+use crate::cart::Cart;
+use crate::error::SpError;
+use crate::export::mask_card_number;
+use crate::money::Money;
+use crate::payment_store::PaymentState;
+use crate::shurjopay::SpVerifyResponse;
+
+/// Template name of the HTML receipt, the `.html` extension turns on escaping
+const HTML_TEMPLATE: &str = "receipt.html";
+/// Template name of the plain text receipt
+const TEXT_TEMPLATE: &str = "receipt.txt";
+
+/// Built-in HTML receipt template
+pub const DEFAULT_HTML_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="{{ language }}">
+<head><meta charset="utf-8"><title>{{ labels.receipt }} {{ invoice_no }}</title></head>
+<body style="font-family: sans-serif; max-width: 40em; margin: auto;">
+<h1>{{ labels.receipt }}</h1>
+<table>
+<tr><th align="left">{{ labels.status }}</th><td>{{ status }}</td></tr>
+<tr><th align="left">{{ labels.order_id }}</th><td>{{ customer_order_id }}</td></tr>
+<tr><th align="left">{{ labels.invoice_no }}</th><td>{{ invoice_no }}</td></tr>
+<tr><th align="left">{{ labels.bank_trx_id }}</th><td>{{ bank_trx_id }}</td></tr>
+<tr><th align="left">{{ labels.method }}</th><td>{{ method }}</td></tr>
+{% if card_number %}<tr><th align="left">{{ labels.card }}</th><td>{{ card_number }}{% if card_holder_name %} ({{ card_holder_name }}){% endif %}</td></tr>
+{% endif %}{% if date %}<tr><th align="left">{{ labels.date }}</th><td>{{ date }}</td></tr>
+{% endif %}{% if customer_name %}<tr><th align="left">{{ labels.customer }}</th><td>{{ customer_name }}{% if customer_phone %}, {{ customer_phone }}{% endif %}</td></tr>
+{% endif %}</table>
+{% if items %}<table style="width: 100%; margin-top: 1em;">
+<tr><th align="left">{{ labels.item }}</th><th align="right">{{ labels.quantity }}</th><th align="right">{{ labels.unit_price }}</th><th align="right">{{ labels.line_total }}</th></tr>
+{% for item in items %}<tr><td>{{ item.name }}</td><td align="right">{{ item.quantity }}</td><td align="right">{{ item.unit_price }}</td><td align="right">{{ item.total }}</td></tr>
+{% endfor %}</table>
+<table style="margin-top: 1em;">
+<tr><th align="left">{{ labels.subtotal }}</th><td align="right">{{ totals.subtotal }}</td></tr>
+{% if totals.discount %}<tr><th align="left">{{ labels.discount }}</th><td align="right">-{{ totals.discount }}</td></tr>
+{% endif %}{% if totals.vat %}<tr><th align="left">{{ labels.vat }}</th><td align="right">{{ totals.vat }}</td></tr>
+{% endif %}{% if totals.shipping %}<tr><th align="left">{{ labels.shipping }}</th><td align="right">{{ totals.shipping }}</td></tr>
+{% endif %}<tr><th align="left">{{ labels.total }}</th><td align="right">{{ totals.total }}</td></tr>
+</table>
+{% endif %}<table style="margin-top: 1em;">
+{% if amount %}<tr><th align="left">{{ labels.amount }}</th><td align="right">{{ amount }} {{ currency }}</td></tr>
+{% endif %}{% if discount %}<tr><th align="left">{{ labels.discount }}</th><td align="right">-{{ discount }} {{ currency }}</td></tr>
+{% endif %}{% if paid_amount %}<tr><th align="left">{{ labels.paid_amount }}</th><td align="right"><strong>{{ paid_amount }} {{ currency }}</strong></td></tr>
+{% endif %}</table>
+<p>{{ labels.thank_you }}</p>
+</body>
+</html>
+"#;
+
+/// Built-in plain text receipt template
+pub const DEFAULT_TEXT_TEMPLATE: &str = r#"{{ labels.receipt }}
+{{ labels.status }}: {{ status }}
+{{ labels.order_id }}: {{ customer_order_id }}
+{{ labels.invoice_no }}: {{ invoice_no }}
+{{ labels.bank_trx_id }}: {{ bank_trx_id }}
+{{ labels.method }}: {{ method }}
+{% if card_number %}
+{{ labels.card }}: {{ card_number }}
+{% endif %}
+{% if date %}
+{{ labels.date }}: {{ date }}
+{% endif %}
+{% if customer_name %}
+{{ labels.customer }}: {{ customer_name }}
+{% endif %}
+{% if items %}
+
+{% for item in items %}
+{{ item.quantity }} x {{ item.name }} @ {{ item.unit_price }} = {{ item.total }}
+{% endfor %}
+{{ labels.subtotal }}: {{ totals.subtotal }}
+{% if totals.discount %}
+{{ labels.discount }}: -{{ totals.discount }}
+{% endif %}
+{% if totals.vat %}
+{{ labels.vat }}: {{ totals.vat }}
+{% endif %}
+{% if totals.shipping %}
+{{ labels.shipping }}: {{ totals.shipping }}
+{% endif %}
+{{ labels.total }}: {{ totals.total }}
+{% endif %}
+
+{% if amount %}
+{{ labels.amount }}: {{ amount }} {{ currency }}
+{% endif %}
+{% if discount %}
+{{ labels.discount }}: -{{ discount }} {{ currency }}
+{% endif %}
+{% if paid_amount %}
+{{ labels.paid_amount }}: {{ paid_amount }} {{ currency }}
+{% endif %}
+
+{{ labels.thank_you }}
+"#;
+
+/// Language of a receipt
+/// This enum implements `Serialize`, `Debug`, `Clone`, `Copy`, `PartialEq`, `Eq` and `Default` functions
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Language {
+    #[default]
+    #[serde(rename = "en")]
+    English,
+    #[serde(rename = "bn")]
+    Bangla,
+}
+
+impl Language {
+    /// Labels of the receipt fields in the language
+    pub fn labels(&self) -> Labels {
+        match self {
+            Language::English => Labels {
+                receipt: "Payment receipt",
+                status: "Status",
+                paid: "Paid",
+                not_paid: "Not paid",
+                order_id: "Order id",
+                invoice_no: "Invoice no",
+                bank_trx_id: "Bank transaction id",
+                method: "Payment method",
+                card: "Card",
+                date: "Date",
+                customer: "Customer",
+                item: "Item",
+                quantity: "Qty",
+                unit_price: "Unit price",
+                line_total: "Total",
+                subtotal: "Subtotal",
+                discount: "Discount",
+                vat: "VAT",
+                shipping: "Shipping",
+                total: "Total",
+                amount: "Amount",
+                paid_amount: "Amount paid",
+                thank_you: "Thank you for your payment.",
+            },
+            Language::Bangla => Labels {
+                receipt: "পেমেন্ট রসিদ",
+                status: "অবস্থা",
+                paid: "পরিশোধিত",
+                not_paid: "অপরিশোধিত",
+                order_id: "অর্ডার আইডি",
+                invoice_no: "ইনভয়েস নম্বর",
+                bank_trx_id: "ব্যাংক লেনদেন আইডি",
+                method: "পেমেন্ট মাধ্যম",
+                card: "কার্ড",
+                date: "তারিখ",
+                customer: "গ্রাহক",
+                item: "পণ্য",
+                quantity: "পরিমাণ",
+                unit_price: "একক মূল্য",
+                line_total: "মোট",
+                subtotal: "উপমোট",
+                discount: "ছাড়",
+                vat: "ভ্যাট",
+                shipping: "ডেলিভারি চার্জ",
+                total: "সর্বমোট",
+                amount: "মূল্য",
+                paid_amount: "পরিশোধিত মূল্য",
+                thank_you: "পেমেন্টের জন্য ধন্যবাদ।",
+            },
+        }
+    }
+
+    /// Replaces ASCII digits with the digits of the language
+    pub fn digits(&self, text: &str) -> String {
+        match self {
+            Language::English => text.to_string(),
+            Language::Bangla => text
+                .chars()
+                .map(|ch| match ch.to_digit(10) {
+                    Some(digit) => BANGLA_DIGITS[digit as usize],
+                    None => ch,
+                })
+                .collect(),
+        }
+    }
+
+    /// Formats an amount with grouped digits, `1,234,567.50` in English and
+    /// `১২,৩৪,৫৬৭.৫০` in Bangla
+    pub fn amount(&self, money: &Money) -> String {
+        let text = money.amount_string();
+        let (sign, text) = match text.strip_prefix('-') {
+            Some(rest) => ("-", rest),
+            None => ("", text.as_str()),
+        };
+        let (major, fraction) = text.split_once('.').unwrap_or((text, "00"));
+        let grouped = match self {
+            Language::English => group_digits(major, 3),
+            Language::Bangla => match major.len() {
+                0..=3 => major.to_string(),
+                len => format!("{},{}", group_digits(&major[..len - 3], 2), &major[len - 3..]),
+            },
+        };
+        self.digits(&format!("{}{}.{}", sign, grouped, fraction))
+    }
+
+    /// Name of a currency, `টাকা` for BDT in Bangla
+    pub fn currency(&self, currency: &str) -> String {
+        match (self, currency.trim().to_uppercase().as_str()) {
+            (Language::Bangla, "BDT") => "টাকা".to_string(),
+            (_, code) => code.to_string(),
+        }
+    }
+
+    /// Formats a date and time, `30 November 2022, 3:07 PM` in English and
+    /// `৩০ নভেম্বর ২০২২, ১৫:০৭` in Bangla
+    pub fn date_time(&self, date_time: &NaiveDateTime) -> String {
+        match self {
+            Language::English => date_time.format("%-d %B %Y, %-I:%M %p").to_string(),
+            Language::Bangla => self.digits(&format!(
+                "{} {} {}, {:02}:{:02}",
+                date_time.day(),
+                BANGLA_MONTHS[date_time.month0() as usize],
+                date_time.year(),
+                date_time.hour(),
+                date_time.minute()
+            )),
+        }
+    }
+}
+
+impl FromStr for Language {
+    type Err = SpError;
+
+    /// Parses `en`, `english`, `bn`, `bangla` or `bengali` in any case
+    fn from_str(language: &str) -> Result<Self, SpError> {
+        match language.trim().to_ascii_lowercase().as_str() {
+            "en" | "english" => Ok(Language::English),
+            "bn" | "bangla" | "bengali" => Ok(Language::Bangla),
+            _ => Err(SpError::Config(format!("unknown receipt language `{}`", language))),
+        }
+    }
+}
+
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Language::English => "en",
+            Language::Bangla => "bn",
+        })
+    }
+}
+
+const BANGLA_DIGITS: [char; 10] = ['০', '১', '২', '৩', '৪', '৫', '৬', '৭', '৮', '৯'];
+
+const BANGLA_MONTHS: [&str; 12] = [
+    "জানুয়ারি",
+    "ফেব্রুয়ারি",
+    "মার্চ",
+    "এপ্রিল",
+    "মে",
+    "জুন",
+    "জুলাই",
+    "আগস্ট",
+    "সেপ্টেম্বর",
+    "অক্টোবর",
+    "নভেম্বর",
+    "ডিসেম্বর",
+];
+
+/// Inserts a comma every `size` digits from the right
+fn group_digits(digits: &str, size: usize) -> String {
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / size);
+    for (index, ch) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index) % size == 0 {
+            grouped.push(',');
+        }
+        grouped.push(ch);
+    }
+    grouped
+}
+
+/// Labels of the receipt fields, available to templates as `labels`
+/// This structure implements `Serialize`, `Debug`, `Clone` and `PartialEq` functions
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Labels {
+    pub receipt: &'static str,
+    pub status: &'static str,
+    pub paid: &'static str,
+    pub not_paid: &'static str,
+    pub order_id: &'static str,
+    pub invoice_no: &'static str,
+    pub bank_trx_id: &'static str,
+    pub method: &'static str,
+    pub card: &'static str,
+    pub date: &'static str,
+    pub customer: &'static str,
+    pub item: &'static str,
+    pub quantity: &'static str,
+    pub unit_price: &'static str,
+    pub line_total: &'static str,
+    pub subtotal: &'static str,
+    pub discount: &'static str,
+    pub vat: &'static str,
+    pub shipping: &'static str,
+    pub total: &'static str,
+    pub amount: &'static str,
+    pub paid_amount: &'static str,
+    pub thank_you: &'static str,
+}
+
+/// A line item of a receipt, amounts are formatted in the receipt language
+/// This structure implements `Serialize`, `Debug`, `Clone` and `PartialEq` functions
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ReceiptLine {
+    pub sku: String,
+    pub name: String,
+    pub quantity: String,
+    pub unit_price: String,
+    pub total: String,
+}
+
+/// Cart totals of a receipt, zero amounts are `None`
+/// This structure implements `Serialize`, `Debug`, `Clone` and `PartialEq` functions
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ReceiptTotals {
+    pub subtotal: String,
+    pub discount: Option<String>,
+    pub vat: Option<String>,
+    pub shipping: Option<String>,
+    pub total: String,
+}
+
+/// Values of a receipt, available to templates by field name
+/// Amounts and dates are formatted in the receipt language, the card number is masked
+/// This structure implements `Serialize`, `Debug`, `Clone` and `PartialEq` functions
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Receipt {
+    pub language: Language,
+    pub labels: Labels,
+    pub paid: bool,
+    /// `Paid` or `Not paid` in the receipt language
+    pub status: String,
+    pub sp_order_id: String,
+    pub customer_order_id: String,
+    pub invoice_no: String,
+    pub bank_trx_id: String,
+    pub method: String,
+    pub card_holder_name: Option<String>,
+    pub card_number: Option<String>,
+    /// `date_time` of the payment, as reported if it could not be read
+    pub date: Option<String>,
+    pub customer_name: Option<String>,
+    pub customer_phone: Option<String>,
+    pub currency: String,
+    pub amount: Option<String>,
+    /// `discsount_amount`, `None` if there was no discount
+    pub discount: Option<String>,
+    /// `received_amount`, or `payable_amount` if nothing was reported received
+    pub paid_amount: Option<String>,
+    pub items: Vec<ReceiptLine>,
+    pub totals: Option<ReceiptTotals>,
+}
+
+/// Format of `date_time` in verification responses
+const GATEWAY_DATE_TIME: &str = "%Y-%m-%d %H:%M:%S";
+
+impl Receipt {
+    /// This function builds the receipt of a verification response
+    /// `cart` adds the line items and cart totals of an itemized order
+    pub fn from_verification(
+        response: &SpVerifyResponse,
+        cart: Option<&Cart>,
+        language: Language,
+    ) -> Result<Self, SpError> {
+        let currency = response
+            .currency
+            .clone()
+            .or_else(|| cart.map(|cart| cart.currency().to_string()))
+            .unwrap_or_else(|| "BDT".to_string());
+        let amount = |value: f64| Money::from_f64(value, &currency).map(|money| language.amount(&money));
+        let text = |value: &Option<String>| value.clone().filter(|value| !value.trim().is_empty());
+
+        let paid = PaymentState::from_sp_code(response.sp_code) == PaymentState::Paid;
+        let labels = language.labels();
+        let received = match &response.received_amount {
+            Some(received) => Money::from_gateway_str(received, &currency)
+                .ok()
+                .filter(|money| money.minor() > 0),
+            None => None,
+        };
+        let paid_amount = match received {
+            Some(received) => Some(language.amount(&received)),
+            None => response.payable_amount.map(amount).transpose()?,
+        };
+        let (items, totals) = match cart {
+            Some(cart) => receipt_items(cart, language)?,
+            None => (Vec::new(), None),
+        };
+
+        Ok(Receipt {
+            language,
+            status: if paid { labels.paid } else { labels.not_paid }.to_string(),
+            labels,
+            paid,
+            sp_order_id: response.order_id.clone().unwrap_or_default(),
+            customer_order_id: response.customer_order_id.clone().unwrap_or_default(),
+            invoice_no: response.invoice_no.clone().unwrap_or_default(),
+            bank_trx_id: response.bank_trx_id.clone().unwrap_or_default(),
+            method: response.method.clone().unwrap_or_default(),
+            card_holder_name: text(&response.card_holder_name),
+            card_number: text(&response.card_number).map(|card_number| mask_card_number(&card_number)),
+            date: text(&response.date_time).map(|date_time| {
+                match NaiveDateTime::parse_from_str(date_time.trim(), GATEWAY_DATE_TIME) {
+                    Ok(date_time) => language.date_time(&date_time),
+                    Err(_) => date_time,
+                }
+            }),
+            customer_name: text(&response.name),
+            customer_phone: text(&response.phone_no),
+            currency: language.currency(&currency),
+            amount: response.amount.map(amount).transpose()?,
+            discount: response
+                .discsount_amount
+                .filter(|discount| *discount > 0.0)
+                .map(amount)
+                .transpose()?,
+            paid_amount,
+            items,
+            totals,
+        })
+    }
+}
+
+fn receipt_items(cart: &Cart, language: Language) -> Result<(Vec<ReceiptLine>, Option<ReceiptTotals>), SpError> {
+    let totals = cart.totals()?;
+    let nonzero = |money: &Money| Some(money).filter(|money| money.minor() != 0).map(|money| language.amount(money));
+    let items = cart
+        .items()
+        .iter()
+        .zip(&totals.lines)
+        .map(|(item, line)| ReceiptLine {
+            sku: item.sku.clone(),
+            name: item.name.clone(),
+            quantity: language.digits(&item.quantity.to_string()),
+            unit_price: language.amount(&item.unit_price),
+            total: language.amount(&line.total),
+        })
+        .collect();
+    let receipt_totals = ReceiptTotals {
+        subtotal: language.amount(&totals.subtotal),
+        discount: nonzero(&totals.discount),
+        vat: nonzero(&totals.vat),
+        shipping: nonzero(&totals.shipping),
+        total: language.amount(&totals.total),
+    };
+    Ok((items, Some(receipt_totals)))
+}
+
+/// Renders receipts as HTML and plain text
+/// This structure implements `Debug` and `Clone` functions
+#[derive(Debug, Clone)]
+pub struct ReceiptRenderer {
+    env: Environment<'static>,
+}
+
+impl ReceiptRenderer {
+    /// This is a constructor to initiate a renderer with the built-in templates
+    pub fn new() -> Self {
+        let mut env = Environment::new();
+        env.set_trim_blocks(true);
+        env.set_keep_trailing_newline(true);
+        let mut renderer = ReceiptRenderer { env };
+        // The built-in templates are known to compile
+        let _ = renderer.set_html_template(DEFAULT_HTML_TEMPLATE);
+        let _ = renderer.set_text_template(DEFAULT_TEXT_TEMPLATE);
+        renderer
+    }
+
+    /// Replaces the HTML template, values are HTML escaped
+    /// A template with a syntax error is `SpError::Config`
+    pub fn set_html_template(&mut self, source: &str) -> Result<&mut Self, SpError> {
+        self.env
+            .add_template_owned(HTML_TEMPLATE, source.to_string())
+            .map_err(template_error)?;
+        Ok(self)
+    }
+
+    /// Replaces the plain text template
+    /// A template with a syntax error is `SpError::Config`
+    pub fn set_text_template(&mut self, source: &str) -> Result<&mut Self, SpError> {
+        self.env
+            .add_template_owned(TEXT_TEMPLATE, source.to_string())
+            .map_err(template_error)?;
+        Ok(self)
+    }
+
+    /// Renders the HTML receipt
+    pub fn render_html(&self, receipt: &Receipt) -> Result<String, SpError> {
+        self.render(HTML_TEMPLATE, receipt)
+    }
+
+    /// Renders the plain text receipt
+    pub fn render_text(&self, receipt: &Receipt) -> Result<String, SpError> {
+        self.render(TEXT_TEMPLATE, receipt)
+    }
+
+    fn render(&self, name: &str, receipt: &Receipt) -> Result<String, SpError> {
+        self.env
+            .get_template(name)
+            .and_then(|template| template.render(receipt))
+            .map_err(template_error)
+    }
+}
+
+impl Default for ReceiptRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn template_error(err: minijinja::Error) -> SpError {
+    SpError::Config(format!("receipt template: {}", err))
+}
@@ -0,0 +1,224 @@
+//!
+//! This module reconciles the local ledger against shurjopay.
+//!
+//! Each local record is verified again through the verification endpoint
+//! and the gateway's answer is compared with what we expected:
+//! - `payable_amount` and `received_amount` against the expected `Money`
+//! - the currency of the payment
+//! - the payment status derived from `sp_code`, unless the local record is
+//!   still waiting for an outcome (`CheckoutRequested` or `CheckoutCreated`)
+//! - orders shurjopay does not know about (`sp_code` 1011)
+//!
+
+use serde::{Deserialize, Serialize};
+
+use crate::money::Money;
+use crate::payment_store::{PaymentRecord, PaymentState};
+use crate::shurjopay::{ShurjopayPlugin, SpVerifyResponse};
+
+/// `sp_code` shurjopay returns for an order id it does not know
+pub const SP_CODE_UNKNOWN_ORDER: i64 = 1011;
+
+/// A payment as our side believes it to be
+/// `order_id` is the shurjopay order id used for verification
+/// This structure implements `Serialize`, `Deserialize`, `Debug` and `Clone` functions
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LocalRecord {
+    pub order_id: String,
+    pub expected: Money,
+    pub expected_status: PaymentState,
+}
+
+impl LocalRecord {
+    /// Builds a `LocalRecord` from a ledger entry
+    /// The expected amount is the amount less the `discount_amount` of the checkout,
+    /// returns `None` if the entry has no `sp_order_id` or its amounts are not valid money
+    pub fn from_payment_record(record: &PaymentRecord) -> Option<Self> {
+        let amount = Money::parse(&record.amount, &record.currency).ok()?;
+        let expected = match &record.checkout_request.discount_amount {
+            Some(discount) => amount
+                .checked_sub(&Money::parse(discount, &record.currency).ok()?)
+                .ok()?,
+            None => amount,
+        };
+        Some(LocalRecord {
+            order_id: record.sp_order_id.clone()?,
+            expected,
+            expected_status: record.state,
+        })
+    }
+}
+
+/// Which amount reported by shurjopay differs from the expected one
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone` and `PartialEq` functions
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AmountField {
+    PayableAmount,
+    ReceivedAmount,
+    /// `discsount_amount`, checked by `discount::compare`
+    DiscountAmount,
+}
+
+/// A single disagreement between the local record and shurjopay
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone` and `PartialEq` functions
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum Mismatch {
+    /// An amount reported by shurjopay differs from the expected amount
+    Amount {
+        field: AmountField,
+        expected: Money,
+        actual: Money,
+    },
+    /// An amount reported by shurjopay could not be read
+    InvalidAmount { field: AmountField, value: String },
+    /// Shurjopay reports a different `disc_percent` than the percentage sent
+    DiscountPercent { expected: String, actual: Option<f64> },
+    /// Shurjopay reports a different currency
+    Currency { expected: String, actual: Option<String> },
+    /// Shurjopay reports a different payment status
+    Status {
+        expected: PaymentState,
+        actual: PaymentState,
+        sp_code: Option<i64>,
+    },
+    /// Shurjopay does not know the order (`sp_code` 1011)
+    UnknownToGateway,
+    /// Shurjopay could not be asked, e.g. the request failed
+    VerificationFailed,
+}
+
+/// Mismatches found for one order
+/// This structure implements `Serialize`, `Deserialize`, `Debug` and `Clone` functions
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Discrepancy {
+    pub order_id: String,
+    pub mismatches: Vec<Mismatch>,
+}
+
+/// Result of a reconciliation run
+/// This structure implements `Serialize`, `Deserialize`, `Debug` and `Clone` functions
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ReconciliationReport {
+    pub checked: usize,
+    pub matched: Vec<String>,
+    /// Orders without a local outcome yet, whatever status shurjopay reports
+    #[serde(default)]
+    pub unverified: Vec<String>,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+impl ReconciliationReport {
+    /// returns true if every record agreed with shurjopay
+    pub fn is_clean(&self) -> bool {
+        self.discrepancies.is_empty()
+    }
+
+    /// Adds the comparison of one record to the report
+    pub fn add(&mut self, order_id: &str, mismatches: Vec<Mismatch>) {
+        self.checked += 1;
+        if mismatches.is_empty() {
+            self.matched.push(order_id.to_string());
+        } else {
+            self.discrepancies.push(Discrepancy {
+                order_id: order_id.to_string(),
+                mismatches,
+            });
+        }
+    }
+
+    /// Adds the comparison of a record still waiting for an outcome to the report
+    pub fn add_unverified(&mut self, order_id: &str, mismatches: Vec<Mismatch>) {
+        if mismatches.is_empty() {
+            self.checked += 1;
+            self.unverified.push(order_id.to_string());
+        } else {
+            self.add(order_id, mismatches);
+        }
+    }
+}
+
+/// Returns true if the ledger has no outcome of the payment yet, so any status
+/// reported by shurjopay is acceptable
+pub fn awaits_outcome(state: PaymentState) -> bool {
+    matches!(state, PaymentState::CheckoutRequested | PaymentState::CheckoutCreated)
+}
+
+/// Compares a local record with the verification response of shurjopay
+/// `None` means the verification request failed. The status of a record that
+/// `awaits_outcome` is not compared.
+pub fn compare(record: &LocalRecord, response: Option<&SpVerifyResponse>) -> Vec<Mismatch> {
+    let response = match response {
+        Some(response) => response,
+        None => return vec![Mismatch::VerificationFailed],
+    };
+    if response.sp_code == Some(SP_CODE_UNKNOWN_ORDER) {
+        return vec![Mismatch::UnknownToGateway];
+    }
+
+    let mut mismatches = Vec::new();
+    let currency = record.expected.currency();
+    let actual_status = PaymentState::from_sp_code(response.sp_code);
+    if actual_status != record.expected_status && !awaits_outcome(record.expected_status) {
+        mismatches.push(Mismatch::Status {
+            expected: record.expected_status,
+            actual: actual_status,
+            sp_code: response.sp_code,
+        });
+    }
+
+    match &response.currency {
+        Some(actual) if actual.trim().eq_ignore_ascii_case(currency) => {}
+        actual => mismatches.push(Mismatch::Currency {
+            expected: currency.to_string(),
+            actual: actual.clone(),
+        }),
+    }
+
+    if let Some(payable) = response.payable_amount {
+        let actual = Money::from_f64(payable, currency).ok();
+        push_amount_mismatch(&mut mismatches, record, AmountField::PayableAmount, actual, payable.to_string());
+    }
+    // Nothing is received for unpaid orders, so only paid orders are checked
+    if actual_status == PaymentState::Paid {
+        if let Some(received) = &response.received_amount {
+            let actual = Money::from_gateway_str(received, currency).ok();
+            push_amount_mismatch(&mut mismatches, record, AmountField::ReceivedAmount, actual, received.clone());
+        }
+    }
+    mismatches
+}
+
+fn push_amount_mismatch(
+    mismatches: &mut Vec<Mismatch>,
+    record: &LocalRecord,
+    field: AmountField,
+    actual: Option<Money>,
+    value: String,
+) {
+    match actual {
+        Some(actual) if actual == record.expected => {}
+        Some(actual) => mismatches.push(Mismatch::Amount {
+            field,
+            expected: record.expected.clone(),
+            actual,
+        }),
+        None => mismatches.push(Mismatch::InvalidAmount { field, value }),
+    }
+}
+
+/// Verifies every record with shurjopay and reports where the two sides disagree
+/// Verification authenticates automatically like `verify_payment`, the answers are not
+/// recorded in the plugin's `PaymentStore` nor reported to its observers
+pub fn reconcile(sp_instance: &mut ShurjopayPlugin, records: &[LocalRecord]) -> ReconciliationReport {
+    let mut report = ReconciliationReport::default();
+    for record in records {
+        let response = sp_instance.verify_payment_raw(record.order_id.clone());
+        let mismatches = compare(record, response.as_ref());
+        if awaits_outcome(record.expected_status) {
+            report.add_unverified(&record.order_id, mismatches);
+        } else {
+            report.add(&record.order_id, mismatches);
+        }
+    }
+    report
+}
@@ -0,0 +1,186 @@
+//!
+//! This module decodes the bodies of gateway responses.
+//!
+//! Shurjopay answers with a JSON object or a JSON array of objects, while
+//! proxies and load balancers in between may answer with an empty body or
+//! an HTML error page. `sp_code` is a number on some end points and a
+//! string on others. The functions here turn any of these into a typed
+//! value or an `SpError` and never panic.
+//!
+//! The `lenient_*` deserializers let the response models tolerate known
+//! fields changing between number and string; unknown fields are kept in
+//! the `extra` map of the models. A value that cannot be read as its field,
+//! e.g. an amount of `"786,50"`, fails decoding rather than being dropped.
+//!
+
+use serde::de::{DeserializeOwned, Deserializer};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::error::SpError;
+use crate::shurjopay::{SpCheckoutResponse, SpVerifyResponse};
+use crate::shurjopay_client::HttpResponse;
+
+/// An `sp_code` sent as a number or a string
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NumberOrString {
+    Number(i64),
+    String(String),
+}
+
+/// Deserializes a field sent as a string, number or boolean into a `String`
+pub fn string_or_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let value = Value::deserialize(deserializer)?;
+    scalar_to_string(&value).ok_or_else(|| serde::de::Error::custom(format!("expected a string, found {}", value)))
+}
+
+/// Deserializes an optional string field, numbers and booleans become strings
+/// Objects and arrays are kept as their JSON text
+pub fn lenient_string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Ok(match Value::deserialize(deserializer)? {
+        Value::Null => None,
+        value @ (Value::Array(_) | Value::Object(_)) => Some(value.to_string()),
+        value => scalar_to_string(&value),
+    })
+}
+
+/// Deserializes an optional number sent as a number or a numeric string
+/// `null` and an empty string are `None`, other values are an error
+pub fn lenient_f64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f64>, D::Error> {
+    let value = Value::deserialize(deserializer)?;
+    let number = match &value {
+        Value::Null => return Ok(None),
+        Value::String(text) if text.trim().is_empty() => return Ok(None),
+        Value::Number(number) => number.as_f64(),
+        Value::String(text) => text.trim().parse::<f64>().ok().filter(|number| number.is_finite()),
+        _ => None,
+    };
+    number
+        .map(Some)
+        .ok_or_else(|| serde::de::Error::custom(format!("expected a number, found {}", value)))
+}
+
+/// Deserializes an optional integer sent as a number or a numeric string
+/// `null` and an empty string are `None`, other values are an error
+pub fn lenient_i64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<i64>, D::Error> {
+    let value = Value::deserialize(deserializer)?;
+    let number = match &value {
+        Value::Null => return Ok(None),
+        Value::String(text) if text.trim().is_empty() => return Ok(None),
+        Value::Number(number) => number.as_i64(),
+        Value::String(text) => text.trim().parse::<i64>().ok(),
+        _ => None,
+    };
+    number
+        .map(Some)
+        .ok_or_else(|| serde::de::Error::custom(format!("expected an integer, found {}", value)))
+}
+
+fn scalar_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(text) => Some(text.clone()),
+        Value::Number(number) => Some(number.to_string()),
+        Value::Bool(flag) => Some(flag.to_string()),
+        _ => None,
+    }
+}
+
+/// Deserializes an optional `sp_code` sent as a number or a string into an `i64`
+pub fn number_or_string<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<i64>, D::Error> {
+    match Option::<NumberOrString>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(NumberOrString::Number(number)) => Ok(Some(number)),
+        Some(NumberOrString::String(text)) => text
+            .trim()
+            .parse::<i64>()
+            .map(Some)
+            .map_err(|_| serde::de::Error::custom(format!("invalid sp_code `{}`", text))),
+    }
+}
+
+/// Returns the JSON objects of a response body
+/// An object body is one item, an array body is its elements in order
+/// Empty bodies, HTML pages and other non-JSON bodies are `SpError::Gateway`
+pub fn items(response: &HttpResponse) -> Result<Vec<Value>, SpError> {
+    let body = response.http_body.trim();
+    if body.is_empty() {
+        return Err(gateway_error(response));
+    }
+    match serde_json::from_str::<Value>(body) {
+        Ok(Value::Array(items)) => Ok(items),
+        Ok(item @ Value::Object(_)) => Ok(vec![item]),
+        _ => Err(gateway_error(response)),
+    }
+}
+
+/// Decodes every item of a successful response into `T`
+/// An item that does not fit `T` is `SpError::Decode` with the reason
+pub fn decode_all<T: DeserializeOwned>(response: &HttpResponse) -> Result<Vec<T>, SpError> {
+    if !(200..300).contains(&response.http_code) {
+        return Err(gateway_error(response));
+    }
+    items(response)?
+        .into_iter()
+        .map(|item| serde_json::from_value(item).map_err(|err| decode_error(response, err)))
+        .collect()
+}
+
+/// Decodes the first item of a successful response into `T`
+pub fn decode<T: DeserializeOwned>(response: &HttpResponse) -> Result<T, SpError> {
+    decode_all(response)?
+        .into_iter()
+        .next()
+        .ok_or_else(|| gateway_error(response))
+}
+
+/// Decodes a checkout response, keeping the raw body in `raw_body`
+pub fn decode_checkout(response: &HttpResponse) -> Result<SpCheckoutResponse, SpError> {
+    let mut checkout_response: SpCheckoutResponse = decode(response)?;
+    checkout_response.raw_body = Some(response.http_body.clone());
+    Ok(checkout_response)
+}
+
+/// Decodes a verification or payment status response, keeping the raw body in `raw_body`
+///
+/// The first item carrying an `sp_code` is the result, whatever the http status.
+/// A failed verification only has `sp_code` and `message`; the message becomes `sp_message`.
+pub fn decode_verification(response: &HttpResponse) -> Result<SpVerifyResponse, SpError> {
+    let mut item = items(response)?
+        .into_iter()
+        .find(|item| item.get("sp_code").map_or(false, |sp_code| !sp_code.is_null()))
+        .ok_or_else(|| gateway_error(response))?;
+    if let Value::Object(fields) = &mut item {
+        if fields.get("sp_message").map_or(true, Value::is_null) {
+            if let Some(message) = fields.remove("message") {
+                fields.insert("sp_message".to_string(), message);
+            }
+        }
+    }
+    let mut verify_response: SpVerifyResponse = serde_json::from_value(item).map_err(|err| decode_error(response, err))?;
+    verify_response.raw_body = Some(response.http_body.clone());
+    Ok(verify_response)
+}
+
+/// Returns the `message` of an error response, e.g. of a rejected token request
+pub fn error_message(response: &HttpResponse) -> Option<String> {
+    items(response)
+        .ok()?
+        .into_iter()
+        .find_map(|item| item.get("message")?.as_str().map(str::to_string))
+}
+
+fn decode_error(response: &HttpResponse, err: serde_json::Error) -> SpError {
+    SpError::Decode {
+        http_code: response.http_code,
+        message: err.to_string(),
+        body: response.http_body.clone(),
+    }
+}
+
+fn gateway_error(response: &HttpResponse) -> SpError {
+    SpError::Gateway {
+        http_code: response.http_code,
+        body: response.http_body.clone(),
+    }
+}
@@ -8,10 +8,12 @@
 //! 
 
 #![allow(dead_code, unused_variables, non_snake_case, non_camel_case_types)]
+#![allow(clippy::needless_return, clippy::new_without_default, clippy::too_many_arguments)]
+#![allow(clippy::clone_on_copy, clippy::bool_comparison, clippy::needless_bool, clippy::redundant_pattern_matching)]
+#![allow(clippy::unnecessary_unwrap, clippy::useless_format, clippy::iter_nth_zero)]
 
-/// Standard library to save `key` and `value` as Hashmap
+// Standard library to save `key` and `value` as Hashmap
 // extern crate std;
-use std::collections::HashMap;
 
 /// The `chrono` crate is included to calculate timeout using datetime 
 // extern crate chrono;
@@ -36,11 +38,37 @@ use serde_json:: Result;
 use reqwest::blocking::Client;
 // use reqwest::Error;
 // use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT, CONTENT_TYPE};
-use reqwest::header:: CONTENT_TYPE;
 
 /// This module handles http request verifications
 use super::shurjopay_client;//::{HttpResponse,is_response_valid};
 
+/// Local ledger of payments
+use crate::payment_store::{PaymentStore, StateTransition};
+/// Error reported by the last failed request
+use crate::error::SpError;
+/// Merchant order id generator
+use crate::order_id::OrderIdGenerator;
+/// Fails fast while an end point keeps failing
+use crate::circuit_breaker::CircuitBreaker;
+/// Client side rate limits of the end points
+use crate::rate_limit::{self, RateLimitConfig, RateLimiter};
+/// Settings of the http client
+use crate::http_config::HttpConfig;
+/// How gateway requests are sent
+use crate::transport::{ReqwestTransport, Transport, TransportRequest};
+/// Metrics of gateway operations
+use crate::metrics::{self, Operation};
+/// Payment lifecycle notifications
+use crate::observer::PaymentObserver;
+/// Decoding of gateway response bodies
+use crate::response;
+/// Checkouts in flight of idempotent checkouts
+use crate::idempotency::CheckoutGuard;
+/// Bangladeshi mobile numbers of customers
+use crate::phone::PhoneNumber;
+use std::time::Instant;
+use std::sync::Arc;
+
 // to redirect to payment link
 // use webbrowser;
 
@@ -52,6 +80,7 @@ pub struct SpAuthToken {
     pub store_id: i32,
     pub execute_url: String,
     pub token_type: String,
+    #[serde(deserialize_with = "response::string_or_number")]
     pub sp_code: String,
     pub message: String,
     pub token_create_time: String,
@@ -60,7 +89,8 @@ pub struct SpAuthToken {
 
 /// Shurjopay checkout data structure
 /// This structure implements `Serialize`, `Deserialize`, `Debug` and `Clone` functions
-/// Each element of the structure must hold a value before checking out
+/// Each element of the structure must hold a value before checking out,
+/// except the optional discount fields which are only sent when set
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SpCheckout {
     pub prefix:String,
@@ -77,6 +107,12 @@ pub struct SpCheckout {
     pub customer_city:String,
     pub customer_post_code:String,
     pub client_ip:String,
+    /// Discount taken off `amount` by shurjopay, see `discount::AppliedDiscount`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub discount_amount:Option<String>,
+    /// Percentage of the discount, reported back as `disc_percent`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disc_percent:Option<String>,
 }
 
 impl Default for SpCheckout
@@ -100,69 +136,127 @@ impl Default for SpCheckout
             customer_city: "".to_string(),
             customer_post_code: "".to_string(),
             client_ip: "".to_string(),
+            discount_amount: None,
+            disc_percent: None,
         }
     }
 }
 
 
 /// Shurjopay checkout response data structure
-/// This structure implements `Serialize`, `Deserialize`, `Debug` and `Clone` functions
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone` and `PartialEq` functions
 /// `customer_email` can hold `null` value
-#[derive(Serialize, Deserialize, Debug, Clone)]
+/// Unknown fields are kept in `extra` and serialized back with the known fields
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct SpCheckoutResponse {
-   checkout_url: String,
-   amount: String,
-   currency: String,
-   sp_order_id: String,
-   customer_order_id: String,
-   customer_name: String,
-   customer_address: String,
-   customer_city: String,
-   customer_phone: String,
-   customer_email: serde_json::value::Value,
-   client_ip: String,
-   intent: String,
-   transactionStatus: String,
+   #[serde(deserialize_with = "response::string_or_number")]
+   pub checkout_url: String,
+   #[serde(deserialize_with = "response::string_or_number")]
+   pub amount: String,
+   #[serde(deserialize_with = "response::string_or_number")]
+   pub currency: String,
+   #[serde(deserialize_with = "response::string_or_number")]
+   pub sp_order_id: String,
+   #[serde(deserialize_with = "response::string_or_number")]
+   pub customer_order_id: String,
+   #[serde(deserialize_with = "response::string_or_number")]
+   pub customer_name: String,
+   #[serde(deserialize_with = "response::string_or_number")]
+   pub customer_address: String,
+   #[serde(deserialize_with = "response::string_or_number")]
+   pub customer_city: String,
+   #[serde(deserialize_with = "response::string_or_number")]
+   pub customer_phone: String,
+   pub customer_email: serde_json::value::Value,
+   #[serde(deserialize_with = "response::string_or_number")]
+   pub client_ip: String,
+   #[serde(deserialize_with = "response::string_or_number")]
+   pub intent: String,
+   #[serde(deserialize_with = "response::string_or_number")]
+   pub transactionStatus: String,
+   /// Fields shurjopay sent that this structure does not know yet
+   #[serde(flatten)]
+   pub extra: serde_json::Map<String, serde_json::Value>,
+   /// Raw JSON body the response was decoded from, it is not serialized
+   #[serde(skip)]
+   pub raw_body: Option<String>,
 }
 
 /// Shurjopay payment verifiacation data structure
 /// This structure implements `Serialize`, `Deserialize`, `Debug` and `Clone` functions
 /// `discsount_amount` , `card_holder_name`, `card_number, `email`, `transaction_status`, 
 /// `method`, `value1`, `value2`, `value3`, `value4` can hold `null` value
+/// Fields sent as a number instead of a string or the other way around are converted,
+/// unknown fields are kept in `extra`
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SpVerifyResponse {
+    #[serde(default, deserialize_with = "response::number_or_string")]
     pub sp_code:Option<i64>,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "response::lenient_i64")]
     pub id:Option<i64>,
+    #[serde(default, deserialize_with = "response::lenient_string")]
     pub order_id:Option<String>,
+    #[serde(default, deserialize_with = "response::lenient_string")]
     pub currency:Option<String>,
+    #[serde(default, deserialize_with = "response::lenient_f64")]
     pub amount:Option<f64>,
+    #[serde(default, deserialize_with = "response::lenient_f64")]
     pub payable_amount:Option<f64>,
+    #[serde(default, deserialize_with = "response::lenient_f64")]
     pub discsount_amount:Option<f64>,
+    #[serde(default, deserialize_with = "response::lenient_f64")]
     pub disc_percent:Option<f64>,
+    #[serde(default, deserialize_with = "response::lenient_string")]
     pub received_amount:Option<String>,
+    #[serde(default, deserialize_with = "response::lenient_f64")]
     pub usd_amt:Option<f64>,
+    #[serde(default, deserialize_with = "response::lenient_f64")]
     pub usd_rate:Option<f64>,
+    #[serde(default, deserialize_with = "response::lenient_string")]
     pub card_holder_name:Option<String>,
+    #[serde(default, deserialize_with = "response::lenient_string")]
     pub card_number:Option<String>,
+    #[serde(default, deserialize_with = "response::lenient_string")]
     pub phone_no:Option<String>,
+    #[serde(default, deserialize_with = "response::lenient_string")]
     pub bank_trx_id:Option<String>,
+    #[serde(default, deserialize_with = "response::lenient_string")]
     pub invoice_no:Option<String>,
+    #[serde(default, deserialize_with = "response::lenient_string")]
     pub bank_status:Option<String>,
+    #[serde(default, deserialize_with = "response::lenient_string")]
     pub customer_order_id:Option<String>,
+    #[serde(default, deserialize_with = "response::lenient_string")]
     pub sp_message:Option<String>,
+    #[serde(default, deserialize_with = "response::lenient_string")]
     pub name:Option<String>,
+    #[serde(default, deserialize_with = "response::lenient_string")]
     pub email:Option<String>,
+    #[serde(default, deserialize_with = "response::lenient_string")]
     pub address:Option<String>,
+    #[serde(default, deserialize_with = "response::lenient_string")]
     pub city:Option<String>,
+    #[serde(default, deserialize_with = "response::lenient_string")]
     pub value1:Option<String>,
+    #[serde(default, deserialize_with = "response::lenient_string")]
     pub value2:Option<String>,
+    #[serde(default, deserialize_with = "response::lenient_string")]
     pub value3:Option<String>,
+    #[serde(default, deserialize_with = "response::lenient_string")]
     pub value4:Option<String>,
+    #[serde(default, deserialize_with = "response::lenient_string")]
     pub transaction_status:Option<String>,
+    #[serde(default, deserialize_with = "response::lenient_string")]
     pub method:Option<String>,
+    #[serde(default, deserialize_with = "response::lenient_string")]
     pub date_time:Option<String>,
-    
+    /// Fields shurjopay sent that this structure does not know yet
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+    /// Raw JSON body the response was decoded from, it is not serialized
+    #[serde(skip)]
+    pub raw_body: Option<String>,
+
 }
 
 /// Shurjopay payment another verifiacation data structure
@@ -200,6 +294,13 @@ pub struct SpConfig {
     pub default_return_url: String,
     pub default_cancel_url: String,
     pub default_client_ip: String,
+    /// Prefix of generated merchant order ids, `sp` if left out
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
+    #[serde(default)]
+    pub http: HttpConfig,
 }
 
 impl Default for SpConfig
@@ -219,10 +320,17 @@ impl Default for SpConfig
             default_return_url: "https://sandbox.shurjopayment.com/response".to_string(), 
             default_cancel_url: "https://sandbox.shurjopayment.com/response".to_string(), 
             default_client_ip: "0.0.0.0".to_string() ,
+            prefix: default_prefix(),
+            rate_limit: RateLimitConfig::default(),
+            http: HttpConfig::default(),
         }    
     }
 }
 
+/// Prefix of generated merchant order ids when none is configured
+fn default_prefix() -> String {
+    "sp".to_string()
+}
 
 /// This the model user will create as a Shurjopay plugin instance
 /// This structure should be declared as mutable
@@ -234,6 +342,7 @@ impl Default for SpConfig
 #[derive(Debug, Clone)]
 pub struct ShurjopayPlugin{
     client: Option<Client>,
+    client_http: Option<HttpConfig>,
     pub config: Option<SpConfig>,
     pub auth_token: Option<SpAuthToken>,
     pub checkout_response: Option<SpCheckoutResponse>,
@@ -241,6 +350,15 @@ pub struct ShurjopayPlugin{
     pub check_response: Option<SpVerifyResponse>,
     pub token_create_time: Option<NaiveDateTime>,
     pub token_expire_time: Option<NaiveDateTime>,
+    pub last_error: Option<SpError>,
+    /// State change the last verification or status check made in the `PaymentStore`
+    pub verify_transition: Option<StateTransition>,
+    store: Option<Arc<dyn PaymentStore>>,
+    checkouts: Arc<CheckoutGuard>,
+    breaker: Arc<CircuitBreaker>,
+    limiter: Arc<RateLimiter>,
+    transport: Option<Arc<dyn Transport>>,
+    observers: Vec<Arc<dyn PaymentObserver>>,
 }
 
 /// A trait to initialize 'Shurjopay Configuration' with function overloadding.
@@ -254,9 +372,11 @@ impl ShurjopayPlugin {
     /// This is a constructor to initiate `null` instance of `ShurjopayPlugin`
     /// returns `ShurjopayPlugin`
     pub fn new() -> Self {
-        let http_client = Client::new();
+        let http_config = HttpConfig::default();
+        let http_client = http_config.build_client().ok();
         ShurjopayPlugin{            
-            client : Some(http_client),
+            client : http_client,
+            client_http : Some(http_config),
             config : None,
             auth_token: None,
             checkout_response: None,
@@ -264,9 +384,95 @@ impl ShurjopayPlugin {
             check_response: None,
             token_create_time : None,
             token_expire_time : None,
+            last_error: None,
+            verify_transition: None,
+            store: None,
+            checkouts: Arc::new(CheckoutGuard::default()),
+            breaker: Arc::new(CircuitBreaker::default()),
+            limiter: Arc::new(RateLimiter::new()),
+            transport: None,
+            observers: Vec::new(),
         }
     }
 
+    /// This function sets the `PaymentStore` where every checkout request, checkout response
+    /// and verification result is recorded
+    pub fn set_payment_store(&mut self, store: Arc<dyn PaymentStore>)
+    {
+        self.store = Some(store);
+        // Orders in flight belong to the ledger they are recorded in
+        self.checkouts = Arc::new(CheckoutGuard::default());
+    }
+
+    /// This function replaces the circuit breaker of the gateway end points
+    pub fn set_circuit_breaker(&mut self, breaker: Arc<CircuitBreaker>)
+    {
+        self.breaker = breaker;
+    }
+
+    /// This function returns the circuit breaker of the gateway end points
+    /// `circuit_breaker().states()` reports the circuit of every end point for health checks
+    pub fn circuit_breaker(&self) -> Arc<CircuitBreaker>
+    {
+        self.breaker.clone()
+    }
+
+    /// This function shares a rate limiter with other plugins, e.g. of other worker threads,
+    /// so their requests count against the same `SpConfig::rate_limit`
+    pub fn set_rate_limiter(&mut self, limiter: Arc<RateLimiter>)
+    {
+        self.limiter = limiter;
+    }
+
+    /// This function returns the rate limiter of the gateway end points
+    pub fn rate_limiter(&self) -> Arc<RateLimiter>
+    {
+        self.limiter.clone()
+    }
+
+    /// This function sends gateway requests through `transport` instead of the http client,
+    /// e.g. a `cassette::RecordingTransport` or `cassette::ReplayTransport`
+    pub fn set_transport(&mut self, transport: Arc<dyn Transport>)
+    {
+        self.transport = Some(transport);
+    }
+
+    /// This function returns the transport set with `set_transport`
+    pub fn transport(&self) -> Option<Arc<dyn Transport>>
+    {
+        self.transport.clone()
+    }
+
+    /// This function registers an observer of token refreshes, checkouts, verifications and errors
+    /// Observers are called synchronously in the order they were added
+    pub fn add_observer(&mut self, observer: Arc<dyn PaymentObserver>)
+    {
+        self.observers.push(observer);
+    }
+
+    /// This function notifies the observers of the error a gateway operation ended with
+    fn notify_error(&self)
+    {
+        if let Some(err) = &self.last_error {
+            for observer in &self.observers {
+                observer.on_error(err);
+            }
+        }
+    }
+
+    /// This function returns the `PaymentStore` of the plugin if one is set, or the
+    /// `MemoryPaymentStore` created by the first `make_payment_idempotent` without one
+    pub fn payment_store(&self) -> Option<Arc<dyn PaymentStore>>
+    {
+        self.store.clone().or_else(|| self.checkouts.fallback())
+    }
+
+    /// This function returns the idempotency state shared by the clones of the plugin
+    pub(crate) fn checkout_guard(&self) -> Arc<CheckoutGuard>
+    {
+        self.checkouts.clone()
+    }
+
 
 
     /// This function will set default value for `ShurjopayPlugin`'s Config
@@ -355,15 +561,16 @@ impl ShurjopayPlugin {
                 default_cancel_url: std::env::var("DEFAULT_CANCEL_URL").unwrap(),
                 // default_client_ip: std::env::var("DEFAULT_CLIENT_IP").unwrap(),
                 default_client_ip: ip_address ,
+                prefix: std::env::var("SP_PREFIX").unwrap_or_else(|_| default_prefix()),
                 ..Default::default()
             };
     
             self.config  = Some(sp_config);
-            println!("configuration is set from .env file");
+            eprintln!("configuration is set from .env file");
         }
         else 
         {
-            println!(".env file not available & config is not set");
+            eprintln!(".env file not available & config is not set");
             self.config = None;
         }
         
@@ -387,7 +594,7 @@ impl ShurjopayPlugin {
     {
         let sp_checkout =SpCheckout
         {
-          prefix: "sp".to_string(),
+          prefix: self.config.clone().unwrap().prefix,
           token: "".to_string(),//self.auth_token.clone().unwrap().token,
           return_url: self.config.clone().unwrap().default_return_url,
           cancel_url:self.config.clone().unwrap().default_cancel_url,
@@ -401,11 +608,22 @@ impl ShurjopayPlugin {
           customer_city,
           customer_post_code,
           client_ip:self.config.clone().unwrap().default_client_ip,
+          discount_amount: None,
+          disc_percent: None,
         };
         // println!("make payment client ip address: {}", sp_checkout.clone().client_ip);
         return sp_checkout;
     }
 
+    /// This function generates a unique merchant order id starting with the configured prefix
+    pub fn generate_order_id(&self) -> std::result::Result<String, SpError>
+    {
+        match &self.config {
+            Some(spay) => Ok(OrderIdGenerator::from_config(spay)?.generate()),
+            None => Err(SpError::Config("Shurjopay Configuration is not set yet!".to_string())),
+        }
+    }
+
    
 }
 
@@ -423,7 +641,7 @@ impl ShurjopayPlugin{
             }
             else 
             {
-                println!("oder id not found");
+                eprintln!("oder id not found");
                 return None;                
             }
             
@@ -439,7 +657,7 @@ impl ShurjopayPlugin{
         let checkout_url = self.make_payment_no_auto_redirect(checkout_item);
 
         if webbrowser::open(checkout_url.clone().unwrap().as_str()).is_ok() {
-            println!("Opened '{}' successfully.", checkout_url.clone().unwrap());
+            eprintln!("Opened '{}' successfully.", checkout_url.clone().unwrap());
         }
         return checkout_url.clone();
     }
@@ -448,6 +666,14 @@ impl ShurjopayPlugin{
     /// It takes `SpCheckout` Struct as input
     /// return checkout_url
     pub fn make_payment_no_auto_redirect(&mut self, checkout_item: SpCheckout)->Option<String> {
+        return self.make_payment_checkout(checkout_item).map(|response| response.checkout_url);
+    }
+
+    /// This function automatically authenticates and commits secure checkout
+    /// It takes `SpCheckout` Struct as input
+    /// It returns the whole checkout response, including `sp_order_id` and `customer_order_id`
+    /// Use it instead of `get_order_id` when checkouts run concurrently
+    pub fn make_payment_checkout(&mut self, checkout_item: SpCheckout)->Option<SpCheckoutResponse> {
         if let Some(_) = self.verify_auth_token()
         {
             let auth_token_val = self.auth_token.clone().unwrap();
@@ -456,11 +682,13 @@ impl ShurjopayPlugin{
                 store_id: auth_token_val.store_id.to_string(),
                 ..checkout_item
             };
-            return self.secure_ckeckout(checkout_mgs);
+            return self.secure_checkout_response(checkout_mgs);
         }
         return None;
     }
 
+    /// This function returns the `sp_order_id` of the last checkout of this instance
+    /// Concurrent checkouts on a shared instance overwrite it, `make_payment_checkout` returns it with the checkout
     pub fn get_order_id(&self) -> Option<String>
     {
         if self.checkout_response.clone().is_some()
@@ -471,112 +699,274 @@ impl ShurjopayPlugin{
         }
         else 
         {
-            println!("SP order ID not found");
+            eprintln!("SP order ID not found");
             return None;
         }
     }
 
     
     /// This function is called to verify payments only once
-    /// Further verification can be done by `checkPayments` function
+    /// Further verification can be done by `check_payment` function
     pub fn verify_payment_id(&mut self,order_id: String)-> Option<SpVerifyResponse> {
+        let end_point = self.config.as_ref().map(|spay| spay.verification_end_point.clone()).unwrap_or_default();
+        return self.post_order_id(Operation::VerifyPayment, end_point, order_id, true);
+    }
+
+    /// This function verifies a payment like `verify_payment`, but the result is neither
+    /// recorded in the `PaymentStore` nor reported to the observers
+    /// It is meant for read only reports such as `reconciliation::reconcile`
+    pub fn verify_payment_raw(&mut self, order_id: String)-> Option<SpVerifyResponse> {
+        self.verify_auth_token()?;
+        let end_point = self.config.as_ref().map(|spay| spay.verification_end_point.clone()).unwrap_or_default();
+        return self.post_order_id(Operation::VerifyPayment, end_point, order_id, false);
+    }
+
+    /// This function checks payment details and status of an order any number of times
+    /// This function automatically authenticates if requires
+    pub fn check_payment(&mut self, order_id: Option<String>)-> Option<SpVerifyResponse> {
+        if let Some(_) = self.verify_auth_token()
+        {
+            if let Some(order_id) = order_id
+            {
+                let end_point = self.config.as_ref().map(|spay| spay.payment_status_end_point.clone()).unwrap_or_default();
+                self.check_response = self.post_order_id(Operation::PaymentStatus, end_point, order_id, true);
+                return self.check_response.clone();
+            }
+            eprintln!("oder id not found");
+        }
+        return None;
+    }
+
+    /// This function posts an order id to the verification or payment status end point
+    /// and records the metrics of `operation`
+    /// With `record` the result is recorded in the `PaymentStore` and reported to the observers
+    fn post_order_id(&mut self, operation: Operation, end_point: String, order_id: String, record: bool)-> Option<SpVerifyResponse> {
+        let started = Instant::now();
+        self.verify_transition = None;
+        let response = self.send_order_id(end_point, order_id);
+        let sp_code = response.as_ref().and_then(|verified| verified.sp_code).map(|sp_code| sp_code.to_string());
+        metrics::record_request(operation, self.last_error.as_ref(), sp_code, started.elapsed());
+        if record {
+            if let Some(verify_response) = &response {
+                self.record_verification(verify_response);
+            }
+            self.notify_error();
+        }
+        return response;
+    }
+
+    /// This function posts an order id to an end point and maps the response to `SpVerifyResponse`
+    fn send_order_id(&mut self, end_point: String, order_id: String)-> Option<SpVerifyResponse> {
+        self.last_error = None;
         let sp_ins = self.clone();
         if let Some(spay) = sp_ins.config {
-            // Checking if client is valid or not
-            if let Some(client) = sp_ins.client{
+            {
                 // Constructing url, header and body
-                let url = format!("{}{}/",spay.post_default_address, spay.verification_end_point);
-                // let body = format!("{{\"order_id\": \"{}\"}}", self.checkout_response.clone().unwrap().sp_order_id);
-                let body = format!("{{\"order_id\": \"{}\"}}",order_id);
-                // let body = "{\"order_id\": \"sp636384e391650\"}";
+                let url = format!("{}{}/",spay.post_default_address, end_point);
+                let body = serde_json::json!({ "order_id": order_id }).to_string();
                 let header =format!{"{} {}", self.auth_token.clone().unwrap().token_type, self.auth_token.clone().unwrap().token };
                 
                 // Making HTTP request
-                let response = client.post(url.as_str())
-                                .header(CONTENT_TYPE, "application/json")
-                                .header("Authorization", header)
-                                .body(body)
-                                .send();
+                let request = TransportRequest::post_json(url, body)
+                                .header("Authorization", header);
 
                 // Checking if respons is valid or not
-                if let Some(responseData) = shurjopay_client::is_response_valid(response) {
-  
+                if let Some(responseData) = self.send_request(&end_point, request) {
                     // Mapping JSON string to structure
-
-                    let modified_http_body =remove_first_and_last_ch(responseData.http_body.as_str());
-                    // println!("modified http body: {:?}", modified_http_body);
-
-                    let mut verify_json_option = SpVerifyResponse::new();
-                    verify_json_option.string_to_json(modified_http_body);
-
-
-            
-                    if verify_json_option.string_to_json(modified_http_body) == true
-                    {
-                        // println!("verify json : {:#?}", verify_json_option);
-                        return Some(verify_json_option);
-                    }
-                    else 
-                    {
-                        let mut verify_json_option2 = SpVerifyResponse2::new();
-                        verify_json_option2.string_to_json(modified_http_body);
-
-                        verify_json_option.convert_sp_response2_to_sp_respose(verify_json_option2);
-                        
-                        return Some(verify_json_option);
-
+                    match response::decode_verification(&responseData) {
+                        Ok(verify_response) => return Some(verify_response),
+                        Err(err) => {
+                            eprintln!("{:?}", responseData);
+                            self.last_error = Some(err);
+                        }
                     }
-                
                 }
                 else {
-                    println!("response is not valid");
+                    eprintln!("response is not valid");
                 }
             }
         }
+        else
+        {
+            self.last_error = Some(SpError::Config("Shurjopay Configuration is not set yet!".to_string()));
+        }
         return None;
     }
 
+    /// This function returns the http client, rebuilt if `SpConfig::http` changed since it was built
+    fn http_client(&mut self) -> Option<Client>
+    {
+        let http_config = self.config.as_ref().map(|spay| spay.http.clone()).unwrap_or_default();
+        if self.client.is_none() || self.client_http.as_ref() != Some(&http_config) {
+            match http_config.build_client() {
+                Ok(client) => {
+                    self.client = Some(client);
+                    self.client_http = Some(http_config);
+                }
+                Err(err) => {
+                    eprintln!("{}", err);
+                    self.last_error = Some(err);
+                    return None;
+                }
+            }
+        }
+        self.client.clone()
+    }
+
+    /// This function sends a request to a gateway end point through the rate limiter, the circuit breaker
+    /// and the transport set with `set_transport`, or the http client if none is set
+    /// Requests that could not be sent and server errors count as failures of the end point
+    /// It returns `None` and sets `last_error` if no response was received
+    fn send_request(&mut self, end_point: &str, request: TransportRequest) -> Option<shurjopay_client::HttpResponse>
+    {
+        // The client is built before a slot or a half-open probe is taken, so that
+        // failing to build it leaves the limiter and the breaker untouched
+        let transport: Arc<dyn Transport> = match self.transport.clone() {
+            Some(transport) => transport,
+            None => Arc::new(ReqwestTransport::new(self.http_client()?)),
+        };
+        let rate_limit = self.config.as_ref().map(|spay| spay.rate_limit.clone()).unwrap_or_default();
+        let allowed = self.limiter.acquire(&rate_limit, end_point)
+            .and_then(|_| self.breaker.allow(end_point));
+        if let Err(err) = allowed {
+            eprintln!("{}", err);
+            self.last_error = Some(err);
+            return None;
+        }
+        let response = transport.send(&request);
+        match &response {
+            Ok(resp) if resp.status < 500 => self.breaker.record_success(end_point),
+            _ => self.breaker.record_failure(end_point),
+        }
+        match response {
+            Ok(resp) => {
+                if resp.status == 429 || resp.status == 503 {
+                    if let Some(retry_after) = resp.header("Retry-After").and_then(rate_limit::parse_retry_after) {
+                        self.limiter.pause(end_point, retry_after);
+                    }
+                }
+                Some(shurjopay_client::HttpResponse { http_code: resp.status, http_body: resp.body })
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                self.last_error = Some(err);
+                None
+            }
+        }
+    }
+
     
     /// This function sends a checkout structure to the Shurjopay server
     /// It returns `Option<checkout_url>` for the frontend
     pub fn secure_ckeckout(&mut self, checkout_item: SpCheckout)->Option<String> {
+        return self.secure_checkout_response(checkout_item).map(|response| response.checkout_url);
+    }
+
+    /// This function sends a checkout structure to the Shurjopay server
+    /// It returns the whole checkout response
+    pub fn secure_checkout_response(&mut self, checkout_item: SpCheckout)->Option<SpCheckoutResponse> {
+        let started = Instant::now();
+        let response = self.post_checkout(checkout_item);
+        metrics::record_request(Operation::SecureCheckout, self.last_error.as_ref(), None, started.elapsed());
+        self.notify_error();
+        return response;
+    }
+
+    /// This function posts a checkout structure to the secure payment end point
+    /// `customer_phone` must be a Bangladeshi mobile number and is sent in the national format
+    fn post_checkout(&mut self, checkout_item: SpCheckout)->Option<SpCheckoutResponse> {
+        self.last_error = None;
+        let checkout_item = match PhoneNumber::parse(&checkout_item.customer_phone) {
+            Ok(phone) => SpCheckout { customer_phone: phone.to_string(), ..checkout_item },
+            Err(err) => {
+                eprintln!("{}", err);
+                self.last_error = Some(err);
+                return None;
+            }
+        };
         let sp_ins = self.clone();
         if let Some(spay) = sp_ins.config {
             // println!("{:?}", spay);
-            if let Some(client) = sp_ins.client{
+            {
                 let url = format!("{}{}/",spay.post_default_address, spay.secure_payment_end_point);
                 let body_json = serde_json::to_string(&checkout_item);
                 let header =format!{"{} {}", self.auth_token.clone().unwrap().token_type, self.auth_token.clone().unwrap().token };
+
+                // A paid order is never sent to checkout again, other ledger errors do not stop the payment
+                if let Some(store) = self.payment_store() {
+                    match store.record_checkout_request(&checkout_item) {
+                        Ok(_) => {}
+                        Err(err @ SpError::AlreadyPaid(_)) => {
+                            self.last_error = Some(err);
+                            return None;
+                        }
+                        Err(err) => eprintln!("{}", err),
+                    }
+                }
                 
                 // Making HTTP request
-                let response = client.post(url.as_str())
-                                .header(CONTENT_TYPE, "application/json")
-                                .header("Authorization", header)
-                                .body(body_json.unwrap())
-                                .send();
+                let request = TransportRequest::post_json(url, body_json.unwrap())
+                                .header("Authorization", header);
 
                 // Checking if respons is valid or not
-                if let Some(responseData) = shurjopay_client::is_response_valid(response) {
+                if let Some(responseData) = self.send_request(&spay.secure_payment_end_point, request) {
                     // println!("Checkout Response: {:?}", responseData);
                     // Mapping JSON string to structure
-                    let checkout_json_option: Option<SpCheckoutResponse> = unwrap_json(&responseData);
+                    let checkout_json_option = response::decode_checkout(&responseData);
                     
                     // Checking JSON structure is matched or not
-                    if let Some(valid_json_data) =  checkout_json_option {
+                    if let Ok(valid_json_data) =  checkout_json_option {
                         self.checkout_response = Some(valid_json_data.clone());
+                        self.record_checkout_response(&checkout_item.order_id, Some(&valid_json_data));
+                        for observer in &self.observers {
+                            observer.on_checkout_created(&valid_json_data);
+                        }
                         // println!("Checkout Response: {:?}", valid_json_data);
-                        return Some(valid_json_data.checkout_url);
+                        return Some(valid_json_data);
                     } else {
                         self.checkout_response = None;
-                        println!("{:?}", responseData);                        
+                        eprintln!("{:?}", responseData);                        
+                        self.last_error = Some(SpError::Gateway{ http_code: responseData.http_code, body: responseData.http_body });
                     }                    
                 }
+                self.record_checkout_response(&checkout_item.order_id, None);
             }
         }
+        else
+        {
+            self.last_error = Some(SpError::Config("Shurjopay Configuration is not set yet!".to_string()));
+        }
         return None;
     }
 
 
+    /// This function records a checkout response in the `PaymentStore` if one is set
+    /// `None` records a failed checkout
+    fn record_checkout_response(&self, order_id: &str, response: Option<&SpCheckoutResponse>)
+    {
+        if let Some(store) = self.payment_store() {
+            if let Err(err) = store.record_checkout_response(order_id, response) {
+                eprintln!("{}", err);
+            }
+        }
+    }
+
+    /// This function records a verification result in the `PaymentStore` if one is set
+    /// and notifies the observers
+    fn record_verification(&mut self, response: &SpVerifyResponse)
+    {
+        for observer in &self.observers {
+            observer.on_verified(response);
+        }
+        if let Some(store) = self.payment_store() {
+            match store.apply_verification(response) {
+                Ok(applied) => self.verify_transition = applied.and_then(|(_, transition)| transition),
+                Err(err) => eprintln!("{}", err),
+            }
+        }
+    }
+
+
     /// This function gets auth token if no token is available
     /// or the existing token is expired
     /// This function return Option<auth_token_as_string>, if it successfully retrives a auth token
@@ -589,14 +979,17 @@ impl ShurjopayPlugin{
             Some(auth_token) => {
                 // Cheking token expiration validity
                 if self.is_token_valid() {
+                    metrics::record_token_cache_hit();
                     return Some(auth_token.token);
                 }else {
                     // If token not valid
                     self.auth_token = None;
-                    return self.verify_auth_token();
+                    metrics::record_token_refresh("expired");
+                    return self.get_auth_token();
                 }
             }
             None => {
+                metrics::record_token_refresh("missing");
                 let token_value = self.get_auth_token();
                 return token_value
             },
@@ -612,7 +1005,7 @@ impl ShurjopayPlugin{
         // println!("Current unix time: {:?}", current_unix_time);
 
         // Cenverting Datetime to unix timestamp
-        let token_expires_at = self.token_expire_time.clone().unwrap().timestamp();
+        let token_expires_at = self.token_expire_time.clone().unwrap().and_utc().timestamp();
         // println!("Token Expire Time: {:?}", token_expires_at);
 
         // Coparing token expiration time with current time setting
@@ -626,54 +1019,66 @@ impl ShurjopayPlugin{
     /// It returns `Option<auth_token>`
     pub fn get_auth_token(&mut self) -> Option<String> 
     {
+        let started = Instant::now();
+        let token = self.request_auth_token();
+        let sp_code = token.as_ref().and(self.auth_token.as_ref()).map(|auth_token| auth_token.sp_code.clone());
+        metrics::record_request(Operation::GetAuthToken, self.last_error.as_ref(), sp_code, started.elapsed());
+        self.notify_error();
+        return token;
+    }
+
+    /// This function posts the merchant credentials to the token end point
+    fn request_auth_token(&mut self) -> Option<String> 
+    {
+        self.last_error = None;
         let sp_ins = self.clone();
         if let Some(spay) = sp_ins.config {
             // println!("{:?}", spay);
-            if let Some(client) = sp_ins.client{
+            {
                 let url = format!("{}{}/",spay.post_default_address, spay.token_end_point);
 
-                let mut body = HashMap::new();
-                body.insert("username", spay.sp_user);
-                body.insert("password", spay.sp_pass);
+                let body = serde_json::json!({
+                    "username": spay.sp_user,
+                    "password": spay.sp_pass,
+                });
 
                 // Making HTTP request
-                let response = client.post(url.as_str())
-                                .header(CONTENT_TYPE, "application/json")
-                                .json(&body)
-                                .send();
+                let request = TransportRequest::post_json(url, body.to_string());
                 // Checking if respons is valid or not
-                if let Some(responseData) = shurjopay_client::is_response_valid(response) 
+                if let Some(responseData) = self.send_request(&spay.token_end_point, request) 
                 {
-                    let auth_json_option: Option<SpAuthToken> = unwrap_json(&responseData);
+                    let auth_json_option = response::decode::<SpAuthToken>(&responseData);
                     // Checking JSON structure is matched or not
-                    if let Some(valid_json_data) =  auth_json_option 
-                    {
-                        self.auth_token = Some(valid_json_data.clone());
-                        self.set_expire_time();
-                        return Some(valid_json_data.token);
-                    } 
-                    else 
+                    match auth_json_option 
                     {
-                        // println!("{:?}", responseData);
-                        let mut verify_json_option2 = SpVerifyResponse2::new();
-                        if verify_json_option2.string_to_json(responseData.http_body.as_str()) == true
-                        {
-                            // println!("{:#?}", verify_json_option2.message.unwrap());
-                            println!("Unauthorized Access: Check your username and password");
+                        Ok(valid_json_data) => {
+                            self.auth_token = Some(valid_json_data.clone());
+                            self.set_expire_time();
+                            for observer in &self.observers {
+                                observer.on_token_refreshed(&valid_json_data);
+                            }
+                            return Some(valid_json_data.token);
+                        }
+                        Err(err) => {
+                            if let Some(message) = response::error_message(&responseData)
+                            {
+                                eprintln!("Unauthorized Access: Check your username and password");
+                                self.last_error = Some(SpError::Auth(message));
+                            }
+                            else
+                            {
+                                self.last_error = Some(err);
+                            }
+                            return None;
                         }
-                        
-                        return None;
                     }
                 }
             } 
-            else 
-            {
-                println!("Shurjopay http client is not set yet!");
-            }            
         } 
         else 
         {
-            println!("Shurjopay Configuration is not set yet!");
+            eprintln!("Shurjopay Configuration is not set yet!");
+            self.last_error = Some(SpError::Config("Shurjopay Configuration is not set yet!".to_string()));
         }
         return None;
     }
@@ -702,9 +1107,7 @@ impl ShurjopayPlugin{
     /// It returns `Option<String>`
     pub fn get_client_ip_address(&mut self) -> Option<String> 
     {
-        let sp_ins = self.clone();
-
-            if let Some(client) = sp_ins.client
+            if let Some(client) = self.http_client()
             {
                 let url = format!("https://api.ipify.org/?format=json");
 
@@ -726,7 +1129,7 @@ impl ShurjopayPlugin{
             } 
             else 
             {
-                println!("Shurjopay http client is not set yet!");
+                eprintln!("Shurjopay http client is not set yet!");
             }            
         return None;
     }
@@ -813,6 +1216,8 @@ impl SpVerifyResponse
             transaction_status:  None, 
             method:  None, 
             date_time:  None,
+            extra: serde_json::Map::new(),
+            raw_body: None,
         }
     }
 
@@ -839,10 +1244,11 @@ impl SpVerifyResponse
 
 
     /// This function will convert `SpVerifyResponse2` data structure to `SoVerifyResponse`
+    #[deprecated(note = "use `response::decode_verification`, it reads failed verifications too")]
     pub fn convert_sp_response2_to_sp_respose(&mut self, sp_response2: SpVerifyResponse2)
     {
-        self.sp_code = Some(sp_response2.sp_code.unwrap().parse::<i64>().unwrap());
-        self.sp_message = Some(sp_response2.message.unwrap().clone());
+        self.sp_code = sp_response2.sp_code.and_then(|sp_code| sp_code.trim().parse::<i64>().ok());
+        self.sp_message = sp_response2.message;
     }
 }
 
@@ -859,6 +1265,7 @@ impl SpVerifyResponse2
 
     /// This function unwraps `JSON` `String` into `SpVerifyResponse2` data structure'
     /// if conversion is possible return true
+    #[deprecated(note = "use `response::decode_verification`")]
     pub fn string_to_json(&mut self, msg: &str) ->bool
     { 
     
@@ -873,7 +1280,7 @@ impl SpVerifyResponse2
         },
             Err(_) => 
             {
-                println!("failed to convert spVerify Response2");
+                eprintln!("failed to convert spVerify Response2");
                 return false;
             },
         }
@@ -885,6 +1292,9 @@ impl SpVerifyResponse2
 
 
 /// This function unwraps `JSON` `String` into specified `<T>` data structure
+/// Only bodies with a single element are handled, see `response::decode`
+#[deprecated(note = "use `response::decode`, it handles any number of elements and reports why decoding failed")]
+#[allow(deprecated)]
 pub fn unwrap_json<'a, T>(response_data: &'a shurjopay_client::HttpResponse) -> Option<T> 
 where T: Deserialize<'a>+ Clone+ std::fmt::Debug {
     if response_data.http_code == 200
@@ -910,7 +1320,7 @@ where T: Deserialize<'a>+ Clone+ std::fmt::Debug {
     } 
     else 
     {
-        println!("response_data: {:?}", response_data);
+        eprintln!("response_data: {:?}", response_data);
     }
     return None
 }
@@ -920,19 +1330,13 @@ where T: Deserialize<'a>+ Clone+ std::fmt::Debug {
 
 /// this function remove first and last char of string if first char is '[' and last char is ']'
 /// Return modified string
+/// The `response` module decodes response bodies with any number of elements
+#[deprecated(note = "use `response::items`")]
 pub fn remove_first_and_last_ch(input: &str) -> &str {
-
-    let firstch = input.chars().nth(0).unwrap();
-    let lastch = input.chars().last().unwrap();
-    if firstch == '[' && lastch == ']'
-    {
-        let first_last_off: &str = &input[1..input.len() - 1];
-        // println!("{}", first_last_off);
-        return first_last_off;
-    
-    };
-    
-    return input;
+    match input.strip_prefix('[').and_then(|rest| rest.strip_suffix(']')) {
+        Some(first_last_off) => first_last_off,
+        None => input,
+    }
 }
 
 /// This function will check if .env file available or not
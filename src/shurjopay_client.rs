@@ -7,7 +7,9 @@
 //! - Authenticates automatically during make_payments or verifyingPayments
 //! 
 
-/// The `log` crate is included to export log for debug purpose
+#![allow(clippy::needless_return)]
+
+// The `log` crate is included to export log for debug purpose
 // extern crate log;
 // use log::{debug, error, info, warn};
 // use log::info;
@@ -0,0 +1,281 @@
+//!
+//! SQLite backed `PaymentStore`
+//!
+//! Available with the `sqlite` feature. Requests and responses are kept
+//! as JSON text next to indexed `order_id` and `sp_order_id` columns.
+//!
+
+use std::path::Path;
+use std::sync::Mutex;
+
+use chrono::NaiveDateTime;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+
+use crate::error::SpError;
+use crate::payment_store::{PaymentRecord, PaymentState, PaymentStore, StateTransition};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS sp_payments (
+    order_id TEXT PRIMARY KEY NOT NULL,
+    sp_order_id TEXT,
+    checkout_url TEXT,
+    amount TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    state TEXT NOT NULL,
+    checkout_request TEXT NOT NULL,
+    checkout_response TEXT,
+    verify_response TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS sp_payments_sp_order_id ON sp_payments (sp_order_id);
+CREATE TABLE IF NOT EXISTS sp_payment_transitions (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    order_id TEXT NOT NULL,
+    from_state TEXT,
+    to_state TEXT NOT NULL,
+    at TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS sp_payment_transitions_order_id ON sp_payment_transitions (order_id);
+";
+
+const RECORD_COLUMNS: &str = "order_id, sp_order_id, checkout_url, amount, currency, state, \
+    checkout_request, checkout_response, verify_response, created_at, updated_at";
+
+const TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.f";
+
+/// SQLite `PaymentStore`
+/// The connection is guarded by a mutex so the store can be shared between threads
+#[derive(Debug)]
+pub struct SqlitePaymentStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqlitePaymentStore {
+    /// Opens or creates the ledger database at `path`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, SpError> {
+        Self::from_connection(Connection::open(path).map_err(store_error)?)
+    }
+
+    /// Opens a ledger that lives only as long as the store
+    pub fn open_in_memory() -> Result<Self, SpError> {
+        Self::from_connection(Connection::open_in_memory().map_err(store_error)?)
+    }
+
+    /// Creates the ledger tables on `conn` if they do not exist
+    pub fn from_connection(conn: Connection) -> Result<Self, SpError> {
+        conn.execute_batch(SCHEMA).map_err(store_error)?;
+        Ok(SqlitePaymentStore {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn find_by(&self, column: &str, value: &str) -> Result<Option<PaymentRecord>, SpError> {
+        let conn = self.conn.lock().map_err(|e| SpError::Store(e.to_string()))?;
+        let sql = format!("SELECT {} FROM sp_payments WHERE {} = ?1", RECORD_COLUMNS, column);
+        let row = conn
+            .query_row(&sql, params![value], read_record_row)
+            .optional()
+            .map_err(store_error)?;
+        row.map(RecordRow::into_record).transpose()
+    }
+}
+
+impl PaymentStore for SqlitePaymentStore {
+    fn save_record(&self, record: &PaymentRecord) -> Result<(), SpError> {
+        let conn = self.conn.lock().map_err(|e| SpError::Store(e.to_string()))?;
+        insert_record(&conn, record)
+    }
+
+    fn append_transition(&self, transition: &StateTransition) -> Result<(), SpError> {
+        let conn = self.conn.lock().map_err(|e| SpError::Store(e.to_string()))?;
+        insert_transition(&conn, transition)
+    }
+
+    /// Saves the record and the transition in one transaction
+    fn save_with_transition(&self, record: &PaymentRecord, transition: Option<&StateTransition>) -> Result<(), SpError> {
+        let mut conn = self.conn.lock().map_err(|e| SpError::Store(e.to_string()))?;
+        let tx = conn.transaction().map_err(store_error)?;
+        insert_record(&tx, record)?;
+        if let Some(transition) = transition {
+            insert_transition(&tx, transition)?;
+        }
+        tx.commit().map_err(store_error)
+    }
+
+    /// Compares the stored state and saves in one transaction
+    fn save_if_state(
+        &self,
+        record: &PaymentRecord,
+        expected: PaymentState,
+        transition: Option<&StateTransition>,
+    ) -> Result<bool, SpError> {
+        let mut conn = self.conn.lock().map_err(|e| SpError::Store(e.to_string()))?;
+        let tx = conn.transaction().map_err(store_error)?;
+        let state: Option<String> = tx
+            .query_row(
+                "SELECT state FROM sp_payments WHERE order_id = ?1",
+                params![record.order_id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(store_error)?;
+        if state.as_deref() != Some(expected.as_str()) {
+            return Ok(false);
+        }
+        insert_record(&tx, record)?;
+        if let Some(transition) = transition {
+            insert_transition(&tx, transition)?;
+        }
+        tx.commit().map_err(store_error).map(|_| true)
+    }
+
+    fn find_by_order_id(&self, order_id: &str) -> Result<Option<PaymentRecord>, SpError> {
+        self.find_by("order_id", order_id)
+    }
+
+    fn find_by_sp_order_id(&self, sp_order_id: &str) -> Result<Option<PaymentRecord>, SpError> {
+        self.find_by("sp_order_id", sp_order_id)
+    }
+
+    fn transitions(&self, order_id: &str) -> Result<Vec<StateTransition>, SpError> {
+        let conn = self.conn.lock().map_err(|e| SpError::Store(e.to_string()))?;
+        let mut stmt = conn
+            .prepare(
+                "SELECT from_state, to_state, at FROM sp_payment_transitions \
+                 WHERE order_id = ?1 ORDER BY id",
+            )
+            .map_err(store_error)?;
+        let rows = stmt
+            .query_map(params![order_id], |row| {
+                Ok((
+                    row.get::<_, Option<String>>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })
+            .map_err(store_error)?;
+
+        let mut transitions = Vec::new();
+        for row in rows {
+            let (from, to, at) = row.map_err(store_error)?;
+            transitions.push(StateTransition {
+                order_id: order_id.to_string(),
+                from: from.as_deref().map(parse_state).transpose()?,
+                to: parse_state(&to)?,
+                at: parse_time(&at)?,
+            });
+        }
+        Ok(transitions)
+    }
+}
+
+/// Inserts or replaces a record on `conn`, which may be a transaction
+fn insert_record(conn: &Connection, record: &PaymentRecord) -> Result<(), SpError> {
+    conn.execute(
+        &format!(
+            "INSERT OR REPLACE INTO sp_payments ({}) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            RECORD_COLUMNS
+        ),
+        params![
+            record.order_id,
+            record.sp_order_id,
+            record.checkout_url,
+            record.amount,
+            record.currency,
+            record.state.as_str(),
+            to_json(&record.checkout_request)?,
+            record.checkout_response.as_ref().map(to_json).transpose()?,
+            record.verify_response.as_ref().map(to_json).transpose()?,
+            record.created_at.format(TIME_FORMAT).to_string(),
+            record.updated_at.format(TIME_FORMAT).to_string(),
+        ],
+    )
+    .map_err(store_error)?;
+    Ok(())
+}
+
+/// Appends a transition on `conn`, which may be a transaction
+fn insert_transition(conn: &Connection, transition: &StateTransition) -> Result<(), SpError> {
+    conn.execute(
+        "INSERT INTO sp_payment_transitions (order_id, from_state, to_state, at) VALUES (?1, ?2, ?3, ?4)",
+        params![
+            transition.order_id,
+            transition.from.map(|state| state.as_str()),
+            transition.to.as_str(),
+            transition.at.format(TIME_FORMAT).to_string(),
+        ],
+    )
+    .map_err(store_error)?;
+    Ok(())
+}
+
+/// Raw column values of a `sp_payments` row
+struct RecordRow {
+    order_id: String,
+    sp_order_id: Option<String>,
+    checkout_url: Option<String>,
+    amount: String,
+    currency: String,
+    state: String,
+    checkout_request: String,
+    checkout_response: Option<String>,
+    verify_response: Option<String>,
+    created_at: String,
+    updated_at: String,
+}
+
+impl RecordRow {
+    fn into_record(self) -> Result<PaymentRecord, SpError> {
+        Ok(PaymentRecord {
+            order_id: self.order_id,
+            sp_order_id: self.sp_order_id,
+            checkout_url: self.checkout_url,
+            amount: self.amount,
+            currency: self.currency,
+            state: parse_state(&self.state)?,
+            checkout_request: from_json(&self.checkout_request)?,
+            checkout_response: self.checkout_response.as_deref().map(from_json).transpose()?,
+            verify_response: self.verify_response.as_deref().map(from_json).transpose()?,
+            created_at: parse_time(&self.created_at)?,
+            updated_at: parse_time(&self.updated_at)?,
+        })
+    }
+}
+
+fn read_record_row(row: &Row<'_>) -> rusqlite::Result<RecordRow> {
+    Ok(RecordRow {
+        order_id: row.get(0)?,
+        sp_order_id: row.get(1)?,
+        checkout_url: row.get(2)?,
+        amount: row.get(3)?,
+        currency: row.get(4)?,
+        state: row.get(5)?,
+        checkout_request: row.get(6)?,
+        checkout_response: row.get(7)?,
+        verify_response: row.get(8)?,
+        created_at: row.get(9)?,
+        updated_at: row.get(10)?,
+    })
+}
+
+fn store_error(err: rusqlite::Error) -> SpError {
+    SpError::Store(err.to_string())
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> Result<String, SpError> {
+    serde_json::to_string(value).map_err(|e| SpError::Store(e.to_string()))
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(text: &str) -> Result<T, SpError> {
+    serde_json::from_str(text).map_err(|e| SpError::Store(e.to_string()))
+}
+
+fn parse_state(name: &str) -> Result<PaymentState, SpError> {
+    PaymentState::parse(name).ok_or_else(|| SpError::Store(format!("unknown payment state `{}`", name)))
+}
+
+fn parse_time(text: &str) -> Result<NaiveDateTime, SpError> {
+    NaiveDateTime::parse_from_str(text, TIME_FORMAT).map_err(|e| SpError::Store(e.to_string()))
+}
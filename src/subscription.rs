@@ -0,0 +1,577 @@
+//!
+//! This module bills subscriptions in recurring cycles.
+//!
+//! A `Plan` fixes the amount, billing interval and trial of a product. A
+//! `Subscription` of a plan is charged once per cycle: cycle `n` is due at
+//! the `n`-th billing date after the trial and is paid through a checkout
+//! whose merchant order id is `{subscription id}-{n}`, so a retried or
+//! repeated charge of the same cycle always uses the same order id.
+//!
+//! Unpaid cycles are handled by dunning: `run_dunning` marks due cycles past
+//! due, sends reminders on the schedule of the `DunningPolicy` and suspends
+//! subscriptions still unpaid after the grace period. Time is read from an
+//! injectable `Clock`, so billing runs can be tested without waiting.
+//!
+//! Features:
+//! - `SubscriptionStore` trait to plug in any storage backend
+//! - `MemorySubscriptionStore` for tests and short lived processes
+//! - `Subscriptions` observes verifications to record paid and failed cycles
+//!
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use chrono::{Days, Duration, Months, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+
+use crate::clock::Clock;
+use crate::error::SpError;
+use crate::money::Money;
+use crate::observer::PaymentObserver;
+use crate::order_id::validate_order_id;
+use crate::payment_link::{LinkState, PaymentLink, PaymentLinks};
+use crate::payment_store::PaymentState;
+use crate::phone::PhoneNumber;
+use crate::shurjopay::{ShurjopayPlugin, SpVerifyResponse};
+use crate::web::CheckoutRequest;
+
+/// Highest cycle number a subscription id is validated for
+const MAX_CYCLE: u32 = 9999;
+
+/// Longest billing interval and trial of a plan, in years
+pub const MAX_PLAN_YEARS: u32 = 10;
+
+/// Billing interval of a plan
+/// This enum implements `Serialize`, `Deserialize`, `Debug`, `Clone`, `Copy`, `PartialEq` and `Eq` functions
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Interval {
+    Days(u32),
+    Weeks(u32),
+    Months(u32),
+}
+
+impl Interval {
+    /// Returns the billing date `periods` intervals after `anchor`
+    ///
+    /// Dates are counted from the anchor, not from the previous billing date, so a
+    /// monthly plan anchored on the 31st bills on the last day of shorter months
+    /// and on the 31st again afterwards. Dates past the end of the calendar are
+    /// `NaiveDateTime::MAX`.
+    pub fn after(&self, anchor: NaiveDateTime, periods: u32) -> NaiveDateTime {
+        let later = match *self {
+            Interval::Days(days) => anchor.checked_add_days(Days::new(u64::from(days) * u64::from(periods))),
+            Interval::Weeks(weeks) => {
+                let days = (u64::from(weeks) * u64::from(periods)).saturating_mul(7);
+                anchor.checked_add_days(Days::new(days))
+            }
+            Interval::Months(months) => anchor.checked_add_months(Months::new(months.saturating_mul(periods))),
+        };
+        later.unwrap_or(NaiveDateTime::MAX)
+    }
+
+    /// Longest accepted count of the interval unit, `MAX_PLAN_YEARS` years
+    fn max_count(&self) -> u32 {
+        match self {
+            Interval::Days(_) => MAX_PLAN_YEARS * 366,
+            Interval::Weeks(_) => MAX_PLAN_YEARS * 53,
+            Interval::Months(_) => MAX_PLAN_YEARS * 12,
+        }
+    }
+
+    fn count(&self) -> u32 {
+        match *self {
+            Interval::Days(count) | Interval::Weeks(count) | Interval::Months(count) => count,
+        }
+    }
+}
+
+/// Checks that a paid cycle was charged the amount and currency of its plan
+fn check_cycle_amount(plan: &Plan, subscription: &Subscription, response: &SpVerifyResponse) -> Result<(), SpError> {
+    let currency = response.currency.as_deref().unwrap_or_default().trim();
+    if !currency.eq_ignore_ascii_case(plan.amount.currency()) {
+        return Err(SpError::CurrencyMismatch {
+            expected: plan.amount.currency().to_string(),
+            actual: currency.to_string(),
+        });
+    }
+    let paid = response
+        .payable_amount
+        .map(|payable| Money::from_f64(payable, plan.amount.currency()))
+        .transpose()?;
+    if paid.as_ref() != Some(&plan.amount) {
+        return Err(SpError::InvalidAmount(format!(
+            "{} paid {} instead of {}",
+            subscription.order_id(),
+            paid.map_or_else(|| "null".to_string(), |paid| paid.to_string()),
+            plan.amount
+        )));
+    }
+    Ok(())
+}
+
+/// Adds a duration to a date, `NaiveDateTime::MAX` past the end of the calendar
+fn add(at: NaiveDateTime, duration: Duration) -> NaiveDateTime {
+    at.checked_add_signed(duration).unwrap_or(NaiveDateTime::MAX)
+}
+
+/// A subscription product
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone` and `PartialEq` functions
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Plan {
+    pub id: String,
+    pub name: String,
+    /// Amount charged every cycle
+    pub amount: Money,
+    pub interval: Interval,
+    /// Days before the first cycle is due
+    pub trial_days: u32,
+}
+
+/// Customer details sent with every checkout of a subscription
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone` and `PartialEq` functions
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Subscriber {
+    pub name: String,
+    pub address: String,
+    pub phone: String,
+    pub city: String,
+    pub post_code: String,
+}
+
+/// State of a subscription
+/// This enum implements `Serialize`, `Deserialize`, `Debug`, `Clone`, `Copy`, `PartialEq` and `Eq` functions
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionState {
+    /// The first cycle is not due yet
+    Trialing,
+    /// Every due cycle is paid
+    Active,
+    /// The current cycle is due and unpaid
+    PastDue,
+    /// The current cycle was not paid within the grace period
+    Suspended,
+    /// The subscription is not billed anymore
+    Cancelled,
+}
+
+/// A subscriber's subscription of a plan
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone` and `PartialEq` functions
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Subscription {
+    pub id: String,
+    pub plan_id: String,
+    pub subscriber: Subscriber,
+    pub state: SubscriptionState,
+    /// Due date of the first cycle, the end of the trial
+    pub anchor: NaiveDateTime,
+    /// Number of the current cycle, starting at 1
+    pub cycle: u32,
+    /// Due date of the current cycle
+    pub due_at: NaiveDateTime,
+    /// Failed payments of the current cycle
+    pub failed_attempts: u32,
+    /// Reminders sent for the current cycle
+    pub reminders_sent: u32,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl Subscription {
+    /// Merchant order id of the current cycle
+    pub fn order_id(&self) -> String {
+        cycle_order_id(&self.id, self.cycle)
+    }
+}
+
+/// Merchant order id of cycle `cycle` of a subscription
+pub fn cycle_order_id(subscription_id: &str, cycle: u32) -> String {
+    format!("{}-{}", subscription_id, cycle)
+}
+
+/// Splits a merchant order id made by `cycle_order_id` into subscription id and cycle
+pub fn parse_cycle_order_id(order_id: &str) -> Option<(&str, u32)> {
+    let (subscription_id, cycle) = order_id.rsplit_once('-')?;
+    Some((subscription_id, cycle.parse().ok()?))
+}
+
+/// Reminder schedule and grace period of unpaid cycles
+/// This structure implements `Debug`, `Clone`, `PartialEq` and `Default` functions
+#[derive(Debug, Clone, PartialEq)]
+pub struct DunningPolicy {
+    /// Time after the due date of each reminder, in ascending order
+    pub reminders: Vec<Duration>,
+    /// Time after the due date a cycle may stay unpaid before the subscription is suspended
+    pub grace_period: Duration,
+}
+
+impl Default for DunningPolicy {
+    /// This function will set default value for DunningPolicy struct
+    fn default() -> Self {
+        DunningPolicy {
+            reminders: vec![Duration::days(1), Duration::days(3), Duration::days(5)],
+            grace_period: Duration::days(7),
+        }
+    }
+}
+
+/// Something `run_dunning` did to a subscription
+/// This enum implements `Debug`, `Clone` and `PartialEq` functions
+#[derive(Debug, Clone, PartialEq)]
+pub enum DunningEvent {
+    /// A cycle became due, its checkout should be sent to the subscriber
+    Due { subscription_id: String, order_id: String },
+    /// A reminder of an unpaid cycle should be sent, `attempt` starts at 1
+    Reminder {
+        subscription_id: String,
+        order_id: String,
+        attempt: u32,
+    },
+    /// The subscription was suspended for an unpaid cycle
+    Suspended { subscription_id: String, order_id: String },
+}
+
+/// Storage backend of plans and subscriptions
+pub trait SubscriptionStore: fmt::Debug + Send + Sync {
+    /// Inserts or replaces the plan with the same `id`
+    fn save_plan(&self, plan: &Plan) -> Result<(), SpError>;
+
+    /// Looks up a plan by its id
+    fn find_plan(&self, id: &str) -> Result<Option<Plan>, SpError>;
+
+    /// Inserts or replaces the subscription with the same `id`
+    fn save_subscription(&self, subscription: &Subscription) -> Result<(), SpError>;
+
+    /// Looks up a subscription by its id
+    fn find_subscription(&self, id: &str) -> Result<Option<Subscription>, SpError>;
+
+    /// Returns every subscription
+    fn subscriptions(&self) -> Result<Vec<Subscription>, SpError>;
+}
+
+/// In memory `SubscriptionStore`
+/// Plans and subscriptions are lost when the process exits
+#[derive(Debug, Default)]
+pub struct MemorySubscriptionStore {
+    plans: Mutex<HashMap<String, Plan>>,
+    subscriptions: Mutex<HashMap<String, Subscription>>,
+}
+
+impl MemorySubscriptionStore {
+    /// This is a constructor to initiate an empty `MemorySubscriptionStore`
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SubscriptionStore for MemorySubscriptionStore {
+    fn save_plan(&self, plan: &Plan) -> Result<(), SpError> {
+        let mut plans = self.plans.lock().map_err(|e| SpError::Store(e.to_string()))?;
+        plans.insert(plan.id.clone(), plan.clone());
+        Ok(())
+    }
+
+    fn find_plan(&self, id: &str) -> Result<Option<Plan>, SpError> {
+        let plans = self.plans.lock().map_err(|e| SpError::Store(e.to_string()))?;
+        Ok(plans.get(id).cloned())
+    }
+
+    fn save_subscription(&self, subscription: &Subscription) -> Result<(), SpError> {
+        let mut subscriptions = self.subscriptions.lock().map_err(|e| SpError::Store(e.to_string()))?;
+        subscriptions.insert(subscription.id.clone(), subscription.clone());
+        Ok(())
+    }
+
+    fn find_subscription(&self, id: &str) -> Result<Option<Subscription>, SpError> {
+        let subscriptions = self.subscriptions.lock().map_err(|e| SpError::Store(e.to_string()))?;
+        Ok(subscriptions.get(id).cloned())
+    }
+
+    fn subscriptions(&self) -> Result<Vec<Subscription>, SpError> {
+        let subscriptions = self.subscriptions.lock().map_err(|e| SpError::Store(e.to_string()))?;
+        let mut subscriptions: Vec<Subscription> = subscriptions.values().cloned().collect();
+        subscriptions.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(subscriptions)
+    }
+}
+
+/// Subscribes customers to plans and bills their cycles
+///
+/// Add it to the plugin with `add_observer` so verified payments are recorded.
+/// This structure implements `Debug` and `Clone` functions
+#[derive(Debug, Clone)]
+pub struct Subscriptions {
+    store: Arc<dyn SubscriptionStore>,
+    clock: Arc<dyn Clock>,
+    dunning: DunningPolicy,
+}
+
+impl Subscriptions {
+    /// This is a constructor to bill the subscriptions of `store` with the default `DunningPolicy`
+    pub fn new(store: Arc<dyn SubscriptionStore>, clock: Arc<dyn Clock>) -> Self {
+        Subscriptions {
+            store,
+            clock,
+            dunning: DunningPolicy::default(),
+        }
+    }
+
+    /// Sets the dunning policy
+    pub fn with_dunning(mut self, dunning: DunningPolicy) -> Self {
+        self.dunning = dunning;
+        self
+    }
+
+    /// Returns the dunning policy
+    pub fn dunning(&self) -> &DunningPolicy {
+        &self.dunning
+    }
+
+    /// Adds or replaces a plan
+    /// The amount must be positive, the interval at least one unit and the interval
+    /// and trial at most `MAX_PLAN_YEARS` years
+    pub fn add_plan(&self, plan: Plan) -> Result<Plan, SpError> {
+        if plan.amount.minor() <= 0 {
+            return Err(SpError::InvalidAmount(format!("plan {} amount must be positive", plan.id)));
+        }
+        let count = plan.interval.count();
+        if count == 0 || count > plan.interval.max_count() {
+            return Err(SpError::Config(format!(
+                "plan {} interval {:?} must be 1 to {}",
+                plan.id,
+                plan.interval,
+                plan.interval.max_count()
+            )));
+        }
+        if plan.trial_days > MAX_PLAN_YEARS * 366 {
+            return Err(SpError::Config(format!(
+                "plan {} trial of {} days is longer than {} years",
+                plan.id, plan.trial_days, MAX_PLAN_YEARS
+            )));
+        }
+        self.store.save_plan(&plan)?;
+        Ok(plan)
+    }
+
+    /// Looks up a plan by its id
+    pub fn plan(&self, id: &str) -> Result<Plan, SpError> {
+        self.store
+            .find_plan(id)?
+            .ok_or_else(|| SpError::Config(format!("unknown plan `{}`", id)))
+    }
+
+    /// This function subscribes a customer to a plan, starting now
+    ///
+    /// `id` is chosen by the merchant and must be usable in the order ids of the cycles:
+    /// letters, digits, `-` and `_`, short enough for `{id}-9999` to be a valid order id.
+    /// The phone of the subscriber must be a Bangladeshi mobile number and is kept in the
+    /// national format, so every cycle's checkout is accepted.
+    pub fn subscribe(&self, id: &str, plan_id: &str, mut subscriber: Subscriber) -> Result<Subscription, SpError> {
+        validate_order_id(&cycle_order_id(id, MAX_CYCLE))?;
+        subscriber.phone = PhoneNumber::parse(&subscriber.phone)?.to_string();
+        let plan = self.plan(plan_id)?;
+        if self.store.find_subscription(id)?.is_some() {
+            return Err(SpError::InvalidOrderId(format!("subscription `{}` already exists", id)));
+        }
+        let now = self.clock.now();
+        let anchor = now
+            .checked_add_days(Days::new(u64::from(plan.trial_days)))
+            .unwrap_or(NaiveDateTime::MAX);
+        let subscription = Subscription {
+            id: id.to_string(),
+            plan_id: plan.id,
+            subscriber,
+            state: if plan.trial_days > 0 { SubscriptionState::Trialing } else { SubscriptionState::Active },
+            anchor,
+            cycle: 1,
+            due_at: anchor,
+            failed_attempts: 0,
+            reminders_sent: 0,
+            created_at: now,
+            updated_at: now,
+        };
+        self.store.save_subscription(&subscription)?;
+        Ok(subscription)
+    }
+
+    /// Looks up a subscription by its id
+    pub fn get(&self, id: &str) -> Result<Subscription, SpError> {
+        self.store
+            .find_subscription(id)?
+            .ok_or_else(|| SpError::SubscriptionNotFound(id.to_string()))
+    }
+
+    /// Stops billing a subscription
+    pub fn cancel(&self, id: &str) -> Result<Subscription, SpError> {
+        let mut subscription = self.get(id)?;
+        subscription.state = SubscriptionState::Cancelled;
+        self.save(&mut subscription)?;
+        Ok(subscription)
+    }
+
+    /// Returns the subscriptions whose current cycle is due and unpaid
+    pub fn due(&self) -> Result<Vec<Subscription>, SpError> {
+        let now = self.clock.now();
+        Ok(self
+            .store
+            .subscriptions()?
+            .into_iter()
+            .filter(|subscription| is_billed(subscription) && subscription.due_at <= now)
+            .collect())
+    }
+
+    /// This function builds the checkout request of the current cycle of a subscription
+    pub fn checkout_request(&self, subscription: &Subscription) -> Result<CheckoutRequest, SpError> {
+        let plan = self.plan(&subscription.plan_id)?;
+        Ok(CheckoutRequest {
+            amount: plan.amount.amount_string(),
+            order_id: Some(subscription.order_id()),
+            currency: Some(plan.amount.currency().to_string()),
+            customer_name: subscription.subscriber.name.clone(),
+            customer_address: subscription.subscriber.address.clone(),
+            customer_phone: subscription.subscriber.phone.clone(),
+            customer_city: subscription.subscriber.city.clone(),
+            customer_post_code: subscription.subscriber.post_code.clone(),
+            client_ip: None,
+        })
+    }
+
+    /// This function creates a payment link of the current cycle of a subscription
+    /// The link expires when the grace period of the cycle ends, a cycle that
+    /// already has a link gets it back and a paid one is `LinkConsumed`
+    ///
+    /// An expired link of an unpaid cycle, e.g. of a suspended subscription, is
+    /// reissued to expire one grace period from now.
+    pub fn payment_link(
+        &self,
+        links: &PaymentLinks,
+        sp_instance: &ShurjopayPlugin,
+        subscription: &Subscription,
+    ) -> Result<PaymentLink, SpError> {
+        if let Some(link) = links.find_by_order_id(&subscription.order_id())? {
+            if link.state == LinkState::Consumed {
+                return Err(SpError::LinkConsumed(link.id));
+            }
+            let now = self.clock.now();
+            if link.is_expired_at(now) {
+                return links.reissue(&link.id, add(now, self.dunning.grace_period));
+            }
+            return Ok(link);
+        }
+        let request = self.checkout_request(subscription)?;
+        links.create(sp_instance, request, add(subscription.due_at, self.dunning.grace_period))
+    }
+
+    /// This function records the verification of a cycle payment
+    ///
+    /// A paid cycle moves the subscription to the next cycle and makes it active again,
+    /// even if it was suspended. A declined or cancelled payment counts as a failed attempt
+    /// and makes a due cycle past due. Returns the updated subscription, or `None` if the
+    /// order is not the current cycle of a subscription.
+    ///
+    /// A payment whose `payable_amount` or `currency` differs from the plan does not pay
+    /// the cycle and is `SpError::InvalidAmount` or `SpError::CurrencyMismatch`.
+    pub fn record_verification(&self, response: &SpVerifyResponse) -> Result<Option<Subscription>, SpError> {
+        let (subscription_id, cycle) = match response.customer_order_id.as_deref().and_then(parse_cycle_order_id) {
+            Some(order) => order,
+            None => return Ok(None),
+        };
+        let mut subscription = match self.store.find_subscription(subscription_id)? {
+            Some(subscription) if subscription.cycle == cycle => subscription,
+            _ => return Ok(None),
+        };
+        if subscription.state == SubscriptionState::Cancelled {
+            return Ok(None);
+        }
+        match PaymentState::from_sp_code(response.sp_code) {
+            PaymentState::Paid => {
+                let plan = self.plan(&subscription.plan_id)?;
+                check_cycle_amount(&plan, &subscription, response)?;
+                subscription.cycle += 1;
+                subscription.due_at = plan.interval.after(subscription.anchor, subscription.cycle - 1);
+                subscription.state = SubscriptionState::Active;
+                subscription.failed_attempts = 0;
+                subscription.reminders_sent = 0;
+            }
+            PaymentState::Declined | PaymentState::Cancelled => {
+                subscription.failed_attempts += 1;
+                if subscription.due_at <= self.clock.now() && subscription.state != SubscriptionState::Suspended {
+                    subscription.state = SubscriptionState::PastDue;
+                }
+            }
+            _ => return Ok(None),
+        }
+        self.save(&mut subscription)?;
+        Ok(Some(subscription))
+    }
+
+    /// This function advances dunning of every subscription to the current time
+    ///
+    /// Due cycles become past due, reminders whose time has come are sent once each and
+    /// subscriptions unpaid after the grace period are suspended.
+    pub fn run_dunning(&self) -> Result<Vec<DunningEvent>, SpError> {
+        let now = self.clock.now();
+        let mut events = Vec::new();
+        for mut subscription in self.store.subscriptions()? {
+            if !is_billed(&subscription) || subscription.due_at > now {
+                continue;
+            }
+            let before = subscription.clone();
+            let subscription_id = subscription.id.clone();
+            let order_id = subscription.order_id();
+            if subscription.state != SubscriptionState::PastDue {
+                subscription.state = SubscriptionState::PastDue;
+                events.push(DunningEvent::Due {
+                    subscription_id: subscription_id.clone(),
+                    order_id: order_id.clone(),
+                });
+            }
+            if now >= add(subscription.due_at, self.dunning.grace_period) {
+                subscription.state = SubscriptionState::Suspended;
+                events.push(DunningEvent::Suspended { subscription_id, order_id });
+            } else {
+                let reminders_due = self
+                    .dunning
+                    .reminders
+                    .iter()
+                    .take_while(|after| add(subscription.due_at, **after) <= now)
+                    .count() as u32;
+                if reminders_due > subscription.reminders_sent {
+                    subscription.reminders_sent = reminders_due;
+                    events.push(DunningEvent::Reminder {
+                        subscription_id,
+                        order_id,
+                        attempt: reminders_due,
+                    });
+                }
+            }
+            if subscription != before {
+                self.save(&mut subscription)?;
+            }
+        }
+        Ok(events)
+    }
+
+    fn save(&self, subscription: &mut Subscription) -> Result<(), SpError> {
+        subscription.updated_at = self.clock.now();
+        self.store.save_subscription(subscription)
+    }
+}
+
+/// Returns true if the current cycle of a subscription is still billed
+fn is_billed(subscription: &Subscription) -> bool {
+    matches!(
+        subscription.state,
+        SubscriptionState::Trialing | SubscriptionState::Active | SubscriptionState::PastDue
+    )
+}
+
+impl PaymentObserver for Subscriptions {
+    fn on_verified(&self, response: &SpVerifyResponse) {
+        if let Err(err) = self.record_verification(response) {
+            eprintln!("{}", err);
+        }
+    }
+}
@@ -0,0 +1,261 @@
+//!
+//! shurjopay gateway operations as `tower::Service`s.
+//!
+//! Available with the `tower` feature.
+//!
+//! Each operation is its own service so it can be composed with the
+//! application's timeout, concurrency-limit, load-shed and tracing layers:
+//! - `TokenService` takes a `TokenRequest` and returns the `SpAuthToken`
+//! - `CheckoutService` takes an `SpCheckout` and returns the `SpCheckoutResponse`
+//! - `VerifyService` and `StatusService` take a shurjopay order id and return the `SpVerifyResponse`
+//!
+//! `default_layers` is the stack the crate recommends: load shedding,
+//! a concurrency limit and a timeout, with every error mapped to `SpError`.
+//!
+//! The plugin is blocking, so every call runs on `tokio::task::spawn_blocking`
+//! and the plugin must be created outside of async code. A timed out call
+//! stops waiting, the blocking request itself runs to completion.
+//!
+//! Calls run concurrently, each on its own copy of the shared plugin. The
+//! shared plugin is only locked to refresh the auth token and to copy it, so
+//! a slow gateway call does not hold up the others. Circuit breaker, rate
+//! limiter, payment store and observers are shared by all copies.
+//!
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use tower::layer::util::{Identity, Stack};
+use tower::limit::ConcurrencyLimitLayer;
+use tower::load_shed::error::Overloaded;
+use tower::load_shed::LoadShedLayer;
+use tower::timeout::error::Elapsed;
+use tower::timeout::TimeoutLayer;
+use tower::util::MapErrLayer;
+use tower::{BoxError, Service, ServiceBuilder};
+
+use crate::error::SpError;
+use crate::shurjopay::{ShurjopayPlugin, SpAuthToken, SpCheckout, SpCheckoutResponse, SpVerifyResponse};
+use crate::web::{self, last_error, PluginHandle, SharedPlugin};
+
+/// Timeout of a gateway call in `default_layers`
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Gateway calls in flight per service in `default_layers`
+pub const DEFAULT_CONCURRENCY_LIMIT: usize = 16;
+
+/// Future returned by the shurjopay services
+pub type ServiceFuture<T> = Pin<Box<dyn Future<Output = Result<T, SpError>> + Send>>;
+
+/// Layer stack returned by `default_layers`
+pub type DefaultLayers = ServiceBuilder<
+    Stack<TimeoutLayer, Stack<ConcurrencyLimitLayer, Stack<LoadShedLayer, Stack<MapErrLayer<fn(BoxError) -> SpError>, Identity>>>>,
+>;
+
+/// Request of `TokenService`
+/// This structure implements `Debug`, `Clone` and `Default` functions
+#[derive(Debug, Clone, Default)]
+pub struct TokenRequest;
+
+/// Service returning a valid auth token, a cached token is reused until it expires
+#[derive(Clone)]
+pub struct TokenService {
+    plugin: PluginHandle,
+}
+
+/// Service creating a payment at shurjopay
+#[derive(Clone)]
+pub struct CheckoutService {
+    plugin: PluginHandle,
+}
+
+/// Service verifying a payment by shurjopay order id
+#[derive(Clone)]
+pub struct VerifyService {
+    plugin: PluginHandle,
+}
+
+/// Service reading the status of a payment by shurjopay order id
+#[derive(Clone)]
+pub struct StatusService {
+    plugin: PluginHandle,
+}
+
+/// All gateway services sharing one plugin
+#[derive(Clone)]
+pub struct ShurjopayServices {
+    pub token: TokenService,
+    pub checkout: CheckoutService,
+    pub verify: VerifyService,
+    pub status: StatusService,
+}
+
+impl ShurjopayServices {
+    /// Creates the services around a configured plugin
+    pub fn new(sp_instance: ShurjopayPlugin) -> Self {
+        Self::from_shared(web::share(sp_instance))
+    }
+
+    /// Creates the services around a plugin that is shared with other handlers
+    pub fn from_shared(plugin: SharedPlugin) -> Self {
+        ShurjopayServices {
+            token: TokenService::new(plugin.clone()),
+            checkout: CheckoutService::new(plugin.clone()),
+            verify: VerifyService::new(plugin.clone()),
+            status: StatusService::new(plugin),
+        }
+    }
+}
+
+impl TokenService {
+    pub fn new(plugin: SharedPlugin) -> Self {
+        TokenService {
+            plugin: PluginHandle::new(plugin),
+        }
+    }
+}
+
+impl CheckoutService {
+    pub fn new(plugin: SharedPlugin) -> Self {
+        CheckoutService {
+            plugin: PluginHandle::new(plugin),
+        }
+    }
+}
+
+impl VerifyService {
+    pub fn new(plugin: SharedPlugin) -> Self {
+        VerifyService {
+            plugin: PluginHandle::new(plugin),
+        }
+    }
+}
+
+impl StatusService {
+    pub fn new(plugin: SharedPlugin) -> Self {
+        StatusService {
+            plugin: PluginHandle::new(plugin),
+        }
+    }
+}
+
+impl Service<TokenRequest> for TokenService {
+    type Response = SpAuthToken;
+    type Error = SpError;
+    type Future = ServiceFuture<SpAuthToken>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), SpError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _request: TokenRequest) -> Self::Future {
+        blocking(self.plugin.shared(), |sp_instance| {
+            match sp_instance.verify_auth_token() {
+                Some(_) => sp_instance
+                    .auth_token
+                    .clone()
+                    .ok_or_else(|| last_error(sp_instance, "token request failed")),
+                None => Err(last_error(sp_instance, "token request failed")),
+            }
+        })
+    }
+}
+
+impl Service<SpCheckout> for CheckoutService {
+    type Response = SpCheckoutResponse;
+    type Error = SpError;
+    type Future = ServiceFuture<SpCheckoutResponse>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), SpError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, checkout_item: SpCheckout) -> Self::Future {
+        blocking(self.plugin.shared(), move |sp_instance| {
+            sp_instance
+                .make_payment_checkout(checkout_item)
+                .ok_or_else(|| last_error(sp_instance, "checkout failed"))
+        })
+    }
+}
+
+impl Service<String> for VerifyService {
+    type Response = SpVerifyResponse;
+    type Error = SpError;
+    type Future = ServiceFuture<SpVerifyResponse>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), SpError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, order_id: String) -> Self::Future {
+        blocking(self.plugin.shared(), move |sp_instance| {
+            sp_instance
+                .verify_payment(Some(order_id))
+                .ok_or_else(|| last_error(sp_instance, "verification failed"))
+        })
+    }
+}
+
+impl Service<String> for StatusService {
+    type Response = SpVerifyResponse;
+    type Error = SpError;
+    type Future = ServiceFuture<SpVerifyResponse>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), SpError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, order_id: String) -> Self::Future {
+        blocking(self.plugin.shared(), move |sp_instance| {
+            sp_instance
+                .check_payment(Some(order_id))
+                .ok_or_else(|| last_error(sp_instance, "payment status request failed"))
+        })
+    }
+}
+
+/// The crate's default layer stack with `DEFAULT_TIMEOUT` and `DEFAULT_CONCURRENCY_LIMIT`
+pub fn default_layers() -> DefaultLayers {
+    layers(DEFAULT_TIMEOUT, DEFAULT_CONCURRENCY_LIMIT)
+}
+
+/// The default layer stack with a custom timeout and concurrency limit
+/// Calls beyond `concurrency_limit` fail at once with `SpError::Overloaded`
+pub fn layers(timeout: Duration, concurrency_limit: usize) -> DefaultLayers {
+    ServiceBuilder::new()
+        .map_err(into_sp_error as fn(BoxError) -> SpError)
+        .load_shed()
+        .concurrency_limit(concurrency_limit)
+        .timeout(timeout)
+}
+
+/// Maps the error of a tower layer back to `SpError`
+pub fn into_sp_error(err: BoxError) -> SpError {
+    let err = match err.downcast::<SpError>() {
+        Ok(err) => return *err,
+        Err(err) => err,
+    };
+    if err.is::<Elapsed>() {
+        SpError::Timeout(err.to_string())
+    } else if err.is::<Overloaded>() {
+        SpError::Overloaded(err.to_string())
+    } else {
+        SpError::Http(err.to_string())
+    }
+}
+
+/// Runs `operation` on a copy of the plugin in the blocking thread pool, see `web::run_on_copy`
+fn blocking<T, F>(plugin: SharedPlugin, operation: F) -> ServiceFuture<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut ShurjopayPlugin) -> Result<T, SpError> + Send + 'static,
+{
+    Box::pin(async move {
+        tokio::task::spawn_blocking(move || web::run_on_copy(&plugin, operation))
+            .await
+            .map_err(|err| SpError::Http(err.to_string()))?
+    })
+}
@@ -0,0 +1,116 @@
+//!
+//! This module abstracts how requests reach the shurjopay gateway.
+//!
+//! `ShurjopayPlugin` sends every gateway request as a `TransportRequest`
+//! through a `Transport`. By default it uses `ReqwestTransport` over the
+//! http client built from `SpConfig::http`; `set_transport` swaps in
+//! another transport, e.g. the cassettes of the `cassette` module.
+//!
+
+use std::fmt::Debug;
+
+use reqwest::blocking::Client;
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+
+use crate::error::SpError;
+use crate::http_config::HttpConfig;
+
+/// A request to the gateway
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone` and `PartialEq` functions
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TransportRequest {
+    pub method: String,
+    pub url: String,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    #[serde(default)]
+    pub body: String,
+}
+
+impl TransportRequest {
+    /// A JSON `POST` request
+    pub fn post_json(url: String, body: String) -> Self {
+        TransportRequest {
+            method: "POST".to_string(),
+            url,
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            body,
+        }
+    }
+
+    /// Adds a header
+    pub fn header(mut self, name: &str, value: String) -> Self {
+        self.headers.push((name.to_string(), value));
+        self
+    }
+}
+
+/// A response of the gateway
+/// This structure implements `Serialize`, `Deserialize`, `Debug`, `Clone` and `PartialEq` functions
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TransportResponse {
+    pub status: u16,
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    #[serde(default)]
+    pub body: String,
+}
+
+impl TransportResponse {
+    /// Value of the header `name`, compared without case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Sends gateway requests
+/// An `Err` means no response was received, http error statuses are `Ok`
+pub trait Transport: Debug + Send + Sync {
+    fn send(&self, request: &TransportRequest) -> Result<TransportResponse, SpError>;
+}
+
+/// Transport over a blocking `reqwest` client
+/// This structure implements `Debug` and `Clone` functions
+#[derive(Debug, Clone)]
+pub struct ReqwestTransport {
+    client: Client,
+}
+
+impl ReqwestTransport {
+    pub fn new(client: Client) -> Self {
+        ReqwestTransport { client }
+    }
+
+    /// Builds the client from `http_config`
+    /// This function must not be called on an async executor thread
+    pub fn from_config(http_config: &HttpConfig) -> Result<Self, SpError> {
+        Ok(Self::new(http_config.build_client()?))
+    }
+}
+
+impl Transport for ReqwestTransport {
+    fn send(&self, request: &TransportRequest) -> Result<TransportResponse, SpError> {
+        let method = Method::from_bytes(request.method.as_bytes())
+            .map_err(|err| SpError::Http(format!("invalid method `{}`: {}", request.method, err)))?;
+        let mut builder = self.client.request(method, request.url.as_str());
+        for (name, value) in &request.headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        let response = builder
+            .body(request.body.clone())
+            .send()
+            .map_err(|err| SpError::Http(err.to_string()))?;
+        let status = response.status().as_u16();
+        let headers = response
+            .headers()
+            .iter()
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        let body = response.text().map_err(|err| SpError::Http(err.to_string()))?;
+        Ok(TransportResponse { status, headers, body })
+    }
+}
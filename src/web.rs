@@ -0,0 +1,366 @@
+//!
+//! Framework independent pieces of the web integrations.
+//!
+//! The axum and actix-web integrations are thin wrappers around the
+//! functions of this module:
+//! - `create_checkout` turns a JSON `CheckoutRequest` into a checkout url
+//! - `verify_order` verifies an order and calls the merchant's callbacks
+//!
+
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::SpError;
+use crate::money::Money;
+use crate::order_id::validate_order_id;
+use crate::payment_store::PaymentState;
+use crate::phone::PhoneNumber;
+use crate::shurjopay::{ShurjopayPlugin, SpCheckout, SpVerifyResponse};
+
+/// `ShurjopayPlugin` shared between request handlers
+pub type SharedPlugin = Arc<Mutex<ShurjopayPlugin>>;
+
+/// Callback for a successfully verified payment
+pub type SuccessCallback = Arc<dyn Fn(&SpVerifyResponse) + Send + Sync>;
+
+/// Callback for a payment that is not successful or could not be verified
+pub type FailureCallback = Arc<dyn Fn(&PaymentOutcome) + Send + Sync>;
+
+/// Checkout request posted by the merchant's frontend
+/// A missing `order_id` is generated with the configured prefix,
+/// a missing `currency` is `BDT` and a missing `client_ip` is the configured default
+/// This structure implements `Serialize`, `Deserialize`, `Debug` and `Clone` functions
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CheckoutRequest {
+    pub amount: String,
+    #[serde(default)]
+    pub order_id: Option<String>,
+    #[serde(default)]
+    pub currency: Option<String>,
+    pub customer_name: String,
+    pub customer_address: String,
+    pub customer_phone: String,
+    pub customer_city: String,
+    pub customer_post_code: String,
+    #[serde(default)]
+    pub client_ip: Option<String>,
+}
+
+/// `order_id` parameter shurjopay appends to the return, cancel and IPN urls
+/// This structure implements `Serialize`, `Deserialize`, `Debug` and `Clone` functions
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct OrderIdParams {
+    #[serde(default)]
+    pub order_id: Option<String>,
+}
+
+/// Result of verifying an order from a return, cancel or IPN request
+/// This structure implements `Serialize`, `Deserialize`, `Debug` and `Clone` functions
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PaymentOutcome {
+    pub order_id: String,
+    pub paid: bool,
+    /// The order is verified as paid for the first time, see `PaymentCallbacks::on_success`
+    #[serde(default)]
+    pub newly_paid: bool,
+    pub sp_code: Option<i64>,
+    pub sp_message: Option<String>,
+    pub response: Option<SpVerifyResponse>,
+    pub error: Option<String>,
+}
+
+/// Merchant callbacks called after an order is verified
+#[derive(Clone, Default)]
+pub struct PaymentCallbacks {
+    on_success: Option<SuccessCallback>,
+    on_failure: Option<FailureCallback>,
+}
+
+impl PaymentCallbacks {
+    /// Sets the callback for successful payments
+    ///
+    /// With a `PaymentStore` the callback is called once per payment, when the ledger
+    /// records it as paid; a later return or IPN of the same order does not call it
+    /// again. Without a store it is called every time the order is verified as paid.
+    pub fn on_success<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&SpVerifyResponse) + Send + Sync + 'static,
+    {
+        self.on_success = Some(Arc::new(callback));
+        self
+    }
+
+    /// Sets the callback for payments that are not successful or could not be verified
+    pub fn on_failure<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&PaymentOutcome) + Send + Sync + 'static,
+    {
+        self.on_failure = Some(Arc::new(callback));
+        self
+    }
+}
+
+/// Wraps a configured plugin to be shared between handlers
+pub fn share(sp_instance: ShurjopayPlugin) -> SharedPlugin {
+    Arc::new(Mutex::new(sp_instance))
+}
+
+/// Owner of a `SharedPlugin` in the state of an async framework
+/// The blocking http client of the plugin owns a runtime that may not be dropped on an
+/// async executor thread, so the last handle drops the plugin on its own thread
+#[cfg(any(feature = "axum", feature = "actix", feature = "tower"))]
+#[derive(Clone)]
+pub(crate) struct PluginHandle(Option<SharedPlugin>);
+
+#[cfg(any(feature = "axum", feature = "actix", feature = "tower"))]
+impl PluginHandle {
+    pub(crate) fn new(plugin: SharedPlugin) -> Self {
+        PluginHandle(Some(plugin))
+    }
+
+    pub(crate) fn shared(&self) -> SharedPlugin {
+        self.0.clone().expect("plugin is only taken on drop")
+    }
+}
+
+#[cfg(any(feature = "axum", feature = "actix", feature = "tower"))]
+impl Drop for PluginHandle {
+    fn drop(&mut self) {
+        if let Some(Ok(sp_instance)) = self.0.take().map(Arc::try_unwrap) {
+            if tokio::runtime::Handle::try_current().is_ok() {
+                std::thread::spawn(move || drop(sp_instance));
+            }
+        }
+    }
+}
+
+/// Locks the shared plugin, a poisoned lock is recovered since the plugin holds no invariants
+pub fn lock(plugin: &SharedPlugin) -> MutexGuard<'_, ShurjopayPlugin> {
+    plugin.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Runs `operation` on a copy of the shared plugin
+///
+/// An expired token is refreshed on the shared plugin first, under its lock, so
+/// concurrent calls request one token between them. The lock is released before
+/// `operation` calls the gateway, a token the call had to refresh is copied back.
+pub(crate) fn run_on_copy<T, F>(plugin: &SharedPlugin, operation: F) -> Result<T, SpError>
+where
+    F: FnOnce(&mut ShurjopayPlugin) -> Result<T, SpError>,
+{
+    let mut sp_instance = {
+        let mut shared = lock(plugin);
+        if shared.verify_auth_token().is_none() {
+            return Err(last_error(&shared, "token request failed"));
+        }
+        shared.clone()
+    };
+    let result = operation(&mut sp_instance);
+    // Keeps a token the call had to refresh for the next calls
+    if sp_instance.auth_token.is_some() {
+        let mut shared = lock(plugin);
+        if shared.token_create_time < sp_instance.token_create_time {
+            shared.auth_token = sp_instance.auth_token.clone();
+            shared.token_create_time = sp_instance.token_create_time;
+            shared.token_expire_time = sp_instance.token_expire_time;
+        }
+    }
+    result
+}
+
+/// The `last_error` of a failed call, or an `SpError::Http` with `message`
+pub(crate) fn last_error(sp_instance: &ShurjopayPlugin, message: &str) -> SpError {
+    sp_instance
+        .last_error
+        .clone()
+        .unwrap_or_else(|| SpError::Http(message.to_string()))
+}
+
+/// Creates a payment at shurjopay and returns its checkout url
+/// The shared plugin is not locked during the gateway call, see `run_on_copy`
+/// This function blocks and must not be called on an async executor thread
+pub fn create_checkout(plugin: &SharedPlugin, request: CheckoutRequest) -> Result<String, SpError> {
+    let checkout = checkout_item(&mut lock(plugin), request)?;
+    run_on_copy(plugin, move |sp_instance| {
+        sp_instance
+            .make_payment_no_auto_redirect(checkout)
+            .ok_or_else(|| last_error(sp_instance, "checkout failed"))
+    })
+}
+
+/// Checks the fields of a checkout request that shurjopay would refuse
+/// A given `order_id` must pass `validate_order_id`, `amount` must be a positive amount
+/// of at most two decimals and `customer_phone` a Bangladeshi mobile number
+pub fn validate_request(request: &CheckoutRequest) -> Result<PhoneNumber, SpError> {
+    if let Some(order_id) = &request.order_id {
+        validate_order_id(order_id)?;
+    }
+    let amount = Money::parse(&request.amount, request.currency.as_deref().unwrap_or("BDT"))?;
+    if amount.minor() <= 0 {
+        return Err(SpError::InvalidAmount(format!("amount {} must be positive", request.amount)));
+    }
+    PhoneNumber::parse(&request.customer_phone)
+}
+
+/// Builds the `SpCheckout` of a checkout request, generating a missing order id
+/// The request is checked with `validate_request`, `customer_phone` is sent in the national format
+pub fn checkout_item(sp_instance: &mut ShurjopayPlugin, request: CheckoutRequest) -> Result<SpCheckout, SpError> {
+    if sp_instance.config.is_none() {
+        return Err(SpError::Config("Shurjopay Configuration is not set yet!".to_string()));
+    }
+    let customer_phone = validate_request(&request)?;
+    let order_id = match request.order_id {
+        Some(order_id) => order_id,
+        None => sp_instance.generate_order_id()?,
+    };
+    let mut checkout = sp_instance.make_payment_request_object(
+        request.amount,
+        order_id,
+        request.currency.unwrap_or_else(|| "BDT".to_string()),
+        request.customer_name,
+        request.customer_address,
+        customer_phone.to_string(),
+        request.customer_city,
+        request.customer_post_code,
+    );
+    if let Some(client_ip) = request.client_ip {
+        checkout.client_ip = client_ip;
+    }
+    Ok(checkout)
+}
+
+/// Verifies `order_id` and calls the matching callback
+/// An `order_id` that shurjopay cannot have issued is refused without calling the gateway
+/// This function blocks and must not be called on an async executor thread
+pub fn verify_order(plugin: &SharedPlugin, order_id: &str, callbacks: &PaymentCallbacks) -> PaymentOutcome {
+    if let Err(err) = validate_order_id(order_id) {
+        let outcome = PaymentOutcome {
+            order_id: order_id.to_string(),
+            paid: false,
+            newly_paid: false,
+            sp_code: None,
+            sp_message: None,
+            response: None,
+            error: Some(err.to_string()),
+        };
+        if let Some(on_failure) = &callbacks.on_failure {
+            on_failure(&outcome);
+        }
+        return outcome;
+    }
+    let verified = run_on_copy(plugin, |sp_instance| match sp_instance.verify_payment(Some(order_id.to_string())) {
+        Some(response) => {
+            // The ledger reports the change to paid to only one of concurrent verifications
+            let newly_paid = match &sp_instance.verify_transition {
+                Some(transition) => transition.to == PaymentState::Paid,
+                None => sp_instance
+                    .payment_store()
+                    .and_then(|store| store.find_by_sp_order_id(order_id).ok().flatten())
+                    .is_none(),
+            };
+            Ok((response, newly_paid))
+        }
+        None => Err(last_error(sp_instance, "verification failed")),
+    });
+    let (response, error, newly_paid) = match verified {
+        Ok((response, newly_paid)) => (Some(response), None, newly_paid),
+        Err(err) => (None, Some(err.to_string()), false),
+    };
+
+    let sp_code = response.as_ref().and_then(|response| response.sp_code);
+    let paid = response.is_some() && PaymentState::from_sp_code(sp_code) == PaymentState::Paid;
+    let outcome = PaymentOutcome {
+        order_id: order_id.to_string(),
+        paid,
+        newly_paid: paid && newly_paid,
+        sp_code,
+        sp_message: response.as_ref().and_then(|response| response.sp_message.clone()),
+        response,
+        error,
+    };
+    match (&outcome.response, outcome.paid) {
+        (Some(response), true) => {
+            if let (Some(on_success), true) = (&callbacks.on_success, outcome.newly_paid) {
+                on_success(response);
+            }
+        }
+        _ => {
+            if let Some(on_failure) = &callbacks.on_failure {
+                on_failure(&outcome);
+            }
+        }
+    }
+    outcome
+}
+
+/// Reads the `order_id` of an IPN body sent as JSON or as a form
+pub fn order_id_from_body(body: &[u8]) -> Option<String> {
+    if let Ok(params) = serde_json::from_slice::<OrderIdParams>(body) {
+        return params.order_id;
+    }
+    let body = std::str::from_utf8(body).ok()?;
+    body.split('&').find_map(|pair| {
+        let (name, value) = pair.split_once('=')?;
+        if name == "order_id" {
+            Some(percent_decode(value))
+        } else {
+            None
+        }
+    })
+}
+
+/// HTTP status code for a failed checkout
+pub fn checkout_error_status(error: &SpError) -> u16 {
+    match error {
+        SpError::InvalidOrderId(_) | SpError::InvalidAmount(_) | SpError::InvalidPhone(_) => 400,
+        SpError::Config(_) | SpError::Store(_) => 500,
+        SpError::CircuitOpen { .. } | SpError::Overloaded(_) => 503,
+        SpError::RateLimited { .. } => 429,
+        SpError::LinkNotFound(_) | SpError::SubscriptionNotFound(_) => 404,
+        SpError::LinkExpired(_) => 410,
+        SpError::LinkConsumed(_)
+        | SpError::IdempotencyConflict { .. }
+        | SpError::AlreadyPaid(_)
+        | SpError::CheckoutInFlight(_) => 409,
+        _ => 502,
+    }
+}
+
+/// Appends `order_id` to a merchant redirect url
+pub fn redirect_url(url: &str, order_id: &str) -> String {
+    let separator = if url.contains('?') { '&' } else { '?' };
+    format!("{}{}order_id={}", url, separator, percent_encode(order_id))
+}
+
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let hex = |at: usize| bytes.get(at).and_then(|byte| (*byte as char).to_digit(16));
+        match bytes[index] {
+            b'+' => out.push(b' '),
+            b'%' => match (hex(index + 1), hex(index + 2)) {
+                (Some(high), Some(low)) => {
+                    out.push((high * 16 + low) as u8);
+                    index += 2;
+                }
+                _ => out.push(b'%'),
+            },
+            byte => out.push(byte),
+        }
+        index += 1;
+    }
+    String::from_utf8_lossy(&out).to_string()
+}
@@ -0,0 +1,141 @@
+#![cfg(feature = "actix")]
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use std::sync::{Arc, Mutex};
+
+    use actix_web::http::{header, StatusCode};
+    use actix_web::{test, web, App};
+
+    use shurjopay_plugin::actix_integration::ShurjopayData;
+    use shurjopay_plugin::web::PaymentOutcome;
+
+    use crate::common::{MockGateway, MockResponse};
+
+    const CHECKOUT_BODY: &str = r#"{"amount":"786","order_id":"actix-001","customer_name":"Mahmudul Islam","customer_address":"Dhaka","customer_phone":"01811177722","customer_city":"Dhaka","customer_post_code":"1203"}"#;
+
+    async fn data(gateway: &MockGateway) -> ShurjopayData {
+        let gateway = gateway.clone();
+        let sp_instance = web::block(move || gateway.plugin()).await.unwrap();
+        ShurjopayData::new(sp_instance)
+    }
+
+    #[actix_web::test]
+    async fn checkout_uses_client_ip_of_connection_test() {
+        let gateway = MockGateway::start();
+        let app = test::init_service(App::new().service(data(&gateway).await.scope("/shurjopay"))).await;
+
+        let request = test::TestRequest::post()
+            .uri("/shurjopay/checkout")
+            .peer_addr("203.0.113.7:51234".parse().unwrap())
+            .insert_header((header::CONTENT_TYPE, "application/json"))
+            .set_payload(CHECKOUT_BODY)
+            .to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let location = response.headers().get(header::LOCATION).unwrap().to_str().unwrap();
+        assert!(location.starts_with(&format!("{}/spaycheckout/", gateway.url)));
+
+        let checkout = gateway.requests().into_iter().find(|req| req.path == "/api/secret-pay/").unwrap();
+        let body: serde_json::Value = serde_json::from_str(&checkout.body).unwrap();
+        assert_eq!(body["client_ip"], "203.0.113.7");
+    }
+
+    #[actix_web::test]
+    async fn forwarded_headers_need_trust_test() {
+        let gateway = MockGateway::start();
+        let checkout = |app_data: ShurjopayData| async move {
+            let app = test::init_service(App::new().service(app_data.scope("/shurjopay"))).await;
+            let request = test::TestRequest::post()
+                .uri("/shurjopay/checkout")
+                .peer_addr("203.0.113.7:51234".parse().unwrap())
+                .insert_header(("x-forwarded-for", "198.51.100.9"))
+                .insert_header((header::CONTENT_TYPE, "application/json"))
+                .set_payload(CHECKOUT_BODY)
+                .to_request();
+            assert_eq!(test::call_service(&app, request).await.status(), StatusCode::SEE_OTHER);
+        };
+        let client_ip = |gateway: &MockGateway| {
+            let checkout = gateway.requests().into_iter().rev().find(|req| req.path == "/api/secret-pay/").unwrap();
+            serde_json::from_str::<serde_json::Value>(&checkout.body).unwrap()["client_ip"].clone()
+        };
+
+        checkout(data(&gateway).await).await;
+        assert_eq!(client_ip(&gateway), "203.0.113.7");
+        checkout(data(&gateway).await.trust_forwarded_headers()).await;
+        assert_eq!(client_ip(&gateway), "198.51.100.9");
+    }
+
+    #[actix_web::test]
+    async fn return_calls_success_callback_and_redirects_test() {
+        let gateway = MockGateway::start();
+        gateway.set_verified("sp-paid-1", "actix-002", 786.0, "BDT", 1000);
+        let paid = Arc::new(Mutex::new(Vec::new()));
+        let seen = paid.clone();
+        let data = data(&gateway)
+            .await
+            .on_success(move |response| seen.lock().unwrap().push(response.order_id.clone()))
+            .success_redirect("/thanks");
+        let app = test::init_service(App::new().service(data.scope("/shurjopay"))).await;
+
+        let request = test::TestRequest::get().uri("/shurjopay/return?order_id=sp-paid-1").to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(response.headers().get(header::LOCATION).unwrap().to_str().unwrap(), "/thanks?order_id=sp-paid-1");
+        assert_eq!(*paid.lock().unwrap(), vec![Some("sp-paid-1".to_string())]);
+    }
+
+    #[actix_web::test]
+    async fn requests_are_handled_concurrently_test() {
+        let gateway = MockGateway::start();
+        let body = r#"[{"order_id":"sp-slow-1","customer_order_id":"actix-004","currency":"BDT","amount":786,"payable_amount":786,"sp_code":1000,"sp_message":"Success"}]"#;
+        for _ in 0..4 {
+            gateway.enqueue("/api/verification/", MockResponse::json(200, body).delayed(400));
+        }
+        let app = test::init_service(App::new().service(data(&gateway).await.scope("/shurjopay"))).await;
+
+        let notify = || {
+            let request = test::TestRequest::post()
+                .uri("/shurjopay/notify")
+                .set_json(serde_json::json!({ "order_id": "sp-slow-1" }))
+                .to_request();
+            test::call_and_read_body_json::<_, _, PaymentOutcome>(&app, request)
+        };
+        let started = std::time::Instant::now();
+        let outcomes = tokio::join!(notify(), notify(), notify(), notify());
+        assert!(started.elapsed() < std::time::Duration::from_millis(1200), "{:?}", started.elapsed());
+        assert!(outcomes.0.paid && outcomes.1.paid && outcomes.2.paid && outcomes.3.paid);
+    }
+
+    #[actix_web::test]
+    async fn cancel_and_notify_test() {
+        let gateway = MockGateway::start();
+        gateway.set_verified("sp-cancel-1", "actix-003", 786.0, "BDT", 1002);
+        let failed = Arc::new(Mutex::new(Vec::new()));
+        let seen = failed.clone();
+        let data = data(&gateway)
+            .await
+            .on_failure(move |outcome| seen.lock().unwrap().push(outcome.order_id.clone()));
+        let app = test::init_service(App::new().service(data.scope("/shurjopay"))).await;
+
+        let request = test::TestRequest::get().uri("/shurjopay/cancel?order_id=sp-cancel-1").to_request();
+        let outcome: PaymentOutcome = test::call_and_read_body_json(&app, request).await;
+        assert!(!outcome.paid);
+        assert_eq!(outcome.sp_code, Some(1002));
+
+        let request = test::TestRequest::post()
+            .uri("/shurjopay/notify")
+            .set_json(serde_json::json!({ "order_id": "sp-cancel-1" }))
+            .to_request();
+        let outcome: PaymentOutcome = test::call_and_read_body_json(&app, request).await;
+        assert_eq!(outcome.order_id, "sp-cancel-1");
+        assert_eq!(*failed.lock().unwrap(), vec!["sp-cancel-1".to_string(); 2]);
+
+        let request = test::TestRequest::get().uri("/shurjopay/cancel").to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
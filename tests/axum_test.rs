@@ -0,0 +1,114 @@
+#![cfg(feature = "axum")]
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use std::sync::{Arc, Mutex};
+
+    use axum::body::{to_bytes, Body};
+    use axum::http::{header, Request, StatusCode};
+    use axum::Router;
+    use tower::ServiceExt;
+
+    use shurjopay_plugin::axum_integration::ShurjopayRoutes;
+    use shurjopay_plugin::web::PaymentOutcome;
+
+    use crate::common::MockGateway;
+
+    const CHECKOUT_BODY: &str = r#"{"amount":"786","order_id":"axum-001","customer_name":"Mahmudul Islam","customer_address":"Dhaka","customer_phone":"01811177722","customer_city":"Dhaka","customer_post_code":"1203"}"#;
+
+    async fn routes(gateway: &MockGateway) -> ShurjopayRoutes {
+        let gateway = gateway.clone();
+        let sp_instance = tokio::task::spawn_blocking(move || gateway.plugin()).await.unwrap();
+        ShurjopayRoutes::new(sp_instance)
+    }
+
+    async fn outcome(router: Router, request: Request<Body>) -> (StatusCode, PaymentOutcome) {
+        let response = router.oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn checkout_redirects_to_shurjopay_test() {
+        let gateway = MockGateway::start();
+        let router: Router = routes(&gateway).await.router();
+
+        let request = Request::post("/checkout")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(CHECKOUT_BODY))
+            .unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        let location = response.headers()[header::LOCATION].to_str().unwrap();
+        assert!(location.starts_with(&format!("{}/spaycheckout/", gateway.url)));
+        assert_eq!(gateway.hits("/api/secret-pay/"), 1);
+    }
+
+    #[tokio::test]
+    async fn return_calls_success_callback_and_redirects_test() {
+        let gateway = MockGateway::start();
+        gateway.set_verified("sp-paid-1", "axum-002", 786.0, "BDT", 1000);
+        let paid = Arc::new(Mutex::new(Vec::new()));
+        let seen = paid.clone();
+        let router: Router = routes(&gateway).await
+            .on_success(move |response| seen.lock().unwrap().push(response.order_id.clone()))
+            .success_redirect("/thanks")
+            .router();
+
+        let request = Request::get("/return?order_id=sp-paid-1").body(Body::empty()).unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(response.headers()[header::LOCATION], "/thanks?order_id=sp-paid-1");
+        assert_eq!(*paid.lock().unwrap(), vec![Some("sp-paid-1".to_string())]);
+    }
+
+    #[tokio::test]
+    async fn cancel_and_missing_order_id_test() {
+        let gateway = MockGateway::start();
+        gateway.set_verified("sp-cancel-1", "axum-003", 786.0, "BDT", 1002);
+        let failed = Arc::new(Mutex::new(Vec::new()));
+        let seen = failed.clone();
+        let router: Router = routes(&gateway).await
+            .on_failure(move |outcome| seen.lock().unwrap().push(outcome.order_id.clone()))
+            .router();
+
+        let request = Request::get("/cancel?order_id=sp-cancel-1").body(Body::empty()).unwrap();
+        let (status, outcome) = outcome(router.clone(), request).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(!outcome.paid);
+        assert_eq!(outcome.sp_code, Some(1002));
+        assert_eq!(*failed.lock().unwrap(), vec!["sp-cancel-1".to_string()]);
+
+        let request = Request::get("/cancel").body(Body::empty()).unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn ipn_reads_order_id_from_form_body_test() {
+        let gateway = MockGateway::start();
+        gateway.set_verified("sp-ipn-1", "axum-004", 786.0, "BDT", 1000);
+        let router: Router = routes(&gateway).await.router();
+
+        let request = Request::post("/ipn")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from("order_id=sp-ipn-1&status=success"))
+            .unwrap();
+        let (status, outcome) = outcome(router.clone(), request).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(outcome.paid);
+        assert_eq!(outcome.order_id, "sp-ipn-1");
+
+        let request = Request::post("/ipn")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from("order_id=x%22%2C%22foo%22%3A%221"))
+            .unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(gateway.hits("/api/verification/"), 1);
+    }
+}
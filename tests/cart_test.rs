@@ -0,0 +1,99 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use shurjopay_plugin::cart::{Cart, CartStore, LineItem, MemoryCartStore, VatRate};
+    use shurjopay_plugin::error::SpError;
+    use shurjopay_plugin::money::Money;
+    use shurjopay_plugin::payment_store::PaymentState;
+    use shurjopay_plugin::reconciliation;
+
+    use crate::common::{checkout_request, MockGateway};
+
+    fn bdt(amount: &str) -> Money {
+        Money::parse(amount, "BDT").unwrap()
+    }
+
+    fn cart() -> Cart {
+        let mut cart = Cart::new("bdt");
+        cart.add_item(LineItem::new("TEA-250", "Tea 250g", 3, bdt("120.50")).with_vat(VatRate::percent(15)))
+            .unwrap()
+            .add_item(LineItem::new("MUG-1", "Mug", 1, bdt("250")).with_vat(VatRate::from_basis_points(750)))
+            .unwrap()
+            .add_item(LineItem::new("BOOK-9", "Book", 2, bdt("99.99")))
+            .unwrap()
+            .add_discount("WELCOME50", bdt("50"))
+            .unwrap()
+            .set_shipping(bdt("60"))
+            .unwrap();
+        cart
+    }
+
+    #[test]
+    fn money_arithmetic_test() {
+        assert_eq!(bdt("120.50").checked_mul(3).unwrap(), bdt("361.50"));
+        assert_eq!(bdt("10.10").basis_points(1500).unwrap(), bdt("1.52"));
+        assert_eq!(bdt("0.10").basis_points(500).unwrap(), bdt("0.01"));
+        assert_eq!(bdt("-0.10").basis_points(500).unwrap(), bdt("-0.01"));
+        assert!(Money::from_minor(i64::MAX, "BDT").checked_mul(2).is_err());
+    }
+
+    #[test]
+    fn totals_test() {
+        let totals = cart().totals().unwrap();
+        assert_eq!(totals.subtotal, bdt("811.48"));
+        assert_eq!(totals.discount, bdt("50"));
+
+        let discounts: Vec<Money> = totals.lines.iter().map(|line| line.discount.clone()).collect();
+        assert_eq!(discounts, vec![bdt("22.28"), bdt("15.40"), bdt("12.32")]);
+        let vats: Vec<Money> = totals.lines.iter().map(|line| line.vat.clone()).collect();
+        assert_eq!(vats, vec![bdt("50.88"), bdt("17.60"), bdt("0")]);
+        assert_eq!(totals.lines[0].total, bdt("390.10"));
+
+        assert_eq!(totals.vat, bdt("68.48"));
+        assert_eq!(totals.shipping, bdt("60"));
+        assert_eq!(totals.total, bdt("889.96"));
+    }
+
+    #[test]
+    fn invalid_cart_test() {
+        let mut cart = Cart::new("BDT");
+        assert_eq!(cart.totals(), Err(SpError::InvalidAmount("cart is empty".to_string())));
+        assert!(matches!(
+            cart.add_item(LineItem::new("X", "X", 1, Money::parse("1", "USD").unwrap())),
+            Err(SpError::CurrencyMismatch { .. })
+        ));
+        assert!(matches!(cart.add_item(LineItem::new("X", "X", 0, bdt("1"))), Err(SpError::InvalidAmount(_))));
+        assert!(matches!(cart.add_discount("BAD", bdt("-1")), Err(SpError::InvalidAmount(_))));
+
+        cart.add_item(LineItem::new("X", "X", 1, bdt("100"))).unwrap();
+        cart.add_discount("ALL", bdt("150")).unwrap();
+        assert!(matches!(cart.totals(), Err(SpError::InvalidAmount(_))));
+        cart.set_shipping(bdt("40")).unwrap();
+        let totals = cart.totals().unwrap();
+        assert_eq!((totals.discount, totals.total), (bdt("100"), bdt("40")));
+    }
+
+    #[test]
+    fn make_payment_cart_test() {
+        let gateway = MockGateway::start();
+        let mut sp_instance = gateway.plugin();
+        let store = MemoryCartStore::new();
+        let cart = cart();
+
+        let checkout = checkout_request(&mut sp_instance, "0", "INV-CART");
+        let response = sp_instance.make_payment_cart(&cart, checkout, &store).unwrap();
+        assert_eq!(response.amount, "889.96");
+        assert_eq!(store.find_cart("INV-CART").unwrap(), Some(cart.clone()));
+
+        gateway.set_verified(&response.sp_order_id, "INV-CART", 889.96, "BDT", 1000);
+        let verified = sp_instance.verify_payment(Some(response.sp_order_id.clone())).unwrap();
+        let record = cart.local_record(&response.sp_order_id, PaymentState::Paid).unwrap();
+        assert!(reconciliation::compare(&record, Some(&verified)).is_empty());
+
+        gateway.set_verified(&response.sp_order_id, "INV-CART", 839.96, "BDT", 1000);
+        let verified = sp_instance.verify_payment(Some(response.sp_order_id)).unwrap();
+        assert!(!reconciliation::compare(&record, Some(&verified)).is_empty());
+    }
+}
@@ -0,0 +1,47 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::Arc;
+
+    use shurjopay_plugin::cassette::{RecordingTransport, ReplayTransport, SCRUBBED};
+    use shurjopay_plugin::http_config::HttpConfig;
+    use shurjopay_plugin::transport::ReqwestTransport;
+
+    use crate::common::{checkout_request, MockGateway};
+
+    #[test]
+    fn record_and_replay_checkout_test() {
+        let path = std::env::temp_dir().join(format!("sp-cassette-{}.json", std::process::id()));
+        let gateway = MockGateway::start();
+
+        let mut sp_instance = gateway.plugin();
+        let real = Arc::new(ReqwestTransport::from_config(&HttpConfig::default()).unwrap());
+        let recording = Arc::new(RecordingTransport::new(real, path.clone()));
+        sp_instance.set_transport(recording.clone());
+        let checkout = checkout_request(&mut sp_instance, "1000", "INV-1");
+        let recorded_url = sp_instance.make_payment_no_auto_redirect(checkout).unwrap();
+        assert_eq!(recording.cassette().interactions.len(), 2);
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert!(!text.contains(&gateway.config().sp_pass));
+        assert!(!text.contains("mock-token"));
+        assert!(text.contains(SCRUBBED));
+
+        let hits = gateway.requests().len();
+        let mut sp_instance = gateway.plugin();
+        let replay = Arc::new(ReplayTransport::load(&path).unwrap());
+        sp_instance.set_transport(replay.clone());
+        let checkout = checkout_request(&mut sp_instance, "1000", "INV-1");
+        assert_eq!(sp_instance.make_payment_no_auto_redirect(checkout), Some(recorded_url));
+        assert_eq!(replay.unplayed(), 0);
+        assert_eq!(gateway.requests().len(), hits);
+
+        let checkout = checkout_request(&mut sp_instance, "2500", "INV-2");
+        let unmatched = panic::catch_unwind(AssertUnwindSafe(|| sp_instance.make_payment_no_auto_redirect(checkout)));
+        assert!(unmatched.is_err());
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,95 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use shurjopay_plugin::circuit_breaker::{CircuitBreaker, CircuitBreakerConfig, CircuitState};
+    use shurjopay_plugin::error::SpError;
+
+    use crate::common::{checkout_request, MockGateway, MockResponse};
+
+    fn config(cool_down: Duration) -> CircuitBreakerConfig {
+        CircuitBreakerConfig {
+            window: 4,
+            minimum_requests: 2,
+            failure_rate: 0.5,
+            cool_down,
+        }
+    }
+
+    #[test]
+    fn circuit_opens_and_half_opens_test() {
+        let breaker = CircuitBreaker::new(config(Duration::from_millis(50)));
+        breaker.record_success("verification");
+        assert_eq!(breaker.state("verification"), CircuitState::Closed);
+        breaker.record_failure("verification");
+        assert_eq!(breaker.state("verification"), CircuitState::Open);
+        assert_eq!(breaker.state("get_token"), CircuitState::Closed);
+        assert!(matches!(breaker.allow("verification"), Err(SpError::CircuitOpen { .. })));
+
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(breaker.state("verification"), CircuitState::HalfOpen);
+        assert!(breaker.allow("verification").is_ok());
+        assert!(breaker.allow("verification").is_err());
+        breaker.record_failure("verification");
+        assert_eq!(breaker.state("verification"), CircuitState::Open);
+
+        thread::sleep(Duration::from_millis(60));
+        assert!(breaker.allow("verification").is_ok());
+        breaker.record_success("verification");
+        assert_eq!(breaker.state("verification"), CircuitState::Closed);
+    }
+
+    #[test]
+    fn failing_checkout_end_point_fails_fast_test() {
+        let gateway = MockGateway::start();
+        for _ in 0..2 {
+            gateway.enqueue("/api/secret-pay/", MockResponse::json(503, r#"{"message":"Service Unavailable"}"#));
+        }
+        let mut sp_instance = gateway.plugin();
+        sp_instance.set_circuit_breaker(Arc::new(CircuitBreaker::new(config(Duration::from_secs(60)))));
+
+        for order_id in ["cb-001", "cb-002"] {
+            let payment_req_obj = checkout_request(&mut sp_instance, "786", order_id);
+            assert!(sp_instance.make_payment_no_auto_redirect(payment_req_obj).is_none());
+            assert!(matches!(sp_instance.last_error, Some(SpError::Gateway { http_code: 503, .. })));
+        }
+
+        let payment_req_obj = checkout_request(&mut sp_instance, "786", "cb-003");
+        assert!(sp_instance.make_payment_no_auto_redirect(payment_req_obj).is_none());
+        assert!(matches!(
+            &sp_instance.last_error,
+            Some(SpError::CircuitOpen { end_point, .. }) if end_point.as_str() == "/api/secret-pay"
+        ));
+        assert_eq!(gateway.hits("/api/secret-pay/"), 2);
+
+        let states = sp_instance.circuit_breaker().states();
+        assert_eq!(states.get("/api/secret-pay"), Some(&CircuitState::Open));
+        assert_eq!(states.get("/api/get_token"), Some(&CircuitState::Closed));
+    }
+
+    #[test]
+    fn client_error_keeps_half_open_probe_test() {
+        let gateway = MockGateway::start();
+        let mut sp_instance = gateway.plugin();
+        let breaker = Arc::new(CircuitBreaker::new(config(Duration::from_millis(50))));
+        sp_instance.set_circuit_breaker(breaker.clone());
+        breaker.record_failure("/api/get_token");
+        breaker.record_failure("/api/get_token");
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(breaker.state("/api/get_token"), CircuitState::HalfOpen);
+
+        sp_instance.config.as_mut().unwrap().http.pin_root_certificates = true;
+        assert!(sp_instance.get_auth_token().is_none());
+        assert!(matches!(sp_instance.last_error, Some(SpError::Config(_))));
+        assert_eq!(breaker.state("/api/get_token"), CircuitState::HalfOpen);
+
+        sp_instance.config.as_mut().unwrap().http.pin_root_certificates = false;
+        assert!(sp_instance.get_auth_token().is_some());
+        assert_eq!(breaker.state("/api/get_token"), CircuitState::Closed);
+    }
+}
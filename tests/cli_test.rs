@@ -0,0 +1,186 @@
+#![cfg(feature = "cli")]
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use std::process::{Command, Output};
+
+    use crate::common::{MockGateway, MockResponse};
+
+    fn shurjopay(gateway: &MockGateway, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_shurjopay"))
+            .args(args)
+            .env("POST_DEFAULT_ADDRESS", &gateway.url)
+            .output()
+            .unwrap()
+    }
+
+    #[test]
+    fn token_json_test() {
+        let gateway = MockGateway::start();
+        let output = shurjopay(&gateway, &["token", "--json"]);
+        assert_eq!(output.status.code(), Some(0));
+        let token: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(token["expires_in"], 3600);
+        assert_eq!(token["token_type"], "Bearer");
+    }
+
+    #[test]
+    fn checkout_prints_checkout_url_test() {
+        let gateway = MockGateway::start();
+        let output = shurjopay(
+            &gateway,
+            &[
+                "checkout",
+                "--amount", "786",
+                "--order-id", "cli-001",
+                "--customer-name", "Mahmudul Islam",
+                "--customer-address", "Dhaka",
+                "--customer-phone", "01811177722",
+                "--customer-city", "Dhaka",
+                "--customer-post-code", "1203",
+            ],
+        );
+        assert_eq!(output.status.code(), Some(0));
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains(&format!("checkout_url: {}/spaycheckout/", gateway.url)));
+        assert!(stdout.contains("sp_order_id:  sp-mock-1"));
+    }
+
+    #[test]
+    fn checkout_rejects_invalid_phone_test() {
+        let gateway = MockGateway::start();
+        let output = shurjopay(
+            &gateway,
+            &[
+                "checkout",
+                "--amount", "786",
+                "--order-id", "cli-002",
+                "--customer-name", "Mahmudul Islam",
+                "--customer-address", "Dhaka",
+                "--customer-phone", "02-9123456",
+                "--customer-city", "Dhaka",
+                "--customer-post-code", "1203",
+            ],
+        );
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8(output.stderr).unwrap().contains("not a mobile number"));
+        assert_eq!(gateway.hits("/api/secret-pay/"), 0);
+    }
+
+    #[cfg(feature = "qr")]
+    #[test]
+    fn checkout_writes_qr_code_test() {
+        let gateway = MockGateway::start();
+        let file = std::env::temp_dir().join(format!("sp-qr-{}.png", std::process::id()));
+        let checkout = [
+            "checkout",
+            "--amount", "786",
+            "--order-id", "cli-qr",
+            "--customer-name", "Mahmudul Islam",
+            "--customer-address", "Dhaka",
+            "--customer-phone", "01811177722",
+            "--customer-city", "Dhaka",
+            "--customer-post-code", "1203",
+        ];
+
+        let mut args = checkout.to_vec();
+        args.extend(["--qr", "png", "--qr-error-correction", "H", "--qr-output", file.to_str().unwrap()]);
+        let output = shurjopay(&gateway, &args);
+        assert_eq!(output.status.code(), Some(0));
+        let png = std::fs::read(&file).unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        let _ = std::fs::remove_file(&file);
+
+        let mut args = checkout.to_vec();
+        args.extend(["--json", "--qr", "terminal"]);
+        let output = shurjopay(&gateway, &args);
+        assert_eq!(output.status.code(), Some(0));
+        let response: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(response["customer_order_id"], "cli-qr");
+        assert!(String::from_utf8(output.stderr).unwrap().contains('\u{2580}'));
+
+        let mut args = checkout.to_vec();
+        args.extend(["--qr", "png"]);
+        assert_eq!(shurjopay(&gateway, &args).status.code(), Some(2));
+        let mut args = checkout.to_vec();
+        args.extend(["--qr", "svg", "--qr-module-size", "65"]);
+        assert_eq!(shurjopay(&gateway, &args).status.code(), Some(2));
+        // Usage errors are refused before the checkout is sent
+        assert_eq!(gateway.hits("/api/secret-pay/"), 2);
+
+        let missing_dir = std::env::temp_dir().join(format!("sp-missing-{}", std::process::id())).join("qr.svg");
+        let mut args = checkout.to_vec();
+        args.extend(["--qr", "svg", "--qr-output", missing_dir.to_str().unwrap()]);
+        let output = shurjopay(&gateway, &args);
+        assert_eq!(output.status.code(), Some(7));
+        assert!(String::from_utf8(output.stderr).unwrap().contains("cannot write"));
+    }
+
+    #[test]
+    fn verify_exit_codes_test() {
+        let gateway = MockGateway::start();
+        gateway.set_verified("sp-paid", "ord-1", 100.0, "BDT", 1000);
+        gateway.set_verified("sp-cancelled", "ord-2", 100.0, "BDT", 1002);
+
+        assert_eq!(shurjopay(&gateway, &["verify", "sp-paid"]).status.code(), Some(0));
+        assert_eq!(shurjopay(&gateway, &["status", "sp-cancelled"]).status.code(), Some(1));
+        assert_eq!(shurjopay(&gateway, &["verify", "sp-missing"]).status.code(), Some(6));
+
+        let output = shurjopay(&gateway, &["verify", "sp-paid", "--json"]);
+        let response: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(response["customer_order_id"], "ord-1");
+    }
+
+    #[test]
+    fn verify_batch_test() {
+        let gateway = MockGateway::start();
+        gateway.set_verified("sp-a", "ord-a", 10.0, "BDT", 1000);
+        gateway.set_verified("sp-b", "ord-b", 20.0, "BDT", 1000);
+        let file = std::env::temp_dir().join(format!("sp-batch-{}.txt", std::process::id()));
+        std::fs::write(&file, "# order ids\nsp-a\n\nsp-b\n").unwrap();
+
+        let output = shurjopay(&gateway, &["verify-batch", file.to_str().unwrap(), "--json"]);
+        assert_eq!(output.status.code(), Some(0));
+        let lines: Vec<serde_json::Value> = String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["order_id"], "sp-b");
+
+        let _ = std::fs::remove_file(&file);
+        let output = shurjopay(&gateway, &["verify-batch", file.to_str().unwrap()]);
+        assert_eq!(output.status.code(), Some(7));
+        assert!(String::from_utf8(output.stderr).unwrap().contains("cannot read"));
+    }
+
+    #[test]
+    fn verify_batch_json_failure_keeps_stdout_json_test() {
+        let gateway = MockGateway::start();
+        gateway.set_verified("sp-a", "ord-a", 10.0, "BDT", 1000);
+        gateway.set_verify_body("sp-bad", "<html>Bad Gateway</html>");
+        let file = std::env::temp_dir().join(format!("sp-batch-fail-{}.txt", std::process::id()));
+        std::fs::write(&file, "sp-bad\nsp-a\n").unwrap();
+
+        let output = shurjopay(&gateway, &["verify-batch", file.to_str().unwrap(), "--json"]);
+        assert_eq!(output.status.code(), Some(4));
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let lines: Vec<serde_json::Value> = stdout.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0]["order_id"], "sp-a");
+        assert!(String::from_utf8(output.stderr).unwrap().contains("sp-bad"));
+
+        let _ = std::fs::remove_file(&file);
+    }
+
+    #[test]
+    fn auth_failure_exit_code_test() {
+        let gateway = MockGateway::start();
+        gateway.enqueue("/api/get_token/", MockResponse::json(401, r#"{"sp_code":"1064","message":"Unauthorized"}"#));
+        assert_eq!(shurjopay(&gateway, &["token"]).status.code(), Some(5));
+    }
+}
@@ -0,0 +1,260 @@
+//!
+//! In process mock of the shurjopay gateway used by the integration tests.
+//!
+//! The mock listens on a random local port and answers the token, checkout,
+//! verification and payment status endpoints the way the sandbox does.
+//!
+
+#![allow(dead_code)]
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use chrono::{Duration, Utc};
+use shurjopay_plugin::shurjopay::{ShurjopayPlugin, SpConfig};
+
+/// A request received by the mock gateway
+#[derive(Debug, Clone)]
+pub struct MockRequest {
+    pub method: String,
+    pub path: String,
+    pub headers: HashMap<String, String>,
+    pub body: String,
+}
+
+/// A canned response of the mock gateway
+#[derive(Debug, Clone)]
+pub struct MockResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+    pub delay_ms: u64,
+}
+
+impl MockResponse {
+    pub fn json(status: u16, body: &str) -> Self {
+        MockResponse {
+            status,
+            headers: vec![("Content-Type".to_string(), "application/json".to_string())],
+            body: body.to_string(),
+            delay_ms: 0,
+        }
+    }
+
+    /// Delays the response by `delay_ms` milliseconds
+    pub fn delayed(mut self, delay_ms: u64) -> Self {
+        self.delay_ms = delay_ms;
+        self
+    }
+}
+
+#[derive(Default)]
+struct MockState {
+    requests: Vec<MockRequest>,
+    queued: HashMap<String, VecDeque<MockResponse>>,
+    verify: HashMap<String, String>,
+    checkouts: u32,
+}
+
+/// Handle of a running mock gateway
+#[derive(Clone)]
+pub struct MockGateway {
+    pub url: String,
+    state: Arc<Mutex<MockState>>,
+}
+
+impl MockGateway {
+    /// Starts the mock gateway on a random local port
+    pub fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let gateway = MockGateway {
+            url,
+            state: Arc::new(Mutex::new(MockState::default())),
+        };
+        let server = gateway.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let server = server.clone();
+                thread::spawn(move || server.handle(stream));
+            }
+        });
+        gateway
+    }
+
+    /// Config pointing the plugin at the mock gateway
+    pub fn config(&self) -> SpConfig {
+        SpConfig {
+            post_default_address: self.url.clone(),
+            default_return_url: format!("{}/response", self.url),
+            default_cancel_url: format!("{}/response", self.url),
+            ..Default::default()
+        }
+    }
+
+    /// A `ShurjopayPlugin` configured against the mock gateway
+    pub fn plugin(&self) -> ShurjopayPlugin {
+        let mut sp_instance = ShurjopayPlugin::new();
+        sp_instance.config = Some(self.config());
+        sp_instance
+    }
+
+    /// Queues a one-off response for the endpoint at `path` (e.g. `/api/verification/`)
+    pub fn enqueue(&self, path: &str, response: MockResponse) {
+        let mut state = self.state.lock().unwrap();
+        state.queued.entry(path.to_string()).or_default().push_back(response);
+    }
+
+    /// Sets the body returned by verification and payment status for `order_id`
+    pub fn set_verify_body(&self, order_id: &str, body: &str) {
+        self.state.lock().unwrap().verify.insert(order_id.to_string(), body.to_string());
+    }
+
+    /// Marks `sp_order_id` as verified with `sp_code`
+    pub fn set_verified(&self, sp_order_id: &str, customer_order_id: &str, amount: f64, currency: &str, sp_code: i64) {
+        let message = if sp_code == 1000 { "Success" } else { "Failed" };
+        let body = format!(
+            r#"[{{"id":1,"order_id":"{sp}","currency":"{cur}","amount":{amt},"payable_amount":{amt},"discsount_amount":null,"disc_percent":0,"received_amount":"{amt:.2}","usd_amt":0,"usd_rate":0,"card_holder_name":null,"card_number":"4111XXXXXXXX1111","phone_no":"01811177722","bank_trx_id":"BTX{sp}","invoice_no":"INV{sp}","bank_status":"{msg}","customer_order_id":"{cust}","sp_code":{code},"sp_message":"{msg}","name":"Mahmudul Islam","email":null,"address":"Dhaka","city":"Dhaka","value1":null,"value2":null,"value3":null,"value4":null,"transaction_status":null,"method":"Visa","date_time":"2022-11-30 15:07:08"}}]"#,
+            sp = sp_order_id,
+            cur = currency,
+            amt = amount,
+            cust = customer_order_id,
+            code = sp_code,
+            msg = message,
+        );
+        self.set_verify_body(sp_order_id, &body);
+    }
+
+    /// Requests received so far
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// Number of requests received on `path`
+    pub fn hits(&self, path: &str) -> usize {
+        self.requests().iter().filter(|req| req.path == path).count()
+    }
+
+    fn handle(&self, stream: TcpStream) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut line = String::new();
+        if reader.read_line(&mut line).is_err() || line.is_empty() {
+            return;
+        }
+        let mut parts = line.split_whitespace();
+        let method = parts.next().unwrap_or_default().to_string();
+        let path = parts.next().unwrap_or_default().to_string();
+
+        let mut headers = HashMap::new();
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header).is_err() || header.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+            }
+        }
+        let length = headers
+            .get("content-length")
+            .and_then(|len| len.parse::<usize>().ok())
+            .unwrap_or(0);
+        let mut body = vec![0; length];
+        let _ = reader.read_exact(&mut body);
+
+        let request = MockRequest {
+            method,
+            path,
+            headers,
+            body: String::from_utf8_lossy(&body).to_string(),
+        };
+        let response = self.respond(&request);
+        self.state.lock().unwrap().requests.push(request);
+
+        if response.delay_ms > 0 {
+            thread::sleep(std::time::Duration::from_millis(response.delay_ms));
+        }
+        let mut out = format!("HTTP/1.1 {} Mock\r\n", response.status);
+        for (name, value) in &response.headers {
+            out.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        out.push_str(&format!("Content-Length: {}\r\nConnection: close\r\n\r\n", response.body.len()));
+        out.push_str(&response.body);
+        let mut stream = stream;
+        let _ = stream.write_all(out.as_bytes());
+    }
+
+    fn respond(&self, request: &MockRequest) -> MockResponse {
+        let mut state = self.state.lock().unwrap();
+        if let Some(queued) = state.queued.get_mut(&request.path).and_then(|queue| queue.pop_front()) {
+            return queued;
+        }
+        let json: serde_json::Value = serde_json::from_str(&request.body).unwrap_or_default();
+        match request.path.as_str() {
+            "/api/get_token/" => {
+                let create_time = (Utc::now() + Duration::hours(6)).format("%Y-%m-%d %I:%M:%S%P");
+                MockResponse::json(
+                    200,
+                    &format!(
+                        r#"{{"token":"mock-token","store_id":1,"execute_url":"{}/api/secret-pay","token_type":"Bearer","sp_code":"200","message":"Ok","token_create_time":"{}","expires_in":3600}}"#,
+                        self.url, create_time
+                    ),
+                )
+            }
+            "/api/secret-pay/" => {
+                state.checkouts += 1;
+                let sp_order_id = format!("sp-mock-{}", state.checkouts);
+                let field = |name: &str| json[name].as_str().unwrap_or_default().to_string();
+                MockResponse::json(
+                    200,
+                    &serde_json::json!({
+                        "checkout_url": format!("{}/spaycheckout/?token=mock&order_id={}", self.url, sp_order_id),
+                        "amount": field("amount"),
+                        "currency": field("currency"),
+                        "sp_order_id": sp_order_id,
+                        "customer_order_id": field("order_id"),
+                        "customer_name": field("customer_name"),
+                        "customer_address": field("customer_address"),
+                        "customer_city": field("customer_city"),
+                        "customer_phone": field("customer_phone"),
+                        "customer_email": null,
+                        "client_ip": field("client_ip"),
+                        "intent": "sale",
+                        "transactionStatus": "Initiated"
+                    })
+                    .to_string(),
+                )
+            }
+            "/api/verification/" | "/api/payment-status/" => {
+                let order_id = json["order_id"].as_str().unwrap_or_default();
+                match state.verify.get(order_id) {
+                    Some(body) => MockResponse::json(200, body),
+                    None => MockResponse::json(200, r#"{"sp_code":"1011","message":"Please check your order id"}"#),
+                }
+            }
+            _ => MockResponse {
+                status: 404,
+                headers: vec![("Content-Type".to_string(), "text/html".to_string())],
+                body: "<html><body>Not Found</body></html>".to_string(),
+                delay_ms: 0,
+            },
+        }
+    }
+}
+
+/// A checkout request for the mock gateway
+pub fn checkout_request(sp_instance: &mut ShurjopayPlugin, amount: &str, order_id: &str) -> shurjopay_plugin::shurjopay::SpCheckout {
+    sp_instance.make_payment_request_object(
+        amount.to_string(),
+        order_id.to_string(),
+        "BDT".to_string(),
+        "Mahmudul Islam".to_string(),
+        "Dhaka".to_string(),
+        "01811177722".to_string(),
+        "Dhaka".to_string(),
+        "1203".to_string(),
+    )
+}
@@ -0,0 +1,111 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use chrono::NaiveDate;
+
+    use shurjopay_plugin::cart::{Cart, LineItem};
+    use shurjopay_plugin::discount::{self, Coupon, Discount};
+    use shurjopay_plugin::error::SpError;
+    use shurjopay_plugin::money::Money;
+    use shurjopay_plugin::reconciliation::{AmountField, Mismatch};
+
+    use crate::common::{checkout_request, MockGateway};
+
+    fn bdt(amount: &str) -> Money {
+        Money::parse(amount, "BDT").unwrap()
+    }
+
+    #[test]
+    fn apply_discount_test() {
+        let fixed = Discount::Fixed(bdt("100")).apply(&bdt("786")).unwrap();
+        assert_eq!((fixed.discount, fixed.payable, fixed.basis_points), (bdt("100"), bdt("686"), None));
+
+        let percent = Discount::Percentage { basis_points: 750 }.apply(&bdt("786.50")).unwrap();
+        assert_eq!((percent.discount, percent.payable), (bdt("58.99"), bdt("727.51")));
+        assert_eq!(percent.basis_points, Some(750));
+
+        let uncapped = Discount::percent_capped(10, bdt("100")).apply(&bdt("786")).unwrap();
+        assert_eq!((uncapped.discount, uncapped.basis_points), (bdt("78.60"), Some(1000)));
+        let capped = Discount::percent_capped(10, bdt("100")).apply(&bdt("1500")).unwrap();
+        assert_eq!((capped.discount, capped.payable, capped.basis_points), (bdt("100"), bdt("1400"), None));
+
+        let all = Discount::Fixed(bdt("900")).apply(&bdt("786")).unwrap();
+        assert_eq!((all.discount, all.payable), (bdt("786"), bdt("0")));
+
+        assert!(matches!(Discount::percent(101).apply(&bdt("786")), Err(SpError::InvalidAmount(_))));
+        assert!(matches!(
+            Discount::Fixed(Money::parse("1", "USD").unwrap()).apply(&bdt("786")),
+            Err(SpError::CurrencyMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn coupon_test() {
+        let now = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap().and_hms_opt(12, 0, 0).unwrap();
+        let mut coupon = Coupon::new("EID24", Discount::percent(20));
+        coupon.minimum = Some(bdt("500"));
+        coupon.valid_until = Some(now);
+
+        assert!(coupon.matches(" eid24 "));
+        assert!(!coupon.matches("EID23"));
+        assert_eq!(coupon.apply(&bdt("500"), now).unwrap().payable, bdt("400"));
+        assert!(matches!(coupon.apply(&bdt("499.99"), now), Err(SpError::InvalidAmount(_))));
+        let later = now + chrono::Duration::seconds(1);
+        assert!(matches!(coupon.apply(&bdt("500"), later), Err(SpError::InvalidAmount(_))));
+
+        let mut cart = Cart::new("BDT");
+        cart.add_item(LineItem::new("TEA", "Tea", 4, bdt("150"))).unwrap();
+        cart.apply_discount("EID24", &coupon.discount).unwrap();
+        assert_eq!(cart.discounts()[0].amount, bdt("120"));
+        assert_eq!(cart.totals().unwrap().total, bdt("480"));
+    }
+
+    #[test]
+    fn discounted_checkout_test() {
+        let gateway = MockGateway::start();
+        let mut sp_instance = gateway.plugin();
+
+        let applied = Discount::Percentage { basis_points: 750 }.apply(&bdt("1000")).unwrap();
+        let checkout = applied.to_checkout(checkout_request(&mut sp_instance, "0", "INV-DISC"));
+        assert_eq!((checkout.discount_amount.as_deref(), checkout.disc_percent.as_deref()), (Some("75.00"), Some("7.5")));
+        let response = sp_instance.make_payment_checkout(checkout).unwrap();
+        let body: serde_json::Value = serde_json::from_str(&gateway.requests().last().unwrap().body).unwrap();
+        assert_eq!((body["amount"].as_str(), body["discount_amount"].as_str()), (Some("1000.00"), Some("75.00")));
+
+        let plain = checkout_request(&mut sp_instance, "1000", "INV-PLAIN");
+        let plain = sp_instance.make_payment_checkout(plain).unwrap();
+        let body: serde_json::Value = serde_json::from_str(&gateway.requests().last().unwrap().body).unwrap();
+        assert!(body.get("discount_amount").is_none() && body.get("disc_percent").is_none());
+
+        let verify_body = |payable: f64, discount: f64, percent: f64| {
+            format!(
+                r#"[{{"sp_code":1000,"order_id":"{}","customer_order_id":"INV-DISC","currency":"BDT","amount":1000,"payable_amount":{},"discsount_amount":{},"disc_percent":{}}}]"#,
+                response.sp_order_id, payable, discount, percent
+            )
+        };
+        gateway.set_verify_body(&response.sp_order_id, &verify_body(925.0, 75.0, 7.5));
+        let verified = sp_instance.verify_payment(Some(response.sp_order_id.clone())).unwrap();
+        assert!(discount::compare(&applied, &verified).is_empty());
+
+        gateway.set_verify_body(&response.sp_order_id, &verify_body(950.0, 50.0, 5.0));
+        let verified = sp_instance.verify_payment(Some(response.sp_order_id.clone())).unwrap();
+        assert_eq!(
+            discount::compare(&applied, &verified),
+            vec![
+                Mismatch::Amount { field: AmountField::PayableAmount, expected: bdt("925"), actual: bdt("950") },
+                Mismatch::Amount { field: AmountField::DiscountAmount, expected: bdt("75"), actual: bdt("50") },
+                Mismatch::DiscountPercent { expected: "7.5".to_string(), actual: Some(5.0) },
+            ]
+        );
+
+        let fixed = Discount::Fixed(bdt("100")).apply(&bdt("1000")).unwrap();
+        gateway.set_verified(&plain.sp_order_id, "INV-PLAIN", 900.0, "BDT", 1000);
+        let verified = sp_instance.verify_payment(Some(plain.sp_order_id)).unwrap();
+        assert_eq!(
+            discount::compare(&fixed, &verified),
+            vec![Mismatch::Amount { field: AmountField::DiscountAmount, expected: bdt("100"), actual: bdt("0") }]
+        );
+    }
+}
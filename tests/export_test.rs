@@ -0,0 +1,138 @@
+#[cfg(test)]
+mod tests {
+
+    use shurjopay_plugin::export::{mask_card_number, ExportColumn, SettlementSummary, VerifyExport};
+    use shurjopay_plugin::money::Money;
+    use shurjopay_plugin::shurjopay::SpVerifyResponse;
+
+    fn response(order_id: &str, sp_code: i64, method: &str, currency: &str, amount: f64, received: &str) -> SpVerifyResponse {
+        let mut response = SpVerifyResponse::new();
+        response.order_id = Some(order_id.to_string());
+        response.customer_order_id = Some(format!("cust-{}", order_id));
+        response.sp_code = Some(sp_code);
+        response.sp_message = Some("Success".to_string());
+        response.method = Some(method.to_string());
+        response.currency = Some(currency.to_string());
+        response.amount = Some(amount);
+        response.payable_amount = Some(amount);
+        response.received_amount = Some(received.to_string());
+        response.card_holder_name = Some("Islam, Mahmudul".to_string());
+        response.card_number = Some("4111-1111-1111-1234".to_string());
+        response
+    }
+
+    #[test]
+    fn mask_card_number_test() {
+        assert_eq!(mask_card_number("4111111111111234"), "************1234");
+        assert_eq!(mask_card_number("4111-1111-1111-1234"), "****-****-****-1234");
+        assert_eq!(mask_card_number("4111XXXXXXXX1111"), "****XXXXXXXX1111");
+        assert_eq!(mask_card_number("123"), "123");
+    }
+
+    #[test]
+    fn write_csv_test() {
+        let responses = vec![response("sp1", 1000, "bKash", "BDT", 786.5, "786.5000")];
+        let export = VerifyExport {
+            columns: vec![
+                ExportColumn::OrderId,
+                ExportColumn::PayableAmount,
+                ExportColumn::CardHolderName,
+                ExportColumn::CardNumber,
+                ExportColumn::Email,
+            ],
+            ..Default::default()
+        };
+
+        let mut out = Vec::new();
+        export.write_csv(&mut out, &responses).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "order_id,payable_amount,card_holder_name,card_number,email\r\n\
+             sp1,786.5,\"Islam, Mahmudul\",****-****-****-1234,\r\n"
+        );
+        assert_eq!(ExportColumn::from_name("discsount_amount"), Some(ExportColumn::DiscountAmount));
+    }
+
+    #[test]
+    fn csv_formula_injection_test() {
+        let mut hostile = response("sp2", 1000, "@SUM(A1:A9)", "BDT", -5.0, "-5.00");
+        hostile.card_holder_name = Some("=HYPERLINK(\"http://x\",\"pay\")".to_string());
+        hostile.sp_message = Some("+cmd|' /C calc'!A0".to_string());
+        hostile.address = Some("-2+3".to_string());
+        let export = VerifyExport {
+            columns: vec![
+                ExportColumn::Method,
+                ExportColumn::CardHolderName,
+                ExportColumn::SpMessage,
+                ExportColumn::Address,
+                ExportColumn::PayableAmount,
+                ExportColumn::ReceivedAmount,
+            ],
+            ..Default::default()
+        };
+
+        let mut out = Vec::new();
+        export.write_csv(&mut out, &[hostile]).unwrap();
+        let row = String::from_utf8(out).unwrap().lines().nth(1).unwrap().to_string();
+        assert_eq!(
+            row,
+            "'@SUM(A1:A9),\"'=HYPERLINK(\"\"http://x\"\",\"\"pay\"\")\",'+cmd|' /C calc'!A0,'-2+3,-5,-5.00"
+        );
+    }
+
+    #[test]
+    fn write_ndjson_test() {
+        let responses = vec![response("sp1", 1000, "bKash", "BDT", 10.0, "10"), response("sp2", 1002, "Visa", "BDT", 20.0, "0")];
+
+        let mut out = Vec::new();
+        VerifyExport::default().write_ndjson(&mut out, &responses).unwrap();
+        let lines: Vec<serde_json::Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["order_id"], "sp2");
+        assert_eq!(lines[0]["card_number"], "****-****-****-1234");
+    }
+
+    #[test]
+    fn settlement_summary_test() {
+        let responses = vec![
+            response("sp1", 1000, "bKash", "BDT", 100.0, "100.00"),
+            response("sp2", 1000, "bKash", "BDT", 50.25, "50.25"),
+            response("sp3", 1000, "Visa", "USD", 10.0, "10"),
+            response("sp4", 1002, "Visa", "BDT", 70.0, "0"),
+        ];
+
+        let summary = SettlementSummary::from_responses(&responses).unwrap();
+        assert_eq!(summary.count, 4);
+        let paid = &summary.by_status["paid"];
+        assert_eq!(paid.count, 3);
+        assert_eq!(paid.totals["BDT"], Money::parse("150.25", "BDT").unwrap());
+        assert_eq!(paid.totals["USD"], Money::parse("10", "USD").unwrap());
+        assert_eq!(summary.by_status["cancelled"].totals["BDT"], Money::zero("BDT"));
+        assert_eq!(summary.by_method["Visa"].count, 2);
+        assert_eq!(summary.by_currency["BDT"].count, 3);
+        assert_eq!(summary.by_currency["BDT"].totals["BDT"], Money::parse("150.25", "BDT").unwrap());
+    }
+
+    #[test]
+    fn settlement_summary_totals_collected_money_test() {
+        let mut unreadable = response("sp3", 1000, "Visa", "BDT", 30.0, "30");
+        unreadable.received_amount = Some("thirty".to_string());
+        let responses = vec![
+            response("sp1", 1000, "Visa", "BDT", 100.0, "100"),
+            response("sp2", 1001, "Visa", "BDT", 70.0, ""),
+            unreadable,
+        ];
+
+        let summary = SettlementSummary::from_responses(&responses).unwrap();
+        assert_eq!((summary.count, summary.invalid), (3, 1));
+        assert_eq!(summary.by_status["declined"].totals["BDT"], Money::parse("70", "BDT").unwrap());
+        assert_eq!(summary.by_status["paid"].count, 2);
+        assert_eq!(summary.by_method["Visa"].count, 3);
+        assert_eq!(summary.by_method["Visa"].totals["BDT"], Money::parse("100", "BDT").unwrap());
+        assert_eq!(summary.by_currency["BDT"].totals["BDT"], Money::parse("100", "BDT").unwrap());
+    }
+}
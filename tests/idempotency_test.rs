@@ -0,0 +1,131 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use std::sync::Arc;
+
+    use chrono::{Duration, Utc};
+
+    use shurjopay_plugin::error::SpError;
+    use shurjopay_plugin::payment_store::{MemoryPaymentStore, PaymentStore};
+
+    use crate::common::{checkout_request, MockGateway, MockResponse};
+
+    #[test]
+    fn repeat_checkout_returns_live_checkout_test() {
+        let gateway = MockGateway::start();
+        let mut sp_instance = gateway.plugin();
+
+        let payment_req_obj = checkout_request(&mut sp_instance, "786", "idem-001");
+        let first = sp_instance.make_payment_idempotent(payment_req_obj).unwrap();
+        assert!(!first.replayed);
+
+        let payment_req_obj = checkout_request(&mut sp_instance, "786.00", "idem-001");
+        let second = sp_instance.make_payment_idempotent(payment_req_obj).unwrap();
+        assert!(second.replayed);
+        assert_eq!(second.checkout_url, first.checkout_url);
+        assert_eq!(second.sp_order_id, first.sp_order_id);
+        assert_eq!(sp_instance.get_order_id(), Some(first.sp_order_id));
+        assert_eq!(gateway.hits("/api/secret-pay/"), 1);
+    }
+
+    #[test]
+    fn clones_share_checkouts_test() {
+        let gateway = MockGateway::start();
+        let mut sp_instance = gateway.plugin();
+        let mut copy = sp_instance.clone();
+
+        let payment_req_obj = checkout_request(&mut sp_instance, "786", "idem-006");
+        let first = sp_instance.make_payment_idempotent(payment_req_obj.clone()).unwrap();
+        let second = copy.make_payment_idempotent(payment_req_obj.clone()).unwrap();
+        assert!(second.replayed);
+        assert_eq!(second.sp_order_id, first.sp_order_id);
+        assert!(copy.payment_store().unwrap().find_by_order_id("idem-006").unwrap().is_some());
+        assert_eq!(gateway.hits("/api/secret-pay/"), 1);
+
+        sp_instance.set_payment_store(Arc::new(MemoryPaymentStore::new()));
+        assert!(!sp_instance.make_payment_idempotent(payment_req_obj).unwrap().replayed);
+        assert_eq!(gateway.hits("/api/secret-pay/"), 2);
+    }
+
+    #[test]
+    fn repeat_checkout_with_other_amount_is_conflict_test() {
+        let gateway = MockGateway::start();
+        let mut sp_instance = gateway.plugin();
+
+        let payment_req_obj = checkout_request(&mut sp_instance, "786", "idem-002");
+        sp_instance.make_payment_idempotent(payment_req_obj).unwrap();
+
+        let payment_req_obj = checkout_request(&mut sp_instance, "800", "idem-002");
+        let err = sp_instance.make_payment_idempotent(payment_req_obj).unwrap_err();
+        assert!(matches!(err, SpError::IdempotencyConflict { .. }));
+
+        let mut payment_req_obj = checkout_request(&mut sp_instance, "786", "idem-002");
+        payment_req_obj.currency = "USD".to_string();
+        assert!(sp_instance.make_payment_idempotent(payment_req_obj).is_err());
+
+        let mut payment_req_obj = checkout_request(&mut sp_instance, "786", "idem-002");
+        payment_req_obj.discount_amount = Some("100".to_string());
+        let err = sp_instance.make_payment_idempotent(payment_req_obj).unwrap_err();
+        assert_eq!(
+            err,
+            SpError::IdempotencyConflict {
+                order_id: "idem-002".to_string(),
+                expected: "786 BDT".to_string(),
+                actual: "786 BDT less 100 discount".to_string(),
+            }
+        );
+        assert_eq!(gateway.hits("/api/secret-pay/"), 1);
+    }
+
+    #[test]
+    fn failed_checkout_is_retried_test() {
+        let gateway = MockGateway::start();
+        gateway.enqueue("/api/secret-pay/", MockResponse::json(500, r#"{"message":"Server Error"}"#));
+        let mut sp_instance = gateway.plugin();
+
+        let payment_req_obj = checkout_request(&mut sp_instance, "786", "idem-003");
+        let err = sp_instance.make_payment_idempotent(payment_req_obj).unwrap_err();
+        assert!(matches!(err, SpError::Gateway { http_code: 500, .. }));
+
+        let payment_req_obj = checkout_request(&mut sp_instance, "786", "idem-003");
+        assert!(!sp_instance.make_payment_idempotent(payment_req_obj).unwrap().replayed);
+        assert_eq!(gateway.hits("/api/secret-pay/"), 2);
+    }
+
+    #[test]
+    fn paid_order_is_not_checked_out_again_test() {
+        let gateway = MockGateway::start();
+        let mut sp_instance = gateway.plugin();
+
+        let payment_req_obj = checkout_request(&mut sp_instance, "786", "idem-004");
+        let first = sp_instance.make_payment_idempotent(payment_req_obj).unwrap();
+        gateway.set_verified(&first.sp_order_id, "idem-004", 786.0, "BDT", 1000);
+        sp_instance.verify_payment(Some(first.sp_order_id)).unwrap();
+
+        let payment_req_obj = checkout_request(&mut sp_instance, "786", "idem-004");
+        let err = sp_instance.make_payment_idempotent(payment_req_obj).unwrap_err();
+        assert_eq!(err, SpError::AlreadyPaid("idem-004".to_string()));
+        assert_eq!(gateway.hits("/api/secret-pay/"), 1);
+    }
+
+    #[test]
+    fn checkout_in_flight_is_refused_test() {
+        let gateway = MockGateway::start();
+        let mut sp_instance = gateway.plugin();
+        let store = Arc::new(MemoryPaymentStore::new());
+        sp_instance.set_payment_store(store.clone());
+
+        let payment_req_obj = checkout_request(&mut sp_instance, "786", "idem-005");
+        let mut record = store.record_checkout_request(&payment_req_obj).unwrap();
+        let err = sp_instance.make_payment_idempotent(payment_req_obj.clone()).unwrap_err();
+        assert_eq!(err, SpError::CheckoutInFlight("idem-005".to_string()));
+        assert_eq!(gateway.hits("/api/secret-pay/"), 0);
+
+        record.updated_at = Utc::now().naive_utc() - Duration::minutes(5);
+        store.save_record(&record).unwrap();
+        assert!(!sp_instance.make_payment_idempotent(payment_req_obj).unwrap().replayed);
+        assert_eq!(gateway.hits("/api/secret-pay/"), 1);
+    }
+}
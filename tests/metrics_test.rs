@@ -0,0 +1,107 @@
+#![cfg(feature = "metrics")]
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+    use metrics_util::{CompositeKey, MetricKind};
+
+    use shurjopay_plugin::metrics::{
+        IDEMPOTENT_REPLAYS_TOTAL, REQUESTS_TOTAL, REQUEST_DURATION_SECONDS, RETRIES_TOTAL, TOKEN_CACHE_HITS_TOTAL,
+        TOKEN_REFRESHES_TOTAL,
+    };
+
+    use crate::common::{checkout_request, MockGateway, MockResponse};
+
+    type Metrics = Vec<(CompositeKey, DebugValue)>;
+
+    /// Value of a counter with exactly `labels`
+    fn counter(metrics: &Metrics, name: &str, labels: &[(&str, &str)]) -> u64 {
+        metrics
+            .iter()
+            .find_map(|(key, value)| {
+                let key_labels: Vec<(&str, &str)> = key.key().labels().map(|label| (label.key(), label.value())).collect();
+                match value {
+                    DebugValue::Counter(count)
+                        if key.kind() == MetricKind::Counter && key.key().name() == name && key_labels == labels =>
+                    {
+                        Some(*count)
+                    }
+                    _ => None,
+                }
+            })
+            .unwrap_or(0)
+    }
+
+    /// Number of values recorded by a histogram over all labels
+    fn histogram_len(metrics: &Metrics, name: &str) -> usize {
+        metrics
+            .iter()
+            .filter_map(|(key, value)| match value {
+                DebugValue::Histogram(values) if key.key().name() == name => Some(values.len()),
+                _ => None,
+            })
+            .sum()
+    }
+
+    #[test]
+    fn gateway_operations_record_metrics_test() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        let gateway = MockGateway::start();
+
+        metrics::with_local_recorder(&recorder, || {
+            let mut sp_instance = gateway.plugin();
+            let checkout = checkout_request(&mut sp_instance, "1000", "INV-1");
+            sp_instance.make_payment_no_auto_redirect(checkout).unwrap();
+            gateway.set_verified("sp-mock-1", "INV-1", 1000.0, "BDT", 1000);
+            sp_instance.check_payment(Some("sp-mock-1".to_string())).unwrap();
+            gateway.enqueue("/api/verification/", MockResponse::json(500, "{}"));
+            sp_instance.verify_payment_id("sp-mock-1".to_string());
+        });
+        let metrics: Metrics = snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, _, _, value)| (key, value))
+            .collect();
+
+        let token = [("operation", "get_auth_token"), ("outcome", "success"), ("sp_code", "200")];
+        assert_eq!(counter(&metrics, REQUESTS_TOTAL, &token), 1);
+        let checkout = [("operation", "secure_ckeckout"), ("outcome", "success"), ("sp_code", "none")];
+        assert_eq!(counter(&metrics, REQUESTS_TOTAL, &checkout), 1);
+        let status = [("operation", "check_payment"), ("outcome", "success"), ("sp_code", "1000")];
+        assert_eq!(counter(&metrics, REQUESTS_TOTAL, &status), 1);
+        assert_eq!(histogram_len(&metrics, REQUEST_DURATION_SECONDS), 4);
+
+        assert_eq!(counter(&metrics, TOKEN_REFRESHES_TOTAL, &[("reason", "missing")]), 1);
+        assert_eq!(counter(&metrics, TOKEN_CACHE_HITS_TOTAL, &[]), 1);
+    }
+
+    #[test]
+    fn idempotent_replays_are_not_retries_test() {
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        let gateway = MockGateway::start();
+
+        metrics::with_local_recorder(&recorder, || {
+            let mut sp_instance = gateway.plugin();
+            for _ in 0..3 {
+                let checkout = checkout_request(&mut sp_instance, "1000", "INV-REPLAY");
+                sp_instance.make_payment_idempotent(checkout).unwrap();
+            }
+        });
+        let metrics: Metrics = snapshotter
+            .snapshot()
+            .into_vec()
+            .into_iter()
+            .map(|(key, _, _, value)| (key, value))
+            .collect();
+
+        let operation = [("operation", "secure_ckeckout")];
+        assert_eq!(counter(&metrics, IDEMPOTENT_REPLAYS_TOTAL, &operation), 2);
+        assert_eq!(counter(&metrics, RETRIES_TOTAL, &operation), 0);
+    }
+}
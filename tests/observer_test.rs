@@ -0,0 +1,59 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use std::sync::{Arc, Mutex};
+
+    use shurjopay_plugin::error::SpError;
+    use shurjopay_plugin::observer::{ChannelObserver, PaymentEvent, PaymentObserver};
+    use shurjopay_plugin::shurjopay::{SpAuthToken, SpCheckoutResponse, SpVerifyResponse};
+
+    use crate::common::{checkout_request, MockGateway, MockResponse};
+
+    #[derive(Debug, Default)]
+    struct Calls(Mutex<Vec<String>>);
+
+    impl PaymentObserver for Calls {
+        fn on_token_refreshed(&self, token: &SpAuthToken) {
+            self.0.lock().unwrap().push(format!("token {}", token.store_id));
+        }
+
+        fn on_checkout_created(&self, response: &SpCheckoutResponse) {
+            self.0.lock().unwrap().push(format!("checkout {}", response.sp_order_id));
+        }
+
+        fn on_verified(&self, response: &SpVerifyResponse) {
+            self.0.lock().unwrap().push(format!("verified {:?}", response.sp_code));
+        }
+    }
+
+    #[test]
+    fn observers_are_notified_test() {
+        let gateway = MockGateway::start();
+        let mut sp_instance = gateway.plugin();
+        let calls = Arc::new(Calls::default());
+        let (channel, events) = ChannelObserver::new();
+        sp_instance.add_observer(calls.clone());
+        sp_instance.add_observer(Arc::new(channel));
+
+        let checkout = checkout_request(&mut sp_instance, "1000", "INV-1");
+        sp_instance.make_payment_no_auto_redirect(checkout).unwrap();
+        gateway.set_verified("sp-mock-1", "INV-1", 1000.0, "BDT", 1000);
+        sp_instance.verify_payment_id("sp-mock-1".to_string()).unwrap();
+        gateway.enqueue("/api/secret-pay/", MockResponse::json(500, "{}"));
+        let checkout = checkout_request(&mut sp_instance, "1000", "INV-2");
+        assert!(sp_instance.make_payment_no_auto_redirect(checkout).is_none());
+
+        assert_eq!(
+            *calls.0.lock().unwrap(),
+            vec!["token 1", "checkout sp-mock-1", "verified Some(1000)"]
+        );
+        let events: Vec<PaymentEvent> = events.try_iter().collect();
+        assert_eq!(events.len(), 4);
+        assert!(matches!(events[0], PaymentEvent::TokenRefreshed(_)));
+        assert!(matches!(events[1], PaymentEvent::CheckoutCreated(_)));
+        assert!(matches!(&events[2], PaymentEvent::Verified(response) if response.sp_code == Some(1000)));
+        assert!(matches!(events[3], PaymentEvent::Error(SpError::Gateway { http_code: 500, .. })));
+    }
+}
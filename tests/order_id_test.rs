@@ -0,0 +1,65 @@
+#[cfg(test)]
+mod tests {
+
+    use chrono::Utc;
+    use shurjopay_plugin::error::SpError;
+    use shurjopay_plugin::order_id::{parse_order_id, validate_order_id, OrderIdGenerator, MAX_ORDER_ID_LEN};
+    use shurjopay_plugin::shurjopay::{ShurjopayPlugin, SpConfig};
+
+    #[test]
+    fn generate_and_parse_order_id_test() {
+        let generator = OrderIdGenerator::new("shop1").unwrap();
+        let before = Utc::now().timestamp_millis();
+        let ids: Vec<String> = (0..100).map(|_| generator.generate()).collect();
+
+        for pair in ids.windows(2) {
+            assert!(pair[0] < pair[1]);
+        }
+        let parsed = parse_order_id(&ids[0]).unwrap();
+        assert_eq!(parsed.prefix, "shop1");
+        assert!(parsed.created_at.timestamp_millis() >= before);
+        assert!(ids[0].len() <= MAX_ORDER_ID_LEN);
+        assert!(validate_order_id(&ids[0]).is_ok());
+    }
+
+    #[test]
+    fn invalid_prefix_and_order_id_test() {
+        assert!(matches!(OrderIdGenerator::new("toolong"), Err(SpError::InvalidOrderId(_))));
+        assert!(OrderIdGenerator::new("").is_err());
+        assert!(OrderIdGenerator::new("s-p").is_err());
+        assert!(validate_order_id("abc 123").is_err());
+        assert!(validate_order_id(&"a".repeat(MAX_ORDER_ID_LEN + 1)).is_err());
+        assert!(parse_order_id("abc123").is_err());
+    }
+
+    #[test]
+    fn plugin_uses_configured_prefix_test() {
+        let mut sp_instance = ShurjopayPlugin::new();
+        sp_instance.config = Some(SpConfig {
+            prefix: "nxs".to_string(),
+            ..Default::default()
+        });
+
+        let order_id = sp_instance.generate_order_id().unwrap();
+        assert!(order_id.starts_with("nxs"));
+        let payment_req_obj = sp_instance.make_payment_request_object(
+            "786".to_string(),
+            order_id,
+            "BDT".to_string(),
+            "Mahmudul Islam".to_string(),
+            "Dhaka".to_string(),
+            "01811177722".to_string(),
+            "Dhaka".to_string(),
+            "1203".to_string(),
+        );
+        assert_eq!(payment_req_obj.prefix, "nxs");
+    }
+
+    #[test]
+    fn config_without_prefix_test() {
+        let mut config = serde_json::to_value(SpConfig::default()).unwrap();
+        config.as_object_mut().unwrap().remove("prefix");
+        let config: SpConfig = serde_json::from_value(config).unwrap();
+        assert_eq!(config.prefix, "sp");
+    }
+}
@@ -0,0 +1,205 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use std::sync::Arc;
+
+    use chrono::{Duration, Utc};
+
+    use shurjopay_plugin::error::SpError;
+    use shurjopay_plugin::payment_link::{LinkState, MemoryPaymentLinkStore, PaymentLinks};
+    use shurjopay_plugin::payment_store::MemoryPaymentStore;
+    use shurjopay_plugin::web::CheckoutRequest;
+
+    use crate::common::MockGateway;
+
+    fn link_request(order_id: Option<&str>) -> CheckoutRequest {
+        CheckoutRequest {
+            amount: "1500".to_string(),
+            order_id: order_id.map(str::to_string),
+            currency: None,
+            customer_name: "Mahmudul Islam".to_string(),
+            customer_address: "Dhaka".to_string(),
+            customer_phone: "01811177722".to_string(),
+            customer_city: "Dhaka".to_string(),
+            customer_post_code: "1203".to_string(),
+            client_ip: None,
+        }
+    }
+
+    fn links() -> PaymentLinks {
+        PaymentLinks::new(Arc::new(MemoryPaymentLinkStore::new()))
+    }
+
+    #[test]
+    fn resolve_link_test() {
+        let gateway = MockGateway::start();
+        let mut sp_instance = gateway.plugin();
+        let links = links();
+
+        let expires_at = Utc::now().naive_utc() + Duration::hours(1);
+        let link = links.create(&sp_instance, link_request(None), expires_at).unwrap();
+        assert_eq!(link.state, LinkState::Open);
+        assert_eq!(link.request.order_id.as_deref(), Some(link.order_id.as_str()));
+
+        let first = links.resolve(&mut sp_instance, &link.id).unwrap();
+        let second = links.resolve(&mut sp_instance, &link.id).unwrap();
+        assert_eq!(first.customer_order_id, link.order_id);
+        assert_ne!(first.sp_order_id, second.sp_order_id);
+        assert_eq!(gateway.hits("/api/secret-pay/"), 2);
+        assert_eq!(links.get(&link.id).unwrap().sp_order_id, Some(second.sp_order_id));
+    }
+
+    #[test]
+    fn refuse_expired_and_unknown_link_test() {
+        let gateway = MockGateway::start();
+        let mut sp_instance = gateway.plugin();
+        let links = links();
+
+        let expires_at = Utc::now().naive_utc() - Duration::minutes(1);
+        let link = links.create(&sp_instance, link_request(Some("INV-EXP")), expires_at).unwrap();
+        assert!(link.is_expired());
+        assert_eq!(links.resolve(&mut sp_instance, &link.id), Err(SpError::LinkExpired(link.id.clone())));
+        assert_eq!(links.resolve(&mut sp_instance, "missing"), Err(SpError::LinkNotFound("missing".to_string())));
+        assert_eq!(gateway.hits("/api/secret-pay/"), 0);
+    }
+
+    #[test]
+    fn consume_link_after_verification_test() {
+        let gateway = MockGateway::start();
+        let mut sp_instance = gateway.plugin();
+        let links = links();
+        sp_instance.add_observer(Arc::new(links.clone()));
+
+        let expires_at = Utc::now().naive_utc() + Duration::hours(1);
+        let link = links.create(&sp_instance, link_request(Some("INV-LINK")), expires_at).unwrap();
+        let response = links.resolve(&mut sp_instance, &link.id).unwrap();
+
+        gateway.set_verified(&response.sp_order_id, "INV-LINK", 1500.0, "BDT", 1001);
+        sp_instance.verify_payment(Some(response.sp_order_id.clone())).unwrap();
+        assert_eq!(links.get(&link.id).unwrap().state, LinkState::Open);
+
+        gateway.set_verified(&response.sp_order_id, "INV-LINK", 1500.0, "BDT", 1000);
+        sp_instance.verify_payment(Some(response.sp_order_id)).unwrap();
+        let consumed = links.get(&link.id).unwrap();
+        assert_eq!(consumed.state, LinkState::Consumed);
+        assert!(consumed.consumed_at.is_some());
+
+        assert_eq!(links.resolve(&mut sp_instance, &link.id), Err(SpError::LinkConsumed(link.id.clone())));
+        assert_eq!(gateway.hits("/api/secret-pay/"), 1);
+    }
+
+    #[test]
+    fn refuse_link_paid_in_ledger_test() {
+        let gateway = MockGateway::start();
+        let mut sp_instance = gateway.plugin();
+        sp_instance.set_payment_store(Arc::new(MemoryPaymentStore::new()));
+        let links = links();
+
+        let expires_at = Utc::now().naive_utc() + Duration::hours(1);
+        let link = links.create(&sp_instance, link_request(Some("INV-PAID")), expires_at).unwrap();
+        let response = links.resolve(&mut sp_instance, &link.id).unwrap();
+        gateway.set_verified(&response.sp_order_id, "INV-PAID", 1500.0, "BDT", 1000);
+        sp_instance.verify_payment(Some(response.sp_order_id)).unwrap();
+
+        assert_eq!(links.resolve(&mut sp_instance, &link.id), Err(SpError::LinkConsumed(link.id.clone())));
+        assert_eq!(links.get(&link.id).unwrap().state, LinkState::Consumed);
+    }
+
+    #[test]
+    fn refuse_duplicate_order_id_test() {
+        let gateway = MockGateway::start();
+        let sp_instance = gateway.plugin();
+        let links = links();
+
+        let expires_at = Utc::now().naive_utc() + Duration::hours(1);
+        let link = links.create(&sp_instance, link_request(Some("INV-DUP")), expires_at).unwrap();
+        assert!(matches!(
+            links.create(&sp_instance, link_request(Some("INV-DUP")), expires_at),
+            Err(SpError::InvalidOrderId(_))
+        ));
+        assert_eq!(links.find_by_order_id("INV-DUP").unwrap().unwrap().id, link.id);
+    }
+
+    #[test]
+    fn refuse_concurrent_duplicate_order_id_test() {
+        let gateway = MockGateway::start();
+        let sp_instance = Arc::new(gateway.plugin());
+        let links = links();
+
+        let expires_at = Utc::now().naive_utc() + Duration::hours(1);
+        let created = (0..8)
+            .map(|_| {
+                let (links, sp_instance) = (links.clone(), sp_instance.clone());
+                std::thread::spawn(move || links.create(&sp_instance, link_request(Some("INV-RACE")), expires_at))
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .filter(Result::is_ok)
+            .count();
+        assert_eq!(created, 1);
+    }
+
+    #[test]
+    fn refuse_unpayable_request_test() {
+        let gateway = MockGateway::start();
+        let sp_instance = gateway.plugin();
+        let links = links();
+        let expires_at = Utc::now().naive_utc() + Duration::hours(1);
+
+        let mut request = link_request(Some("INV-PHONE"));
+        request.customer_phone = "12345".to_string();
+        assert!(matches!(links.create(&sp_instance, request, expires_at), Err(SpError::InvalidPhone(_))));
+        let mut request = link_request(Some("INV-AMOUNT"));
+        request.amount = "15.005".to_string();
+        assert!(matches!(links.create(&sp_instance, request, expires_at), Err(SpError::InvalidAmount(_))));
+        let mut request = link_request(Some("INV-ZERO"));
+        request.amount = "0".to_string();
+        assert!(matches!(links.create(&sp_instance, request, expires_at), Err(SpError::InvalidAmount(_))));
+        assert!(matches!(
+            links.create(&sp_instance, link_request(Some("INV 1/2")), expires_at),
+            Err(SpError::InvalidOrderId(_))
+        ));
+        for order_id in ["INV-PHONE", "INV-AMOUNT", "INV-ZERO", "INV 1/2"] {
+            assert!(links.find_by_order_id(order_id).unwrap().is_none());
+        }
+
+        let mut request = link_request(Some("INV-NATIONAL"));
+        request.customer_phone = "+8801811177722".to_string();
+        assert!(links.create(&sp_instance, request, expires_at).is_ok());
+    }
+
+    #[test]
+    fn keep_underpaid_link_open_test() {
+        let gateway = MockGateway::start();
+        let mut sp_instance = gateway.plugin();
+        let links = links();
+        sp_instance.add_observer(Arc::new(links.clone()));
+
+        let expires_at = Utc::now().naive_utc() + Duration::hours(1);
+        let link = links.create(&sp_instance, link_request(Some("INV-LOW")), expires_at).unwrap();
+        let response = links.resolve(&mut sp_instance, &link.id).unwrap();
+
+        gateway.set_verified(&response.sp_order_id, "INV-LOW", 15.0, "BDT", 1000);
+        let verified = sp_instance.verify_payment(Some(response.sp_order_id.clone())).unwrap();
+        assert!(matches!(links.consume(&verified), Err(SpError::InvalidAmount(_))));
+        gateway.set_verified(&response.sp_order_id, "INV-LOW", 1500.0, "USD", 1000);
+        let verified = sp_instance.verify_payment(Some(response.sp_order_id.clone())).unwrap();
+        assert!(matches!(links.consume(&verified), Err(SpError::CurrencyMismatch { .. })));
+        assert_eq!(links.get(&link.id).unwrap().state, LinkState::Open);
+
+        // Matched on the sp_order_id even when the merchant order id is missing
+        gateway.set_verify_body(
+            &response.sp_order_id,
+            &format!(
+                r#"[{{"sp_code":1000,"order_id":"{}","currency":"BDT","amount":1500,"payable_amount":1500}}]"#,
+                response.sp_order_id
+            ),
+        );
+        let verified = sp_instance.verify_payment(Some(response.sp_order_id)).unwrap();
+        assert_eq!(links.consume(&verified).unwrap().unwrap().id, link.id);
+        assert_eq!(links.get(&link.id).unwrap().state, LinkState::Consumed);
+    }
+}
@@ -0,0 +1,225 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use std::sync::Arc;
+
+    use shurjopay_plugin::error::SpError;
+    use shurjopay_plugin::payment_store::{MemoryPaymentStore, PaymentState, PaymentStore};
+    use shurjopay_plugin::web::{self, PaymentCallbacks};
+
+    use crate::common::{checkout_request, MockGateway, MockResponse};
+
+    #[test]
+    fn checkout_and_verification_are_recorded_test() {
+        let gateway = MockGateway::start();
+        let store = Arc::new(MemoryPaymentStore::new());
+        let mut sp_instance = gateway.plugin();
+        sp_instance.set_payment_store(store.clone());
+
+        let payment_req_obj = checkout_request(&mut sp_instance, "786", "store-001");
+        let checkout_url = sp_instance.make_payment_no_auto_redirect(payment_req_obj).unwrap();
+
+        let record = store.find_by_order_id("store-001").unwrap().unwrap();
+        assert_eq!(record.state, PaymentState::CheckoutCreated);
+        assert_eq!(record.checkout_url, Some(checkout_url));
+        assert_eq!(record.checkout_request.token, "");
+        let sp_order_id = record.sp_order_id.clone().unwrap();
+
+        gateway.set_verified(&sp_order_id, "store-001", 786.0, "BDT", 1000);
+        sp_instance.verify_payment(Some(sp_order_id.clone())).unwrap();
+
+        let record = store.find_by_sp_order_id(&sp_order_id).unwrap().unwrap();
+        assert_eq!(record.order_id, "store-001");
+        assert_eq!(record.state, PaymentState::Paid);
+        assert_eq!(record.verify_response.unwrap().bank_trx_id, Some(format!("BTX{}", sp_order_id)));
+
+        let states: Vec<(Option<PaymentState>, PaymentState)> = store
+            .transitions("store-001")
+            .unwrap()
+            .into_iter()
+            .map(|transition| (transition.from, transition.to))
+            .collect();
+        assert_eq!(
+            states,
+            vec![
+                (None, PaymentState::CheckoutRequested),
+                (Some(PaymentState::CheckoutRequested), PaymentState::CheckoutCreated),
+                (Some(PaymentState::CheckoutCreated), PaymentState::Paid),
+            ]
+        );
+
+        let payment_req_obj = checkout_request(&mut sp_instance, "786", "store-001");
+        assert!(sp_instance.make_payment_checkout(payment_req_obj.clone()).is_none());
+        assert_eq!(sp_instance.last_error, Some(SpError::AlreadyPaid("store-001".to_string())));
+        assert!(matches!(store.record_checkout_request(&payment_req_obj), Err(SpError::AlreadyPaid(_))));
+        assert_eq!(store.find_by_order_id("store-001").unwrap().unwrap().state, PaymentState::Paid);
+        assert_eq!(store.transitions("store-001").unwrap().len(), 3);
+        assert_eq!(gateway.hits("/api/secret-pay/"), 1);
+    }
+
+    #[test]
+    fn success_callback_once_per_payment_test() {
+        let gateway = MockGateway::start();
+        let store = Arc::new(MemoryPaymentStore::new());
+        let mut sp_instance = gateway.plugin();
+        sp_instance.set_payment_store(store.clone());
+        let payment_req_obj = checkout_request(&mut sp_instance, "786", "store-once");
+        let response = sp_instance.make_payment_checkout(payment_req_obj).unwrap();
+        let plugin = web::share(sp_instance);
+
+        let paid = Arc::new(std::sync::Mutex::new(0));
+        let seen = paid.clone();
+        let callbacks = PaymentCallbacks::default().on_success(move |_| *seen.lock().unwrap() += 1);
+        gateway.set_verified(&response.sp_order_id, "store-once", 786.0, "BDT", 1002);
+        assert!(!web::verify_order(&plugin, &response.sp_order_id, &callbacks).paid);
+        gateway.set_verified(&response.sp_order_id, "store-once", 786.0, "BDT", 1000);
+        let first = web::verify_order(&plugin, &response.sp_order_id, &callbacks);
+        let second = web::verify_order(&plugin, &response.sp_order_id, &callbacks);
+        assert_eq!((first.paid, first.newly_paid), (true, true));
+        assert_eq!((second.paid, second.newly_paid), (true, false));
+        assert_eq!(*paid.lock().unwrap(), 1);
+    }
+
+    #[test]
+    fn concurrent_returns_verify_in_parallel_test() {
+        let gateway = MockGateway::start();
+        let store = Arc::new(MemoryPaymentStore::new());
+        let mut sp_instance = gateway.plugin();
+        sp_instance.set_payment_store(store.clone());
+        let payment_req_obj = checkout_request(&mut sp_instance, "786", "store-race");
+        let response = sp_instance.make_payment_checkout(payment_req_obj).unwrap();
+        let plugin = web::share(sp_instance);
+
+        let body = format!(
+            r#"[{{"order_id":"{}","customer_order_id":"store-race","currency":"BDT","amount":786,"payable_amount":786,"sp_code":1000,"sp_message":"Success"}}]"#,
+            response.sp_order_id
+        );
+        for _ in 0..4 {
+            gateway.enqueue("/api/verification/", MockResponse::json(200, &body).delayed(400));
+        }
+        let paid = Arc::new(std::sync::Mutex::new(0));
+        let seen = paid.clone();
+        let callbacks = PaymentCallbacks::default().on_success(move |_| *seen.lock().unwrap() += 1);
+        let started = std::time::Instant::now();
+        let returns: Vec<_> = (0..4)
+            .map(|_| {
+                let (plugin, callbacks, sp_order_id) = (plugin.clone(), callbacks.clone(), response.sp_order_id.clone());
+                std::thread::spawn(move || web::verify_order(&plugin, &sp_order_id, &callbacks))
+            })
+            .collect();
+        let newly_paid = returns
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .filter(|outcome| outcome.newly_paid)
+            .count();
+        assert!(started.elapsed() < std::time::Duration::from_millis(1200), "{:?}", started.elapsed());
+        assert_eq!(newly_paid, 1);
+        assert_eq!(*paid.lock().unwrap(), 1);
+        assert_eq!(store.transitions("store-race").unwrap().len(), 3);
+    }
+
+    #[test]
+    fn paid_record_stays_paid_test() {
+        let gateway = MockGateway::start();
+        let store = Arc::new(MemoryPaymentStore::new());
+        let mut sp_instance = gateway.plugin();
+        sp_instance.set_payment_store(store.clone());
+        let payment_req_obj = checkout_request(&mut sp_instance, "786", "store-paid");
+        let response = sp_instance.make_payment_checkout(payment_req_obj).unwrap();
+
+        gateway.set_verified(&response.sp_order_id, "store-paid", 786.0, "BDT", 1000);
+        sp_instance.verify_payment(Some(response.sp_order_id.clone())).unwrap();
+        gateway.set_verified(&response.sp_order_id, "store-paid", 786.0, "BDT", 1001);
+        sp_instance.verify_payment(Some(response.sp_order_id.clone())).unwrap();
+
+        let record = store.find_by_order_id("store-paid").unwrap().unwrap();
+        assert_eq!(record.state, PaymentState::Paid);
+        assert_eq!(record.verify_response.unwrap().sp_code, Some(1001));
+        assert_eq!(store.transitions("store-paid").unwrap().len(), 3);
+
+        let payment_req_obj = checkout_request(&mut sp_instance, "786", "store-paid");
+        assert!(sp_instance.make_payment_checkout(payment_req_obj).is_none());
+        assert_eq!(sp_instance.last_error, Some(SpError::AlreadyPaid("store-paid".to_string())));
+        assert_eq!(gateway.hits("/api/secret-pay/"), 1);
+    }
+
+    #[test]
+    fn failed_checkout_is_recorded_test() {
+        let gateway = MockGateway::start();
+        gateway.enqueue("/api/secret-pay/", MockResponse::json(500, r#"{"message":"Server Error"}"#));
+        let store = Arc::new(MemoryPaymentStore::new());
+        let mut sp_instance = gateway.plugin();
+        sp_instance.set_payment_store(store.clone());
+
+        let payment_req_obj = checkout_request(&mut sp_instance, "100", "store-002");
+        assert!(sp_instance.make_payment_no_auto_redirect(payment_req_obj).is_none());
+
+        let record = store.find_by_order_id("store-002").unwrap().unwrap();
+        assert_eq!(record.state, PaymentState::CheckoutFailed);
+        assert!(record.sp_order_id.is_none());
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_store_survives_reopen_test() {
+        use shurjopay_plugin::sqlite_store::SqlitePaymentStore;
+
+        let path = std::env::temp_dir().join(format!("sp-ledger-{}.sqlite", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let gateway = MockGateway::start();
+
+        {
+            let mut sp_instance = gateway.plugin();
+            sp_instance.set_payment_store(Arc::new(SqlitePaymentStore::open(&path).unwrap()));
+            let payment_req_obj = checkout_request(&mut sp_instance, "250", "store-003");
+            sp_instance.make_payment_no_auto_redirect(payment_req_obj).unwrap();
+        }
+
+        let store = SqlitePaymentStore::open(&path).unwrap();
+        let record = store.find_by_order_id("store-003").unwrap().unwrap();
+        assert_eq!(record.state, PaymentState::CheckoutCreated);
+        assert_eq!(record.amount, "250");
+        let by_sp_order_id = store.find_by_sp_order_id(record.sp_order_id.as_deref().unwrap()).unwrap().unwrap();
+        assert_eq!(by_sp_order_id.order_id, "store-003");
+        assert_eq!(store.transitions("store-003").unwrap().len(), 2);
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn sqlite_state_change_is_atomic_test() {
+        use shurjopay_plugin::sqlite_store::SqlitePaymentStore;
+
+        // A transitions table that refuses `paid` makes the second write of the state change fail
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE sp_payment_transitions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                order_id TEXT NOT NULL,
+                from_state TEXT,
+                to_state TEXT NOT NULL CHECK (to_state != 'paid'),
+                at TEXT NOT NULL
+            );",
+        )
+        .unwrap();
+        let store = SqlitePaymentStore::from_connection(conn).unwrap();
+        let gateway = MockGateway::start();
+        let mut sp_instance = gateway.plugin();
+
+        let payment_req_obj = checkout_request(&mut sp_instance, "250", "store-004");
+        store.record_checkout_request(&payment_req_obj).unwrap();
+        let checkout = sp_instance.make_payment_checkout(payment_req_obj).unwrap();
+        store.record_checkout_response("store-004", Some(&checkout)).unwrap();
+        gateway.set_verified(&checkout.sp_order_id, "store-004", 250.0, "BDT", 1000);
+        let verified = sp_instance.verify_payment(Some(checkout.sp_order_id)).unwrap();
+
+        assert!(matches!(store.record_verification(&verified), Err(SpError::Store(_))));
+        let record = store.find_by_order_id("store-004").unwrap().unwrap();
+        assert_eq!(record.state, PaymentState::CheckoutCreated);
+        assert!(record.verify_response.is_none());
+        assert_eq!(store.transitions("store-004").unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,116 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use shurjopay_plugin::error::SpError;
+    use shurjopay_plugin::phone::{Operator, PhoneError, PhoneNumber};
+    use shurjopay_plugin::web::{self, CheckoutRequest};
+
+    use crate::common::{checkout_request, MockGateway};
+
+    fn invalid(phone: &str) -> PhoneError {
+        match PhoneNumber::parse(phone) {
+            Err(SpError::InvalidPhone(err)) => err,
+            other => panic!("`{}` parsed as {:?}", phone, other),
+        }
+    }
+
+    #[test]
+    fn parse_phone_test() {
+        for phone in [
+            "01811177722",
+            "1811177722",
+            "+8801811177722",
+            "8801811177722",
+            "008801811177722",
+            " +880 1811-177722 ",
+            "+880 (0)1811 177 722",
+            "01811.177.722",
+            "০১৮১১১৭৭৭২২",
+        ] {
+            let parsed: PhoneNumber = phone.parse().unwrap();
+            assert_eq!(parsed.national(), "01811177722", "{}", phone);
+        }
+        let phone = PhoneNumber::parse("+880 1711-223344").unwrap();
+        assert_eq!(phone.to_string(), "01711223344");
+        assert_eq!(phone.international(), "+8801711223344");
+        assert_eq!(phone.operator(), Operator::Grameenphone);
+        assert_eq!(PhoneNumber::parse("01611223344").unwrap().operator(), Operator::Airtel);
+        assert_eq!(PhoneNumber::parse("01911223344").unwrap().operator(), Operator::Banglalink);
+    }
+
+    #[test]
+    fn invalid_phone_test() {
+        assert_eq!(invalid("  "), PhoneError::Empty);
+        assert_eq!(invalid("0181117772a"), PhoneError::InvalidCharacter('a'));
+        assert_eq!(invalid("018+11177722"), PhoneError::InvalidCharacter('+'));
+        assert_eq!(invalid("+14155550100"), PhoneError::NotBangladeshi);
+        assert_eq!(invalid("0044 7911 123456"), PhoneError::NotBangladeshi);
+        assert_eq!(invalid("02-9123456"), PhoneError::NotMobile);
+        assert_eq!(invalid("0181117772"), PhoneError::WrongLength { digits: 10 });
+        assert_eq!(invalid("+88018111777222"), PhoneError::WrongLength { digits: 12 });
+        assert_eq!(invalid("01211177722"), PhoneError::UnknownOperator { prefix: "012".to_string() });
+        assert_eq!(
+            SpError::from(PhoneError::WrongLength { digits: 10 }).to_string(),
+            "invalid phone number: 10 digits, a mobile number has 11 digits (01XNNNNNNNN)"
+        );
+    }
+
+    #[test]
+    fn masked_phone_test() {
+        let phone = PhoneNumber::parse("+8801811177722").unwrap();
+        assert_eq!(phone.masked(), "018*****722");
+        assert_eq!(format!("{:?}", phone), r#"PhoneNumber("018*****722")"#);
+
+        assert_eq!(serde_json::to_string(&phone).unwrap(), r#""01811177722""#);
+        let parsed: PhoneNumber = serde_json::from_str(r#""+880 1811-177722""#).unwrap();
+        assert_eq!(parsed, phone);
+        assert!(serde_json::from_str::<PhoneNumber>(r#""01211177722""#).is_err());
+    }
+
+    #[test]
+    fn checkout_phone_test() {
+        let gateway = MockGateway::start();
+        let mut sp_instance = gateway.plugin();
+        let request = |customer_phone: &str| CheckoutRequest {
+            amount: "786".to_string(),
+            order_id: Some("INV-PHONE".to_string()),
+            currency: None,
+            customer_name: "Mahmudul Islam".to_string(),
+            customer_address: "Dhaka".to_string(),
+            customer_phone: customer_phone.to_string(),
+            customer_city: "Dhaka".to_string(),
+            customer_post_code: "1203".to_string(),
+            client_ip: None,
+        };
+
+        let checkout = web::checkout_item(&mut sp_instance, request("+880 1811-177722")).unwrap();
+        assert_eq!(checkout.customer_phone, "01811177722");
+        sp_instance.make_payment_checkout(checkout).unwrap();
+        let body: serde_json::Value = serde_json::from_str(&gateway.requests().last().unwrap().body).unwrap();
+        assert_eq!(body["customer_phone"], "01811177722");
+
+        let err = web::checkout_item(&mut sp_instance, request("01811")).unwrap_err();
+        assert_eq!(err, SpError::InvalidPhone(PhoneError::WrongLength { digits: 5 }));
+        assert_eq!(web::checkout_error_status(&err), 400);
+    }
+
+    #[test]
+    fn core_checkout_phone_test() {
+        let gateway = MockGateway::start();
+        let mut sp_instance = gateway.plugin();
+        let mut checkout = checkout_request(&mut sp_instance, "786", "INV-CORE");
+        checkout.customer_phone = "+880 1811-177722".to_string();
+        sp_instance.make_payment_checkout(checkout.clone()).unwrap();
+        let body: serde_json::Value = serde_json::from_str(&gateway.requests().last().unwrap().body).unwrap();
+        assert_eq!(body["customer_phone"], "01811177722");
+
+        checkout.customer_phone = "01211177722".to_string();
+        assert!(sp_instance.make_payment_no_auto_redirect(checkout.clone()).is_none());
+        assert!(matches!(sp_instance.last_error, Some(SpError::InvalidPhone(_))));
+        assert!(sp_instance.secure_ckeckout(checkout).is_none());
+        assert!(matches!(sp_instance.last_error, Some(SpError::InvalidPhone(_))));
+        assert_eq!(gateway.hits("/api/secret-pay/"), 1);
+    }
+}
@@ -0,0 +1,61 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use shurjopay_plugin::error::SpError;
+    use shurjopay_plugin::shurjopay::ShurjopayPlugin;
+
+    use crate::common::{checkout_request, MockGateway, MockResponse};
+
+    #[test]
+    fn check_payment_test() {
+        let gateway = MockGateway::start();
+        gateway.set_verified("sp-check", "ord-1", 786.0, "BDT", 1000);
+        let mut sp_instance = gateway.plugin();
+
+        let response = sp_instance.check_payment(Some("sp-check".to_string())).unwrap();
+        assert_eq!(response.sp_code, Some(1000));
+        assert_eq!(sp_instance.check_response.clone().unwrap().customer_order_id, Some("ord-1".to_string()));
+        assert_eq!(gateway.hits("/api/payment-status/"), 1);
+        assert_eq!(gateway.hits("/api/verification/"), 0);
+
+        let injected = r#"x","order_id":"sp-check"#;
+        sp_instance.check_payment(Some(injected.to_string()));
+        let body: serde_json::Value = serde_json::from_str(&gateway.requests().last().unwrap().body).unwrap();
+        assert_eq!(body, serde_json::json!({ "order_id": injected }));
+    }
+
+    #[test]
+    fn last_error_test() {
+        let gateway = MockGateway::start();
+        gateway.enqueue("/api/get_token/", MockResponse::json(401, r#"{"sp_code":"1064","message":"Unauthorized"}"#));
+        let mut sp_instance = gateway.plugin();
+        assert!(sp_instance.get_auth_token().is_none());
+        assert_eq!(sp_instance.last_error, Some(SpError::Auth("Unauthorized".to_string())));
+
+        assert!(sp_instance.get_auth_token().is_some());
+        assert_eq!(sp_instance.last_error, None);
+
+        let mut sp_instance = ShurjopayPlugin::new();
+        assert!(sp_instance.get_auth_token().is_none());
+        assert!(matches!(sp_instance.last_error, Some(SpError::Config(_))));
+    }
+
+    #[test]
+    fn make_payment_checkout_test() {
+        let gateway = MockGateway::start();
+        let mut sp_instance = gateway.plugin();
+
+        let first = checkout_request(&mut sp_instance, "1000", "INV-1");
+        let first = sp_instance.make_payment_checkout(first).unwrap();
+        let second = checkout_request(&mut sp_instance, "2500", "INV-2");
+        let second = sp_instance.make_payment_checkout(second).unwrap();
+
+        assert_eq!((first.sp_order_id.as_str(), first.customer_order_id.as_str()), ("sp-mock-1", "INV-1"));
+        assert_eq!((second.sp_order_id.as_str(), second.customer_order_id.as_str()), ("sp-mock-2", "INV-2"));
+        assert!(first.checkout_url.ends_with("order_id=sp-mock-1"));
+        assert_eq!(first.transactionStatus, "Initiated");
+        assert_eq!(sp_instance.checkout_response, Some(second));
+    }
+}
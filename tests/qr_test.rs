@@ -0,0 +1,62 @@
+#![cfg(feature = "qr")]
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use shurjopay_plugin::error::SpError;
+    use shurjopay_plugin::qr::{CheckoutQr, ErrorCorrection, QrOptions, MAX_MODULE_SIZE};
+
+    use crate::common::{checkout_request, MockGateway};
+
+    const URL: &str = "https://sandbox.shurjopayment.com/spaycheckout/?token=abc&order_id=sp636384e391650";
+
+    #[test]
+    fn render_checkout_qr_test() {
+        let options = QrOptions {
+            module_size: 4,
+            ..Default::default()
+        };
+        let code = CheckoutQr::new(URL, options).unwrap();
+        let side = (code.modules() + 8) * 4;
+
+        let svg = code.to_svg();
+        assert!(svg.contains("<svg") && svg.contains(&format!("width=\"{}\"", side)));
+
+        let png = code.to_png().unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(u32::from_be_bytes([png[16], png[17], png[18], png[19]]) as usize, side);
+
+        let terminal = code.to_terminal();
+        assert_eq!(terminal.lines().count(), (code.modules() + 8 + 1) / 2);
+
+        let high = CheckoutQr::new(URL, QrOptions { error_correction: ErrorCorrection::High, ..Default::default() }).unwrap();
+        let low = CheckoutQr::new(URL, QrOptions { error_correction: ErrorCorrection::Low, ..Default::default() }).unwrap();
+        assert!(high.modules() > low.modules());
+    }
+
+    #[test]
+    fn qr_options_test() {
+        assert_eq!("q".parse::<ErrorCorrection>(), Ok(ErrorCorrection::Quartile));
+        assert_eq!("High".parse::<ErrorCorrection>(), Ok(ErrorCorrection::High));
+        assert!(matches!("X".parse::<ErrorCorrection>(), Err(SpError::Config(_))));
+        assert!(matches!(CheckoutQr::new(&"x".repeat(5000), QrOptions::default()), Err(SpError::Config(_))));
+        for module_size in [0, MAX_MODULE_SIZE + 1, u32::MAX] {
+            let options = QrOptions { module_size, ..Default::default() };
+            assert!(matches!(CheckoutQr::new(URL, options), Err(SpError::Config(_))));
+        }
+        let options = QrOptions { module_size: MAX_MODULE_SIZE, ..Default::default() };
+        assert!(CheckoutQr::new(URL, options).is_ok());
+    }
+
+    #[test]
+    fn checkout_response_qr_test() {
+        let gateway = MockGateway::start();
+        let mut sp_instance = gateway.plugin();
+        let checkout = checkout_request(&mut sp_instance, "1000", "INV-1");
+        let response = sp_instance.make_payment_checkout(checkout).unwrap();
+        let code = response.qr_code(QrOptions::default()).unwrap();
+        assert!(code.to_svg().starts_with("<?xml"));
+    }
+}
@@ -0,0 +1,74 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use std::time::{Duration, Instant};
+
+    use shurjopay_plugin::error::SpError;
+    use shurjopay_plugin::rate_limit::{parse_retry_after, OnExhausted, MAX_RETRY_AFTER, RateLimit, RateLimitConfig, RateLimiter};
+
+    use crate::common::{MockGateway, MockResponse};
+
+    #[test]
+    fn global_and_end_point_buckets_test() {
+        let config = RateLimitConfig::default()
+            .global(RateLimit { requests_per_second: 1.0, burst: 3 })
+            .end_point("/api/verification", RateLimit { requests_per_second: 1.0, burst: 1 })
+            .on_exhausted(OnExhausted::Fail);
+        let limiter = RateLimiter::new();
+
+        assert!(limiter.acquire(&config, "/api/verification").is_ok());
+        let err = limiter.acquire(&config, "/api/verification").unwrap_err();
+        assert!(matches!(err, SpError::RateLimited { retry_after_ms, .. } if retry_after_ms > 0));
+        assert!(limiter.acquire(&config, "/api/get_token").is_ok());
+        assert!(limiter.acquire(&config, "/api/secret-pay").is_ok());
+        assert!(limiter.acquire(&config, "/api/secret-pay").is_err());
+    }
+
+    #[test]
+    fn exhausted_bucket_waits_for_a_token_test() {
+        let config = RateLimitConfig::default().global(RateLimit { requests_per_second: 20.0, burst: 1 });
+        let limiter = RateLimiter::new();
+
+        let started = Instant::now();
+        for _ in 0..3 {
+            limiter.acquire(&config, "/api/verification").unwrap();
+        }
+        assert!(started.elapsed() >= Duration::from_millis(90));
+    }
+
+    #[test]
+    fn retry_after_pauses_end_point_test() {
+        let gateway = MockGateway::start();
+        let mut throttled = MockResponse::json(429, r#"{"message":"Too Many Attempts."}"#);
+        throttled.headers.push(("Retry-After".to_string(), "60".to_string()));
+        gateway.enqueue("/api/verification/", throttled);
+
+        let mut sp_instance = gateway.plugin();
+        if let Some(config) = sp_instance.config.as_mut() {
+            config.rate_limit = RateLimitConfig::default().on_exhausted(OnExhausted::Fail);
+        }
+        sp_instance.verify_payment(Some("sp-throttled".to_string()));
+        assert!(sp_instance.verify_payment(Some("sp-throttled".to_string())).is_none());
+        assert!(matches!(
+            sp_instance.last_error,
+            Some(SpError::RateLimited { retry_after_ms, .. }) if retry_after_ms > 50_000
+        ));
+        assert_eq!(gateway.hits("/api/verification/"), 1);
+
+        assert_eq!(parse_retry_after("120"), Some(Duration::from_secs(120)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon"), None);
+        assert_eq!(parse_retry_after("18446744073709551615"), Some(MAX_RETRY_AFTER));
+        assert_eq!(parse_retry_after("Fri, 31 Dec 9999 23:59:59 GMT"), Some(MAX_RETRY_AFTER));
+
+        let limiter = RateLimiter::new();
+        limiter.pause("/api/verification", Duration::MAX);
+        let config = RateLimitConfig::default().on_exhausted(OnExhausted::Fail);
+        assert!(matches!(
+            limiter.acquire(&config, "/api/verification"),
+            Err(SpError::RateLimited { retry_after_ms, .. }) if retry_after_ms <= 3_600_000
+        ));
+    }
+}
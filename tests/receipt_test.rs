@@ -0,0 +1,119 @@
+#![cfg(feature = "receipt")]
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use shurjopay_plugin::cart::{Cart, LineItem, VatRate};
+    use shurjopay_plugin::error::SpError;
+    use shurjopay_plugin::money::Money;
+    use shurjopay_plugin::receipt::{Language, Receipt, ReceiptRenderer};
+    use shurjopay_plugin::shurjopay::SpVerifyResponse;
+
+    use crate::common::{checkout_request, MockGateway};
+
+    fn response(body: &str) -> SpVerifyResponse {
+        serde_json::from_str(body).unwrap()
+    }
+
+    #[test]
+    fn english_receipt_test() {
+        let gateway = MockGateway::start();
+        let mut sp_instance = gateway.plugin();
+        let checkout = checkout_request(&mut sp_instance, "1234567.5", "INV-RCPT");
+        let checkout = sp_instance.make_payment_checkout(checkout).unwrap();
+        gateway.set_verified(&checkout.sp_order_id, "INV-RCPT", 1234567.5, "BDT", 1000);
+        let verified = sp_instance.verify_payment(Some(checkout.sp_order_id.clone())).unwrap();
+
+        let receipt = Receipt::from_verification(&verified, None, Language::English).unwrap();
+        assert!(receipt.paid);
+        assert_eq!(receipt.card_number.as_deref(), Some("****XXXXXXXX1111"));
+        assert_eq!(receipt.date.as_deref(), Some("30 November 2022, 3:07 PM"));
+        assert_eq!(receipt.amount.as_deref(), Some("1,234,567.50"));
+        assert_eq!(receipt.discount, None);
+
+        let renderer = ReceiptRenderer::new();
+        let text = renderer.render_text(&receipt).unwrap();
+        assert!(text.starts_with("Payment receipt\nStatus: Paid\nOrder id: INV-RCPT\n"));
+        assert!(text.contains(&format!("Bank transaction id: BTX{}\n", checkout.sp_order_id)));
+        assert!(text.contains("Card: ****XXXXXXXX1111\n"));
+        assert!(text.contains("Amount paid: 1,234,567.50 BDT\n"));
+        assert!(!text.contains("4111"));
+        let html = renderer.render_html(&receipt).unwrap();
+        assert!(html.contains("<td>Visa</td>"));
+        assert!(html.contains("<strong>1,234,567.50 BDT</strong>"));
+    }
+
+    #[test]
+    fn bangla_receipt_test() {
+        let verified = response(
+            r#"{"sp_code":1000,"order_id":"sp1","customer_order_id":"INV-BN","currency":"BDT","amount":125000,"payable_amount":120000,"discsount_amount":5000,"received_amount":"120000.00","method":"bKash","date_time":"2024-03-05 09:30:00"}"#,
+        );
+        let receipt = Receipt::from_verification(&verified, None, "bn".parse().unwrap()).unwrap();
+        assert_eq!(receipt.date.as_deref(), Some("৫ মার্চ ২০২৪, ০৯:৩০"));
+        assert_eq!(receipt.amount.as_deref(), Some("১,২৫,০০০.০০"));
+        assert_eq!(receipt.discount.as_deref(), Some("৫,০০০.০০"));
+        assert_eq!(receipt.paid_amount.as_deref(), Some("১,২০,০০০.০০"));
+        assert_eq!(receipt.currency, "টাকা");
+
+        let text = ReceiptRenderer::new().render_text(&receipt).unwrap();
+        assert!(text.starts_with("পেমেন্ট রসিদ\nঅবস্থা: পরিশোধিত\n"));
+        assert!(text.contains("ছাড়: -৫,০০০.০০ টাকা\n"));
+        assert!(text.contains("পরিশোধিত মূল্য: ১,২০,০০০.০০ টাকা\n"));
+
+        let unread = response(r#"{"sp_code":1002,"currency":"BDT","date_time":"30/11/2022"}"#);
+        let receipt = Receipt::from_verification(&unread, None, Language::Bangla).unwrap();
+        assert_eq!((receipt.paid, receipt.status.as_str()), (false, "অপরিশোধিত"));
+        assert_eq!(receipt.date.as_deref(), Some("30/11/2022"));
+        assert!(matches!("fr".parse::<Language>(), Err(SpError::Config(_))));
+    }
+
+    #[test]
+    fn cart_receipt_test() {
+        let bdt = |amount: &str| Money::parse(amount, "BDT").unwrap();
+        let mut cart = Cart::new("BDT");
+        cart.add_item(LineItem::new("TEA", "Tea & biscuits", 3, bdt("120")).with_vat(VatRate::percent(15)))
+            .unwrap();
+        cart.add_item(LineItem::new("MUG", "Mug", 1, bdt("450"))).unwrap();
+        cart.set_shipping(bdt("60")).unwrap();
+        let verified = response(
+            r#"{"sp_code":1000,"order_id":"sp2","customer_order_id":"INV-CART","currency":"BDT","amount":924,"payable_amount":924,"card_number":"5412 7534 5678 9010","name":"<b>Rahim</b>"}"#,
+        );
+
+        let receipt = Receipt::from_verification(&verified, Some(&cart), Language::English).unwrap();
+        assert_eq!(receipt.items.len(), 2);
+        assert_eq!((receipt.items[0].quantity.as_str(), receipt.items[0].total.as_str()), ("3", "414.00"));
+        let totals = receipt.totals.as_ref().unwrap();
+        assert_eq!((totals.subtotal.as_str(), totals.total.as_str()), ("810.00", "924.00"));
+        assert_eq!((totals.discount.as_deref(), totals.vat.as_deref()), (None, Some("54.00")));
+        assert_eq!(receipt.card_number.as_deref(), Some("**** **** **** 9010"));
+
+        let renderer = ReceiptRenderer::new();
+        let text = renderer.render_text(&receipt).unwrap();
+        assert!(text.contains("3 x Tea & biscuits @ 120.00 = 414.00\n1 x Mug @ 450.00 = 450.00\n"));
+        assert!(text.contains("Shipping: 60.00\nTotal: 924.00\n"));
+        let html = renderer.render_html(&receipt).unwrap();
+        assert!(html.contains("<td>Tea &amp; biscuits</td>"));
+        assert!(html.contains("&lt;b&gt;Rahim&lt;&#x2f;b&gt;"));
+        assert!(!html.contains("<b>Rahim"));
+    }
+
+    #[test]
+    fn template_override_test() {
+        let verified = response(r#"{"sp_code":1000,"customer_order_id":"INV-OWN","currency":"BDT","amount":50,"invoice_no":"<SP-9>"}"#);
+        let receipt = Receipt::from_verification(&verified, None, Language::English).unwrap();
+
+        let mut renderer = ReceiptRenderer::default();
+        renderer
+            .set_text_template("{{ labels.invoice_no }} {{ invoice_no }}: {{ amount }} {{ currency }}")
+            .unwrap()
+            .set_html_template("<p>{{ invoice_no }}</p>")
+            .unwrap();
+        assert_eq!(renderer.render_text(&receipt).unwrap(), "Invoice no <SP-9>: 50.00 BDT");
+        assert_eq!(renderer.render_html(&receipt).unwrap(), "<p>&lt;SP-9&gt;</p>");
+
+        assert!(matches!(renderer.set_text_template("{% if %}"), Err(SpError::Config(_))));
+        assert_eq!(renderer.render_text(&receipt).unwrap(), "Invoice no <SP-9>: 50.00 BDT");
+    }
+}
@@ -0,0 +1,169 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use std::sync::Arc;
+
+    use shurjopay_plugin::discount::Discount;
+    use shurjopay_plugin::money::Money;
+    use shurjopay_plugin::observer::ChannelObserver;
+    use shurjopay_plugin::payment_store::{MemoryPaymentStore, PaymentState, PaymentStore};
+    use shurjopay_plugin::reconciliation::{reconcile, AmountField, LocalRecord, Mismatch};
+
+    use crate::common::{checkout_request, MockGateway};
+
+    fn local(order_id: &str, amount: &str, status: PaymentState) -> LocalRecord {
+        LocalRecord {
+            order_id: order_id.to_string(),
+            expected: Money::parse(amount, "BDT").unwrap(),
+            expected_status: status,
+        }
+    }
+
+    #[test]
+    fn money_parse_test() {
+        assert_eq!(Money::parse("786", "bdt").unwrap().minor(), 78600);
+        assert_eq!(Money::parse("786.5", "BDT").unwrap().amount_string(), "786.50");
+        assert_eq!(Money::from_f64(10.005, "BDT").unwrap(), Money::parse("10.01", "BDT").unwrap());
+        assert!(Money::parse("1.234", "BDT").is_err());
+        assert!(Money::parse("12a", "BDT").is_err());
+        assert!(Money::parse("", "BDT").is_err());
+    }
+
+    #[test]
+    fn reconcile_reports_mismatches_test() {
+        let gateway = MockGateway::start();
+        gateway.set_verified("sp-ok", "ord-1", 786.0, "BDT", 1000);
+        gateway.set_verified("sp-amount", "ord-2", 700.0, "BDT", 1000);
+        gateway.set_verified("sp-currency", "ord-3", 100.0, "USD", 1000);
+        gateway.set_verified("sp-status", "ord-4", 100.0, "BDT", 1002);
+        let mut sp_instance = gateway.plugin();
+
+        let report = reconcile(
+            &mut sp_instance,
+            &[
+                local("sp-ok", "786", PaymentState::Paid),
+                local("sp-amount", "786", PaymentState::Paid),
+                local("sp-currency", "100", PaymentState::Paid),
+                local("sp-status", "100", PaymentState::Paid),
+                local("sp-missing", "100", PaymentState::Paid),
+            ],
+        );
+
+        assert_eq!(report.checked, 5);
+        assert_eq!(report.matched, vec!["sp-ok".to_string()]);
+        let mismatches = |order_id: &str| {
+            report
+                .discrepancies
+                .iter()
+                .find(|discrepancy| discrepancy.order_id == order_id)
+                .unwrap()
+                .mismatches
+                .clone()
+        };
+
+        assert_eq!(
+            mismatches("sp-amount"),
+            vec![
+                Mismatch::Amount {
+                    field: AmountField::PayableAmount,
+                    expected: Money::parse("786", "BDT").unwrap(),
+                    actual: Money::parse("700", "BDT").unwrap(),
+                },
+                Mismatch::Amount {
+                    field: AmountField::ReceivedAmount,
+                    expected: Money::parse("786", "BDT").unwrap(),
+                    actual: Money::parse("700", "BDT").unwrap(),
+                },
+            ]
+        );
+        assert_eq!(
+            mismatches("sp-currency"),
+            vec![Mismatch::Currency { expected: "BDT".to_string(), actual: Some("USD".to_string()) }]
+        );
+        assert_eq!(
+            mismatches("sp-status"),
+            vec![Mismatch::Status { expected: PaymentState::Paid, actual: PaymentState::Cancelled, sp_code: Some(1002) }]
+        );
+        assert_eq!(mismatches("sp-missing"), vec![Mismatch::UnknownToGateway]);
+    }
+
+    #[test]
+    fn reconcile_open_checkouts_test() {
+        let gateway = MockGateway::start();
+        gateway.set_verified("sp-paid", "ord-1", 786.0, "BDT", 1000);
+        gateway.set_verified("sp-cancelled", "ord-2", 786.0, "BDT", 1002);
+        gateway.set_verified("sp-amount", "ord-3", 700.0, "BDT", 1000);
+        let mut sp_instance = gateway.plugin();
+
+        let report = reconcile(
+            &mut sp_instance,
+            &[
+                local("sp-paid", "786", PaymentState::CheckoutCreated),
+                local("sp-cancelled", "786", PaymentState::CheckoutRequested),
+                local("sp-amount", "786", PaymentState::CheckoutCreated),
+                local("sp-missing", "786", PaymentState::CheckoutCreated),
+            ],
+        );
+
+        assert_eq!(report.checked, 4);
+        assert!(report.matched.is_empty());
+        assert_eq!(report.unverified, vec!["sp-paid".to_string(), "sp-cancelled".to_string()]);
+        let order_ids: Vec<&str> = report.discrepancies.iter().map(|d| d.order_id.as_str()).collect();
+        assert_eq!(order_ids, vec!["sp-amount", "sp-missing"]);
+        assert!(report.discrepancies[0]
+            .mismatches
+            .iter()
+            .all(|mismatch| matches!(mismatch, Mismatch::Amount { .. })));
+    }
+
+    #[test]
+    fn reconcile_discounted_ledger_test() {
+        let gateway = MockGateway::start();
+        let mut sp_instance = gateway.plugin();
+        let store = Arc::new(MemoryPaymentStore::new());
+        sp_instance.set_payment_store(store.clone());
+
+        let applied = Discount::Fixed(Money::parse("100", "BDT").unwrap())
+            .apply(&Money::parse("786", "BDT").unwrap())
+            .unwrap();
+        let checkout = applied.to_checkout(checkout_request(&mut sp_instance, "786", "INV-DISC"));
+        let response = sp_instance.make_payment_checkout(checkout).unwrap();
+        gateway.set_verified(&response.sp_order_id, "INV-DISC", 686.0, "BDT", 1000);
+        sp_instance.verify_payment(Some(response.sp_order_id.clone())).unwrap();
+
+        let record = store.find_by_order_id("INV-DISC").unwrap().unwrap();
+        assert_eq!(record.amount, "786.00");
+        let local = LocalRecord::from_payment_record(&record).unwrap();
+        assert_eq!(local.expected, Money::parse("686", "BDT").unwrap());
+        let report = reconcile(&mut sp_instance, &[local]);
+        assert!(report.is_clean());
+        assert_eq!(report.matched, vec![response.sp_order_id]);
+    }
+
+    #[test]
+    fn reconcile_leaves_ledger_and_observers_alone_test() {
+        let gateway = MockGateway::start();
+        let mut sp_instance = gateway.plugin();
+        let store = Arc::new(MemoryPaymentStore::new());
+        sp_instance.set_payment_store(store.clone());
+        let (channel, events) = ChannelObserver::new();
+        sp_instance.add_observer(Arc::new(channel));
+
+        let checkout = checkout_request(&mut sp_instance, "786", "INV-READ");
+        let response = sp_instance.make_payment_checkout(checkout).unwrap();
+        let _ = events.try_iter().count();
+        gateway.set_verified(&response.sp_order_id, "INV-READ", 786.0, "BDT", 1002);
+        let record = store.find_by_order_id("INV-READ").unwrap().unwrap();
+        let report = reconcile(&mut sp_instance, &[LocalRecord::from_payment_record(&record).unwrap()]);
+        assert_eq!(report.unverified, vec![response.sp_order_id.clone()]);
+
+        let record = store.find_by_order_id("INV-READ").unwrap().unwrap();
+        assert_eq!(record.state, PaymentState::CheckoutCreated);
+        assert!(record.verify_response.is_none());
+        assert_eq!(store.transitions("INV-READ").unwrap().len(), 2);
+        assert_eq!(events.try_iter().count(), 0);
+        assert_eq!(gateway.hits("/api/verification/"), 1);
+    }
+}
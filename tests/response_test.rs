@@ -0,0 +1,171 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use proptest::prelude::*;
+
+    use shurjopay_plugin::error::SpError;
+    use shurjopay_plugin::response::{decode, decode_all, decode_checkout, decode_verification, error_message, items};
+    #[allow(deprecated)]
+    use shurjopay_plugin::shurjopay::{remove_first_and_last_ch, unwrap_json};
+    use shurjopay_plugin::shurjopay::{SpAuthToken, SpCheckoutResponse, SpVerifyResponse};
+    use shurjopay_plugin::shurjopay_client::HttpResponse;
+
+    use crate::common::{MockGateway, MockResponse};
+
+    fn response(http_code: u16, body: &str) -> HttpResponse {
+        HttpResponse {
+            http_code,
+            http_body: body.to_string(),
+        }
+    }
+
+    #[test]
+    fn decode_envelopes_test() {
+        assert!(matches!(items(&response(200, "")), Err(SpError::Gateway { http_code: 200, .. })));
+        assert!(matches!(items(&response(502, "<html><body>Bad Gateway</body></html>")), Err(SpError::Gateway { http_code: 502, .. })));
+        assert_eq!(items(&response(200, "[]")).unwrap().len(), 0);
+        assert_eq!(items(&response(200, r#"[{"a":1},{"a":2}]"#)).unwrap().len(), 2);
+        assert_eq!(items(&response(200, r#" {"a":1} "#)).unwrap().len(), 1);
+        assert!(decode::<SpVerifyResponse>(&response(200, "[]")).is_err());
+
+        let token = r#"{"token":"t","store_id":1,"execute_url":"","token_type":"Bearer","sp_code":200,"message":"Ok","token_create_time":"","expires_in":3600}"#;
+        assert_eq!(decode::<SpAuthToken>(&response(200, token)).unwrap().sp_code, "200");
+        assert!(decode::<SpAuthToken>(&response(401, token)).is_err());
+        assert!(decode_all::<SpCheckoutResponse>(&response(200, token)).is_err());
+        assert_eq!(error_message(&response(401, r#"{"sp_code":"1064","message":"Unauthorized"}"#)), Some("Unauthorized".to_string()));
+    }
+
+    #[test]
+    fn decode_verification_test() {
+        let body = r#"[{"sp_code":"1000","order_id":"sp-1","sp_message":"Success"},{"sp_code":1002,"order_id":"sp-2"}]"#;
+        let verified = decode_verification(&response(200, body)).unwrap();
+        assert_eq!((verified.sp_code, verified.order_id.as_deref()), (Some(1000), Some("sp-1")));
+
+        let failed = decode_verification(&response(200, r#"{"sp_code":"1011","message":"Order Not Found"}"#)).unwrap();
+        assert_eq!((failed.sp_code, failed.sp_message.as_deref()), (Some(1011), Some("Order Not Found")));
+
+        assert!(decode_verification(&response(500, "{}")).is_err());
+        assert!(decode_verification(&response(200, r#"[{"sp_code":"abc"}]"#)).is_err());
+    }
+
+    #[test]
+    fn decode_error_keeps_reason_test() {
+        let err = decode::<SpAuthToken>(&response(200, r#"{"token":"t"}"#)).unwrap_err();
+        match &err {
+            SpError::Decode { http_code, message, body } => {
+                assert_eq!(*http_code, 200);
+                assert!(message.contains("missing field"), "{}", message);
+                assert_eq!(body, r#"{"token":"t"}"#);
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(err.to_string().contains("missing field"));
+
+        let err = decode_verification(&response(200, r#"[{"sp_code":1000,"amount":"786,50"}]"#)).unwrap_err();
+        assert!(err.to_string().contains("expected a number"), "{}", err);
+    }
+
+    #[test]
+    fn plugin_maps_undecodable_bodies_to_errors_test() {
+        let gateway = MockGateway::start();
+        let mut sp_instance = gateway.plugin();
+        sp_instance.get_auth_token().unwrap();
+
+        gateway.enqueue("/api/verification/", MockResponse::json(200, ""));
+        assert!(sp_instance.verify_payment_id("sp-1".to_string()).is_none());
+        assert!(matches!(sp_instance.last_error, Some(SpError::Gateway { http_code: 200, .. })));
+
+        gateway.enqueue("/api/verification/", MockResponse::json(200, r#"[{"sp_code":"1011","message":"Order Not Found"}]"#));
+        let failed = sp_instance.verify_payment_id("sp-1".to_string()).unwrap();
+        assert_eq!(failed.sp_code, Some(1011));
+        assert_eq!(sp_instance.last_error, None);
+    }
+
+    #[test]
+    fn unknown_fields_and_type_drift_test() {
+        let body = r#"[{"sp_code":1000,"id":"42","order_id":"sp-1","amount":"786.50","received_amount":786.5,"bank_trx_id":9001,"usd_amt":null,"emi_tenure":6,"loyalty":{"points":12}}]"#;
+        let verified = decode_verification(&response(200, body)).unwrap();
+        assert_eq!((verified.id, verified.amount), (Some(42), Some(786.5)));
+        assert_eq!(verified.received_amount.as_deref(), Some("786.5"));
+        assert_eq!(verified.bank_trx_id.as_deref(), Some("9001"));
+        assert_eq!(verified.extra["emi_tenure"], 6);
+        assert_eq!(verified.extra["loyalty"]["points"], 12);
+        assert_eq!(verified.raw_body.as_deref(), Some(body));
+
+        let stored = serde_json::to_value(&verified).unwrap();
+        assert_eq!(stored["emi_tenure"], 6);
+        assert!(stored.get("raw_body").is_none());
+        let restored: SpVerifyResponse = serde_json::from_value(stored).unwrap();
+        assert_eq!(restored.extra, verified.extra);
+
+        let checkout = r#"{"checkout_url":"https://pay/1","amount":1000,"currency":"BDT","sp_order_id":"sp-1","customer_order_id":77,"customer_name":"M","customer_address":"D","customer_city":"D","customer_phone":"018","customer_email":null,"client_ip":"::1","intent":"sale","transactionStatus":"Initiated","expires_at":"2026-10-19 10:00:00"}"#;
+        let created = decode_checkout(&response(200, checkout)).unwrap();
+        assert_eq!((created.amount.as_str(), created.customer_order_id.as_str()), ("1000", "77"));
+        assert_eq!(created.extra["expires_at"], "2026-10-19 10:00:00");
+        assert_eq!(created.raw_body.as_deref(), Some(checkout));
+    }
+
+    #[test]
+    fn unreadable_values_are_not_dropped_test() {
+        for body in [
+            r#"[{"sp_code":1000,"payable_amount":"786,50"}]"#,
+            r#"[{"sp_code":1000,"amount":{"value":786}}]"#,
+            r#"[{"sp_code":1000,"amount":true}]"#,
+            r#"[{"sp_code":1000,"id":"4x"}]"#,
+        ] {
+            assert!(decode_verification(&response(200, body)).is_err(), "{}", body);
+        }
+
+        let body = r#"[{"sp_code":1000,"amount":"","payable_amount":null,"bank_status":{"code":1},"card_number":["4111"]}]"#;
+        let verified = decode_verification(&response(200, body)).unwrap();
+        assert_eq!((verified.amount, verified.payable_amount), (None, None));
+        assert_eq!(verified.bank_status.as_deref(), Some(r#"{"code":1}"#));
+        assert_eq!(verified.card_number.as_deref(), Some(r#"["4111"]"#));
+    }
+
+    /// Fragments of real and broken gateway bodies
+    fn body_fragment() -> impl Strategy<Value = String> {
+        prop_oneof![
+            Just("[".to_string()),
+            Just("]".to_string()),
+            Just("{".to_string()),
+            Just("}".to_string()),
+            Just(",".to_string()),
+            Just("\"sp_code\":".to_string()),
+            Just("\"message\":".to_string()),
+            Just("\"sp_message\":null".to_string()),
+            Just("\"1000\"".to_string()),
+            Just("1011".to_string()),
+            Just("<html>".to_string()),
+            Just("৳".to_string()),
+            any::<String>(),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        #[allow(deprecated)]
+        fn decoder_never_panics_test(http_code in any::<u16>(), body in any::<String>()) {
+            let response = response(http_code, &body);
+            let _ = items(&response);
+            let _ = decode::<SpVerifyResponse>(&response);
+            let _ = decode::<SpAuthToken>(&response);
+            let _ = decode_verification(&response);
+            let _ = error_message(&response);
+            let _ = remove_first_and_last_ch(&body);
+            let _ = unwrap_json::<SpCheckoutResponse>(&response);
+        }
+
+        #[test]
+        #[allow(deprecated)]
+        fn decoder_never_panics_on_fragments_test(fragments in prop::collection::vec(body_fragment(), 0..12)) {
+            let body = fragments.concat();
+            let response = response(200, &body);
+            let _ = decode_all::<SpVerifyResponse>(&response);
+            let _ = decode_verification(&response);
+            let _ = remove_first_and_last_ch(&body);
+        }
+    }
+}
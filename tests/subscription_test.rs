@@ -0,0 +1,234 @@
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use std::sync::Arc;
+
+    use chrono::{Duration, NaiveDate, NaiveDateTime};
+
+    use shurjopay_plugin::clock::ManualClock;
+    use shurjopay_plugin::error::SpError;
+    use shurjopay_plugin::money::Money;
+    use shurjopay_plugin::payment_link::{MemoryPaymentLinkStore, PaymentLinks};
+    use shurjopay_plugin::phone::PhoneError;
+    use shurjopay_plugin::shurjopay::SpVerifyResponse;
+    use shurjopay_plugin::subscription::{
+        parse_cycle_order_id, DunningEvent, Interval, MemorySubscriptionStore, Plan, Subscriber,
+        SubscriptionState, Subscriptions,
+    };
+
+    use crate::common::MockGateway;
+
+    fn at(year: i32, month: u32, day: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap().and_hms_opt(10, 0, 0).unwrap()
+    }
+
+    fn subscriber() -> Subscriber {
+        Subscriber {
+            name: "Mahmudul Islam".to_string(),
+            address: "Dhaka".to_string(),
+            phone: "01811177722".to_string(),
+            city: "Dhaka".to_string(),
+            post_code: "1203".to_string(),
+        }
+    }
+
+    fn billing(clock: &Arc<ManualClock>, trial_days: u32) -> Subscriptions {
+        let subscriptions = Subscriptions::new(Arc::new(MemorySubscriptionStore::new()), clock.clone());
+        subscriptions
+            .add_plan(Plan {
+                id: "monthly".to_string(),
+                name: "Monthly".to_string(),
+                amount: Money::parse("499", "BDT").unwrap(),
+                interval: Interval::Months(1),
+                trial_days,
+            })
+            .unwrap();
+        subscriptions
+    }
+
+    fn verification(order_id: &str, sp_code: i64) -> SpVerifyResponse {
+        let mut response = SpVerifyResponse::new();
+        response.customer_order_id = Some(order_id.to_string());
+        response.sp_code = Some(sp_code);
+        response.currency = Some("BDT".to_string());
+        response.payable_amount = Some(499.0);
+        response
+    }
+
+    fn plan(interval: Interval, trial_days: u32) -> Plan {
+        Plan {
+            id: "custom".to_string(),
+            name: "Custom".to_string(),
+            amount: Money::parse("499", "BDT").unwrap(),
+            interval,
+            trial_days,
+        }
+    }
+
+    #[test]
+    fn interval_test() {
+        let anchor = at(2024, 1, 31);
+        assert_eq!(Interval::Months(1).after(anchor, 1), at(2024, 2, 29));
+        assert_eq!(Interval::Months(1).after(anchor, 2), at(2024, 3, 31));
+        assert_eq!(Interval::Months(3).after(anchor, 4), at(2025, 1, 31));
+        assert_eq!(Interval::Weeks(2).after(anchor, 1), at(2024, 2, 14));
+        assert_eq!(Interval::Days(10).after(anchor, 0), anchor);
+        assert_eq!(Interval::Days(u32::MAX).after(anchor, u32::MAX), NaiveDateTime::MAX);
+        assert_eq!(Interval::Weeks(u32::MAX).after(anchor, u32::MAX), NaiveDateTime::MAX);
+        assert_eq!(parse_cycle_order_id("sub-42-12"), Some(("sub-42", 12)));
+        assert_eq!(parse_cycle_order_id("sub42"), None);
+    }
+
+    #[test]
+    fn subscribe_test() {
+        let clock = Arc::new(ManualClock::new(at(2024, 1, 1)));
+        let subscriptions = billing(&clock, 14);
+
+        let subscription = subscriptions.subscribe("cust-7", "monthly", subscriber()).unwrap();
+        assert_eq!(subscription.state, SubscriptionState::Trialing);
+        assert_eq!(subscription.due_at, at(2024, 1, 15));
+        assert_eq!(subscription.order_id(), "cust-7-1");
+
+        let request = subscriptions.checkout_request(&subscription).unwrap();
+        assert_eq!((request.amount.as_str(), request.currency.as_deref()), ("499.00", Some("BDT")));
+        assert_eq!(request.order_id.as_deref(), Some("cust-7-1"));
+
+        assert!(matches!(subscriptions.subscribe("cust-7", "monthly", subscriber()), Err(SpError::InvalidOrderId(_))));
+        assert!(matches!(subscriptions.subscribe("cust 8", "monthly", subscriber()), Err(SpError::InvalidOrderId(_))));
+        assert!(matches!(subscriptions.subscribe("cust-8", "weekly", subscriber()), Err(SpError::Config(_))));
+        for (interval, trial_days) in [(Interval::Days(0), 0), (Interval::Weeks(u32::MAX), 0), (Interval::Months(1), u32::MAX)] {
+            assert!(matches!(subscriptions.add_plan(plan(interval, trial_days)), Err(SpError::Config(_))));
+        }
+        assert!(subscriptions.add_plan(plan(Interval::Months(120), 3660)).is_ok());
+        assert_eq!(subscriptions.get("cust-9"), Err(SpError::SubscriptionNotFound("cust-9".to_string())));
+        assert!(subscriptions.due().unwrap().is_empty());
+
+        clock.set(at(2024, 1, 15));
+        assert_eq!(subscriptions.due().unwrap(), vec![subscriptions.get("cust-7").unwrap()]);
+
+        let mut land_line = subscriber();
+        land_line.phone = "02-9123456".to_string();
+        assert_eq!(
+            subscriptions.subscribe("cust-8", "monthly", land_line),
+            Err(SpError::InvalidPhone(PhoneError::NotMobile))
+        );
+        let mut international = subscriber();
+        international.phone = "+880 1711-223344".to_string();
+        let subscription = subscriptions.subscribe("cust-8", "monthly", international).unwrap();
+        assert_eq!(subscription.subscriber.phone, "01711223344");
+        assert_eq!(subscriptions.get("cust-8").unwrap().subscriber.phone, "01711223344");
+    }
+
+    #[test]
+    fn dunning_test() {
+        let clock = Arc::new(ManualClock::new(at(2024, 1, 1)));
+        let subscriptions = billing(&clock, 0);
+        subscriptions.subscribe("cust-7", "monthly", subscriber()).unwrap();
+
+        assert_eq!(
+            subscriptions.run_dunning().unwrap(),
+            vec![DunningEvent::Due {
+                subscription_id: "cust-7".to_string(),
+                order_id: "cust-7-1".to_string()
+            }]
+        );
+        assert!(subscriptions.run_dunning().unwrap().is_empty());
+
+        let failed = subscriptions.record_verification(&verification("cust-7-1", 1001)).unwrap().unwrap();
+        assert_eq!((failed.state, failed.failed_attempts), (SubscriptionState::PastDue, 1));
+
+        clock.advance(Duration::days(4));
+        assert_eq!(
+            subscriptions.run_dunning().unwrap(),
+            vec![DunningEvent::Reminder {
+                subscription_id: "cust-7".to_string(),
+                order_id: "cust-7-1".to_string(),
+                attempt: 2
+            }]
+        );
+        assert!(subscriptions.run_dunning().unwrap().is_empty());
+
+        clock.advance(Duration::days(3));
+        assert_eq!(
+            subscriptions.run_dunning().unwrap(),
+            vec![DunningEvent::Suspended {
+                subscription_id: "cust-7".to_string(),
+                order_id: "cust-7-1".to_string()
+            }]
+        );
+        assert_eq!(subscriptions.get("cust-7").unwrap().state, SubscriptionState::Suspended);
+        assert!(subscriptions.due().unwrap().is_empty());
+
+        let mut underpaid = verification("cust-7-1", 1000);
+        underpaid.payable_amount = Some(1.0);
+        assert!(matches!(subscriptions.record_verification(&underpaid), Err(SpError::InvalidAmount(_))));
+        let mut other_currency = verification("cust-7-1", 1000);
+        other_currency.currency = Some("USD".to_string());
+        assert!(matches!(subscriptions.record_verification(&other_currency), Err(SpError::CurrencyMismatch { .. })));
+        assert_eq!(subscriptions.get("cust-7").unwrap().cycle, 1);
+
+        let paid = subscriptions.record_verification(&verification("cust-7-1", 1000)).unwrap().unwrap();
+        assert_eq!((paid.state, paid.cycle, paid.due_at), (SubscriptionState::Active, 2, at(2024, 2, 1)));
+        assert_eq!((paid.failed_attempts, paid.reminders_sent), (0, 0));
+
+        assert_eq!(subscriptions.record_verification(&verification("cust-7-1", 1000)).unwrap(), None);
+        subscriptions.cancel("cust-7").unwrap();
+        clock.set(at(2024, 3, 1));
+        assert!(subscriptions.run_dunning().unwrap().is_empty());
+    }
+
+    #[test]
+    fn pay_cycle_through_link_test() {
+        let gateway = MockGateway::start();
+        let mut sp_instance = gateway.plugin();
+        let clock = Arc::new(ManualClock::new(at(2024, 1, 1)));
+        let subscriptions = billing(&clock, 0);
+        sp_instance.add_observer(Arc::new(subscriptions.clone()));
+        let links = PaymentLinks::new(Arc::new(MemoryPaymentLinkStore::new())).with_clock(clock.clone());
+
+        let subscription = subscriptions.subscribe("cust-7", "monthly", subscriber()).unwrap();
+        let link = subscriptions.payment_link(&links, &sp_instance, &subscription).unwrap();
+        assert_eq!(link.order_id, "cust-7-1");
+        assert_eq!(link.expires_at, at(2024, 1, 8));
+        assert_eq!(subscriptions.payment_link(&links, &sp_instance, &subscription).unwrap().id, link.id);
+
+        let response = links.resolve(&mut sp_instance, &link.id).unwrap();
+        gateway.set_verified(&response.sp_order_id, "cust-7-1", 499.0, "BDT", 1000);
+        sp_instance.verify_payment(Some(response.sp_order_id)).unwrap();
+
+        let subscription = subscriptions.get("cust-7").unwrap();
+        assert_eq!((subscription.cycle, subscription.due_at), (2, at(2024, 2, 1)));
+        assert_eq!(subscription.order_id(), "cust-7-2");
+    }
+
+    #[test]
+    fn pay_suspended_subscription_through_link_test() {
+        let gateway = MockGateway::start();
+        let mut sp_instance = gateway.plugin();
+        let clock = Arc::new(ManualClock::new(at(2024, 1, 1)));
+        let subscriptions = billing(&clock, 0);
+        sp_instance.add_observer(Arc::new(subscriptions.clone()));
+        let links = PaymentLinks::new(Arc::new(MemoryPaymentLinkStore::new())).with_clock(clock.clone());
+
+        let subscription = subscriptions.subscribe("cust-9", "monthly", subscriber()).unwrap();
+        let link = subscriptions.payment_link(&links, &sp_instance, &subscription).unwrap();
+        subscriptions.run_dunning().unwrap();
+        clock.set(at(2024, 1, 9));
+        subscriptions.run_dunning().unwrap();
+        let subscription = subscriptions.get("cust-9").unwrap();
+        assert_eq!(subscription.state, SubscriptionState::Suspended);
+        assert!(matches!(links.resolve(&mut sp_instance, &link.id), Err(SpError::LinkExpired(_))));
+
+        let reissued = subscriptions.payment_link(&links, &sp_instance, &subscription).unwrap();
+        assert_eq!((reissued.id.as_str(), reissued.order_id.as_str()), (link.id.as_str(), "cust-9-1"));
+        assert_eq!(reissued.expires_at, at(2024, 1, 16));
+        let response = links.resolve(&mut sp_instance, &link.id).unwrap();
+        gateway.set_verified(&response.sp_order_id, "cust-9-1", 499.0, "BDT", 1000);
+        sp_instance.verify_payment(Some(response.sp_order_id)).unwrap();
+
+        let subscription = subscriptions.get("cust-9").unwrap();
+        assert_eq!((subscription.state, subscription.cycle), (SubscriptionState::Active, 2));
+    }
+}
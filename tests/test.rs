@@ -3,7 +3,6 @@ mod tests {
 
     use shurjopay_plugin::shurjopay::ShurjopayPlugin;
     use assert_str::assert_str_eq;
-    use webbrowser;
 
     #[test]
     fn set_config_from_env_file_test() {
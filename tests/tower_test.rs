@@ -0,0 +1,95 @@
+#![cfg(feature = "tower")]
+
+mod common;
+
+#[cfg(test)]
+mod tests {
+
+    use std::time::Duration;
+
+    use tower::ServiceExt;
+
+    use shurjopay_plugin::error::SpError;
+    use shurjopay_plugin::shurjopay::SpCheckout;
+    use shurjopay_plugin::tower_integration::{self, ShurjopayServices, TokenRequest};
+
+    use crate::common::{MockGateway, MockResponse};
+
+    async fn services(gateway: &MockGateway) -> ShurjopayServices {
+        let gateway = gateway.clone();
+        let sp_instance = tokio::task::spawn_blocking(move || gateway.plugin()).await.unwrap();
+        ShurjopayServices::new(sp_instance)
+    }
+
+    #[tokio::test]
+    async fn token_checkout_and_verify_services_test() {
+        let gateway = MockGateway::start();
+        let services = services(&gateway).await;
+
+        let auth_token = services.token.clone().oneshot(TokenRequest).await.unwrap();
+        assert_eq!(auth_token.token, "mock-token");
+        services.token.clone().oneshot(TokenRequest).await.unwrap();
+        assert_eq!(gateway.hits("/api/get_token/"), 1);
+
+        let checkout_item = SpCheckout {
+            amount: "786".to_string(),
+            order_id: "tower-001".to_string(),
+            currency: "BDT".to_string(),
+            customer_name: "Mahmudul Islam".to_string(),
+            customer_phone: "01811177722".to_string(),
+            ..Default::default()
+        };
+        let checkout_response = services.checkout.clone().oneshot(checkout_item).await.unwrap();
+        let body = serde_json::to_value(&checkout_response).unwrap();
+        assert_eq!(body["sp_order_id"], "sp-mock-1");
+
+        gateway.set_verified("sp-mock-1", "tower-001", 786.0, "BDT", 1000);
+        let response = services.verify.clone().oneshot("sp-mock-1".to_string()).await.unwrap();
+        assert_eq!(response.sp_code, Some(1000));
+        let response = services.status.clone().oneshot("unknown".to_string()).await.unwrap();
+        assert_eq!(response.sp_code, Some(1011));
+    }
+
+    #[tokio::test]
+    async fn calls_run_concurrently_test() {
+        let gateway = MockGateway::start();
+        let services = services(&gateway).await;
+        services.token.clone().oneshot(TokenRequest).await.unwrap();
+
+        let body = r#"[{"order_id":"sp-slow","customer_order_id":"ord-slow","currency":"BDT","amount":10,"payable_amount":10,"sp_code":1000,"sp_message":"Success"}]"#;
+        for _ in 0..4 {
+            gateway.enqueue("/api/verification/", MockResponse::json(200, body).delayed(400));
+        }
+        let started = std::time::Instant::now();
+        let calls: Vec<_> = (0..4)
+            .map(|_| tokio::spawn(services.verify.clone().oneshot("sp-slow".to_string())))
+            .collect();
+        for call in calls {
+            assert_eq!(call.await.unwrap().unwrap().sp_code, Some(1000));
+        }
+        assert!(started.elapsed() < Duration::from_millis(1200), "{:?}", started.elapsed());
+        assert_eq!(gateway.hits("/api/get_token/"), 1);
+    }
+
+    #[tokio::test]
+    async fn default_layers_map_timeout_to_sp_error_test() {
+        let gateway = MockGateway::start();
+        gateway.enqueue("/api/get_token/", MockResponse::json(500, "{}").delayed(500));
+        let services = services(&gateway).await;
+
+        let service = tower_integration::layers(Duration::from_millis(50), 4).service(services.token.clone());
+        let err = service.oneshot(TokenRequest).await.unwrap_err();
+        assert!(matches!(err, SpError::Timeout(_)));
+
+        let service = tower_integration::default_layers().service(services.token);
+        assert_eq!(service.oneshot(TokenRequest).await.unwrap().token, "mock-token");
+    }
+
+    #[test]
+    fn into_sp_error_keeps_sp_error_test() {
+        let err = tower_integration::into_sp_error(Box::new(SpError::Auth("Unauthorized".to_string())));
+        assert_eq!(err, SpError::Auth("Unauthorized".to_string()));
+        let err = tower_integration::into_sp_error(Box::new(tower::load_shed::error::Overloaded::new()));
+        assert!(matches!(err, SpError::Overloaded(_)));
+    }
+}